@@ -0,0 +1,154 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{
+    transfer_checked,
+    Mint,
+    TokenAccount,
+    TokenInterface,
+    TransferChecked,
+};
+use web3_for_dummies::program::Web3ForDummies;
+
+declare_id!("FA8Gxa4s3Ht6hHuq4X2wZZfpsyQ6DgBou3QckSjq6aHZ");
+
+/// A minimal `flash_swap` receiver for the tests. Its callback does what the flash swap's
+/// callback data asks for: pay the pool back, swap on the pool that is still locked, or take
+/// a flash loan from inside the callback.
+#[program]
+pub mod flash_swap_receiver {
+    use super::*;
+
+    /// Invoked by `flash_swap` once the borrowed amounts are in the payer's token accounts.
+    pub fn flash_swap_callback(
+        ctx: Context<ReceiveFlashSwap>,
+        callback: web3_for_dummies::FlashSwapCallback,
+    ) -> Result<()> {
+        // Only act on flash swaps the payer started on the pool passed in
+        if callback.initiator != ctx.accounts.payer.key() || callback.pool != ctx.accounts.pool.key() {
+            return err!(ReceiverError::UnexpectedCallback);
+        }
+
+        match ReceiverAction::try_from_slice(&callback.data)? {
+            ReceiverAction::Repay { amount_a, amount_b } => {
+                let legs = [
+                    (&ctx.accounts.payer_token_a_account, &ctx.accounts.token_a_mint, &ctx.accounts.token_a_vault, amount_a),
+                    (&ctx.accounts.payer_token_b_account, &ctx.accounts.token_b_mint, &ctx.accounts.token_b_vault, amount_b),
+                ];
+                for (source, mint, vault, amount) in legs {
+                    if amount == 0 {
+                        continue;
+                    }
+                    let transfer_accounts = TransferChecked {
+                        from: source.to_account_info(),
+                        mint: mint.to_account_info(),
+                        to: vault.to_account_info(),
+                        authority: ctx.accounts.payer.to_account_info(),
+                    };
+                    let transfer_cpi = CpiContext::new(
+                        ctx.accounts.token_program.to_account_info(),
+                        transfer_accounts,
+                    );
+                    transfer_checked(transfer_cpi, amount, mint.decimals)?;
+                }
+            }
+            ReceiverAction::Swap { amount_in } => {
+                let swap_accounts = web3_for_dummies::cpi::accounts::Swap {
+                    source_mint: ctx.accounts.token_a_mint.to_account_info(),
+                    destination_mint: ctx.accounts.token_b_mint.to_account_info(),
+                    user_authority: ctx.accounts.payer.to_account_info(),
+                    pool: ctx.accounts.pool.to_account_info(),
+                    pool_authority: ctx.accounts.pool_authority.to_account_info(),
+                    observations: ctx.accounts.observations.to_account_info(),
+                    user_source_token_account: ctx.accounts.payer_token_a_account.to_account_info(),
+                    user_destination_token_account: ctx.accounts.payer_token_b_account.to_account_info(),
+                    token_a_vault: ctx.accounts.token_a_vault.to_account_info(),
+                    token_b_vault: ctx.accounts.token_b_vault.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                };
+                let swap_cpi = CpiContext::new(
+                    ctx.accounts.pool_program.to_account_info(),
+                    swap_accounts,
+                );
+                web3_for_dummies::cpi::swap(swap_cpi, amount_in, 0, None)?;
+            }
+            ReceiverAction::FlashBorrow { amount } => {
+                let flash_loan_accounts = web3_for_dummies::cpi::accounts::FlashLoan {
+                    pool: ctx.accounts.pool.to_account_info(),
+                    pool_authority: ctx.accounts.pool_authority.to_account_info(),
+                    mint: ctx.accounts.token_a_mint.to_account_info(),
+                    vault: ctx.accounts.token_a_vault.to_account_info(),
+                    user_token_account: ctx.accounts.payer_token_a_account.to_account_info(),
+                    user_authority: ctx.accounts.payer.to_account_info(),
+                    instructions: ctx.accounts.instructions.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                };
+                let flash_loan_cpi = CpiContext::new(
+                    ctx.accounts.pool_program.to_account_info(),
+                    flash_loan_accounts,
+                );
+                web3_for_dummies::cpi::flash_borrow(flash_loan_cpi, amount, web3_for_dummies::PoolToken::A)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// What the callback does, Borsh-encoded as the flash swap's callback data.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub enum ReceiverAction {
+    /// Pays `amount_a` and `amount_b` from the payer's token accounts into the pool vaults.
+    Repay { amount_a: u64, amount_b: u64 },
+    /// Swaps `amount_in` of Token A for Token B on the pool, which must fail while it is locked.
+    Swap { amount_in: u64 },
+    /// Flash borrows `amount` of Token A from the pool, which must fail inside a CPI.
+    FlashBorrow { amount: u64 },
+}
+
+/// Defines the accounts the receiver expects as `flash_swap`'s remaining accounts.
+#[derive(Accounts)]
+pub struct ReceiveFlashSwap<'info> {
+    pub pool_program: Program<'info, Web3ForDummies>,
+
+    /// CHECK: The pool being flash swapped, only passed on to the pool program.
+    #[account(mut)]
+    pub pool: UncheckedAccount<'info>,
+
+    /// CHECK: The pool's authority PDA, only passed on to the pool program.
+    pub pool_authority: UncheckedAccount<'info>,
+
+    /// CHECK: The pool's observation buffer, only passed on to the pool program.
+    #[account(mut)]
+    pub observations: UncheckedAccount<'info>,
+
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The payer's Token A account, which received the flash swap's Token A.
+    #[account(mut)]
+    pub payer_token_a_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The payer's Token B account, which received the flash swap's Token B.
+    #[account(mut)]
+    pub payer_token_b_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The flash swap's initiator, still a signer inside the callback.
+    pub payer: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// CHECK: The instructions sysvar, only passed on to `flash_borrow`.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+}
+
+#[error_code]
+pub enum ReceiverError {
+    #[msg("The callback is not for a flash swap the payer started on this pool.")]
+    UnexpectedCallback,
+}
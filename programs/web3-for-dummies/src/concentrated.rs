@@ -0,0 +1,289 @@
+//! Tick and sqrt-price math for concentrated-liquidity pools. Prices are Token B per Token A,
+//! tick `i` is the price 1.0001^i, and square roots of prices are stored as Q64.64.
+
+use anchor_lang::prelude::*;
+
+use crate::curve::U256;
+use crate::{SwapError, BPS_DENOMINATOR};
+
+/// Lowest and highest tick whose sqrt price fits the Q64.64 range.
+pub const MIN_TICK: i32 = -443_636;
+pub const MAX_TICK: i32 = 443_636;
+
+/// sqrt(1.0001)^-(2^i) as Q128.128, for each bit `i` of a tick's magnitude.
+const TICK_RATIOS_X128: [u128; 19] = [
+    0xfffcb933bd6fad37aa2d162d1a594001,
+    0xfff97272373d413259a46990580e2139,
+    0xfff2e50f5f656932ef12357cf3c7fdcb,
+    0xffe5caca7e10e4e61c3624eaa0941ccf,
+    0xffcb9843d60f6159c9db58835c926643,
+    0xff973b41fa98c081472e6896dfb254bf,
+    0xff2ea16466c96a3843ec78b326b52860,
+    0xfe5dee046a99a2a811c461f1969c3052,
+    0xfcbe86c7900a88aedcffc83b479aa3a3,
+    0xf987a7253ac413176f2b074cf7815e53,
+    0xf3392b0822b70005940c7a398e4b70f2,
+    0xe7159475a2c29b7443b29c7fa6e889d8,
+    0xd097f3bdfd2022b8845ad8f792aa5825,
+    0xa9f746462d870fdf8a65dc1f90e061e4,
+    0x70d869a156d2a1b890bb3df62baf32f6,
+    0x31be135f97d08fd981231505542fcfa5,
+    0x9aa508b5b7a84e1c677de54f3e99bc8,
+    0x5d6af8dedb81196699c329225ee604,
+    0x2216e584f5fa1ea926041bedfe97,
+];
+
+/// Returns sqrt(1.0001^tick) as Q64.64, rounded up.
+pub fn sqrt_price_at_tick(tick: i32) -> Result<u128> {
+    if !(MIN_TICK..=MAX_TICK).contains(&tick) {
+        return err!(SwapError::InvalidTickRange);
+    }
+
+    // Multiply the ratios of the set bits of |tick|, giving sqrt(1.0001)^-|tick| as Q128.128
+    let magnitude = tick.unsigned_abs();
+    let mut ratio = U256::one() << 128;
+    for (bit, tick_ratio) in TICK_RATIOS_X128.iter().enumerate() {
+        if magnitude & (1 << bit) != 0 {
+            ratio = (ratio * U256::from(*tick_ratio)) >> 128;
+        }
+    }
+    if tick > 0 {
+        ratio = U256::MAX / ratio;
+    }
+
+    // Q128.128 -> Q64.64, rounding up so the price of a tick never falls below the tick
+    let rounding = if ratio.low_u64() != 0 { U256::one() } else { U256::zero() };
+    Ok(((ratio >> 64) + rounding).as_u128())
+}
+
+/// Returns the greatest tick whose sqrt price is at most `sqrt_price_x64`.
+pub fn tick_at_sqrt_price(sqrt_price_x64: u128) -> Result<i32> {
+    if sqrt_price_x64 < sqrt_price_at_tick(MIN_TICK)? || sqrt_price_x64 > sqrt_price_at_tick(MAX_TICK)? {
+        return err!(SwapError::InvalidSqrtPrice);
+    }
+
+    // Binary search over the monotone tick -> sqrt price mapping
+    let (mut low, mut high) = (MIN_TICK, MAX_TICK);
+    while low < high {
+        let mid = low + (high - low + 1) / 2;
+        if sqrt_price_at_tick(mid)? <= sqrt_price_x64 {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    Ok(low)
+}
+
+/// Amount of Token A between two sqrt prices for `liquidity`:
+/// liquidity * (sqrt_upper - sqrt_lower) / (sqrt_upper * sqrt_lower).
+pub fn amount_a_delta(sqrt_price_a_x64: u128, sqrt_price_b_x64: u128, liquidity: u128, round_up: bool) -> Result<u128> {
+    let (lower, upper) = sorted(sqrt_price_a_x64, sqrt_price_b_x64);
+    if lower == 0 {
+        return err!(SwapError::InvalidSqrtPrice);
+    }
+
+    let numerator = (U256::from(liquidity) << 64)
+        .checked_mul(U256::from(upper - lower))
+        .ok_or(SwapError::CalculationOverflow)?;
+    let amount = if round_up {
+        div_ceil_u256(div_ceil_u256(numerator, U256::from(upper)), U256::from(lower))
+    } else {
+        numerator / U256::from(upper) / U256::from(lower)
+    };
+    u128::try_from(amount).map_err(|_| error!(SwapError::CalculationOverflow))
+}
+
+/// Amount of Token B between two sqrt prices for `liquidity`: liquidity * (sqrt_upper - sqrt_lower).
+pub fn amount_b_delta(sqrt_price_a_x64: u128, sqrt_price_b_x64: u128, liquidity: u128, round_up: bool) -> Result<u128> {
+    let (lower, upper) = sorted(sqrt_price_a_x64, sqrt_price_b_x64);
+
+    let product = U256::from(liquidity) * U256::from(upper - lower);
+    let amount = if round_up {
+        div_ceil_u256(product, U256::one() << 64)
+    } else {
+        product >> 64
+    };
+    u128::try_from(amount).map_err(|_| error!(SwapError::CalculationOverflow))
+}
+
+/// Returns the Token A and Token B amounts backing `liquidity` in the range [tick_lower, tick_upper)
+/// at the pool's current price.
+pub fn position_amounts(
+    sqrt_price_x64: u128,
+    tick_current: i32,
+    tick_lower: i32,
+    tick_upper: i32,
+    liquidity: u128,
+    round_up: bool,
+) -> Result<(u64, u64)> {
+    let sqrt_lower = sqrt_price_at_tick(tick_lower)?;
+    let sqrt_upper = sqrt_price_at_tick(tick_upper)?;
+
+    // Below the range the position is all Token A, above it all Token B
+    let (amount_a, amount_b) = if tick_current < tick_lower {
+        (amount_a_delta(sqrt_lower, sqrt_upper, liquidity, round_up)?, 0)
+    } else if tick_current < tick_upper {
+        (
+            amount_a_delta(sqrt_price_x64, sqrt_upper, liquidity, round_up)?,
+            amount_b_delta(sqrt_lower, sqrt_price_x64, liquidity, round_up)?,
+        )
+    } else {
+        (0, amount_b_delta(sqrt_lower, sqrt_upper, liquidity, round_up)?)
+    };
+
+    Ok((
+        u64::try_from(amount_a).map_err(|_| error!(SwapError::CalculationOverflow))?,
+        u64::try_from(amount_b).map_err(|_| error!(SwapError::CalculationOverflow))?,
+    ))
+}
+
+/// Returns the sqrt price after adding `amount_in` to the pool, rounded against the trader.
+fn next_sqrt_price_from_input(sqrt_price_x64: u128, liquidity: u128, amount_in: u128, a_to_b: bool) -> Result<u128> {
+    if liquidity == 0 {
+        return err!(SwapError::CalculationOverflow);
+    }
+    let next = if a_to_b {
+        // Adding Token A lowers the price: liquidity * sqrt_price / (liquidity + amount_in * sqrt_price)
+        let numerator = U256::from(liquidity) << 64;
+        let denominator = numerator
+            .checked_add(U256::from(amount_in) * U256::from(sqrt_price_x64))
+            .ok_or(SwapError::CalculationOverflow)?;
+        let product = numerator
+            .checked_mul(U256::from(sqrt_price_x64))
+            .ok_or(SwapError::CalculationOverflow)?;
+        div_ceil_u256(product, denominator)
+    } else {
+        // Adding Token B raises the price: sqrt_price + amount_in / liquidity
+        U256::from(sqrt_price_x64) + (U256::from(amount_in) << 64) / U256::from(liquidity)
+    };
+    u128::try_from(next).map_err(|_| error!(SwapError::CalculationOverflow))
+}
+
+/// The result of swapping within a single tick range.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SwapStep {
+    pub sqrt_price_next_x64: u128,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub fee_amount: u64,
+}
+
+/// Swaps up to `amount_remaining` (fee included) from `sqrt_price_x64` towards `sqrt_price_target_x64`
+/// with constant `liquidity`. The input is rounded up and the output down, in favor of the pool.
+pub fn compute_swap_step(
+    sqrt_price_x64: u128,
+    sqrt_price_target_x64: u128,
+    liquidity: u128,
+    amount_remaining: u64,
+    fee_bps: u16,
+) -> Result<SwapStep> {
+    let a_to_b = sqrt_price_target_x64 <= sqrt_price_x64;
+    let fee_bps = fee_bps as u128;
+    let amount_remaining_less_fee = amount_remaining as u128 * (BPS_DENOMINATOR - fee_bps) / BPS_DENOMINATOR;
+
+    let amount_in_to = |sqrt_price_next_x64: u128| {
+        if a_to_b {
+            amount_a_delta(sqrt_price_next_x64, sqrt_price_x64, liquidity, true)
+        } else {
+            amount_b_delta(sqrt_price_x64, sqrt_price_next_x64, liquidity, true)
+        }
+    };
+
+    // Stop at the target if the remaining input reaches it, otherwise wherever the input runs out
+    let amount_in_to_target = amount_in_to(sqrt_price_target_x64)?;
+    let reached_target = amount_remaining_less_fee >= amount_in_to_target;
+    let sqrt_price_next_x64 = if reached_target {
+        sqrt_price_target_x64
+    } else {
+        next_sqrt_price_from_input(sqrt_price_x64, liquidity, amount_remaining_less_fee, a_to_b)?
+    };
+    let amount_in = if reached_target { amount_in_to_target } else { amount_in_to(sqrt_price_next_x64)? };
+    let amount_out = if a_to_b {
+        amount_b_delta(sqrt_price_next_x64, sqrt_price_x64, liquidity, false)?
+    } else {
+        amount_a_delta(sqrt_price_x64, sqrt_price_next_x64, liquidity, false)?
+    };
+
+    // A step that uses up the input keeps the rest of it as the fee
+    let fee_amount = if reached_target {
+        (amount_in * fee_bps).div_ceil(BPS_DENOMINATOR - fee_bps)
+    } else {
+        amount_remaining as u128 - amount_in
+    };
+
+    Ok(SwapStep {
+        sqrt_price_next_x64,
+        amount_in: amount_in as u64,
+        amount_out: u64::try_from(amount_out).map_err(|_| error!(SwapError::CalculationOverflow))?,
+        fee_amount: fee_amount as u64,
+    })
+}
+
+/// Applies a signed liquidity change.
+pub fn add_liquidity_delta(liquidity: u128, delta: i128) -> Result<u128> {
+    if delta < 0 {
+        liquidity.checked_sub(delta.unsigned_abs())
+    } else {
+        liquidity.checked_add(delta as u128)
+    }
+    .ok_or_else(|| error!(SwapError::CalculationOverflow))
+}
+
+fn sorted(a: u128, b: u128) -> (u128, u128) {
+    if a <= b { (a, b) } else { (b, a) }
+}
+
+fn div_ceil_u256(numerator: U256, denominator: U256) -> U256 {
+    let quotient = numerator / denominator;
+    if quotient * denominator == numerator { quotient } else { quotient + 1 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn tick_zero_is_price_one() {
+        assert_eq!(sqrt_price_at_tick(0).unwrap(), 1 << 64);
+        assert_eq!(tick_at_sqrt_price(1 << 64).unwrap(), 0);
+    }
+
+    proptest! {
+        #[test]
+        fn tick_round_trips(tick in MIN_TICK..=MAX_TICK) {
+            let sqrt_price = sqrt_price_at_tick(tick).unwrap();
+            prop_assert_eq!(tick_at_sqrt_price(sqrt_price).unwrap(), tick);
+            if tick < MAX_TICK {
+                prop_assert!(sqrt_price_at_tick(tick + 1).unwrap() > sqrt_price);
+            }
+        }
+
+        /// A step never spends more than it was given and pays out no more than the
+        /// liquidity between the start price and the price it stops at.
+        #[test]
+        fn swap_step_stays_within_budget(
+            tick in -100_000i32..100_000,
+            tick_distance in 1i32..10_000,
+            a_to_b: bool,
+            liquidity in 1_000u128..1_000_000_000_000_000,
+            amount_remaining in 1u64..1_000_000_000_000,
+            fee_bps in 0u16..=1_000,
+        ) {
+            let target_tick = if a_to_b { tick - tick_distance } else { tick + tick_distance };
+            let sqrt_price = sqrt_price_at_tick(tick).unwrap();
+            let target = sqrt_price_at_tick(target_tick).unwrap();
+
+            let step = compute_swap_step(sqrt_price, target, liquidity, amount_remaining, fee_bps).unwrap();
+            prop_assert!(step.amount_in as u128 + step.fee_amount as u128 <= amount_remaining as u128);
+            if a_to_b {
+                prop_assert!(step.sqrt_price_next_x64 >= target && step.sqrt_price_next_x64 <= sqrt_price);
+                prop_assert!(step.amount_out as u128 <= amount_b_delta(step.sqrt_price_next_x64, sqrt_price, liquidity, false).unwrap());
+            } else {
+                prop_assert!(step.sqrt_price_next_x64 <= target && step.sqrt_price_next_x64 >= sqrt_price);
+                prop_assert!(step.amount_out as u128 <= amount_a_delta(sqrt_price, step.sqrt_price_next_x64, liquidity, false).unwrap());
+            }
+        }
+    }
+}
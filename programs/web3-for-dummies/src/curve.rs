@@ -0,0 +1,1089 @@
+//! Pricing curves. Every pool prices swaps and LP deposits/withdrawals through the
+//! `SwapCurve` implementation selected by its `CurveKind`.
+
+use anchor_lang::prelude::*;
+
+// Required for picking the smaller of the two proportional LP share amounts
+use std::cmp::{min, Ordering};
+
+use crate::{SwapError, SwapReserves, BPS_DENOMINATOR, STABLE_MAX_ITERATIONS};
+
+// 256-bit integers for the StableSwap invariant, whose intermediate products exceed u128.
+// Kept in its own module so the macro's `Result` does not resolve to Anchor's.
+#[allow(clippy::all)]
+mod big_num {
+    uint::construct_uint! {
+        pub struct U256(4);
+    }
+}
+pub(crate) use big_num::U256;
+
+/// The pricing curve a pool is created with.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum CurveKind {
+    /// x * y = k
+    #[default]
+    ConstantProduct,
+    /// Curve-style StableSwap with amplification coefficient `amp`, for pegged pairs.
+    StableSwap,
+    /// Fixed exchange rate of `token_b_price` Token A per Token B.
+    ConstantPrice,
+    /// Balancer-style weighted product with normalized token weights.
+    Weighted,
+    /// Weighted product whose weights shift linearly over a sale window, for token launches.
+    LiquidityBootstrapping,
+}
+
+/// The amounts of a swap priced by a curve.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CurveSwap {
+    /// Total amount sent into the pool, fee included.
+    pub amount_in: u64,
+    /// Amount sent out of the pool.
+    pub amount_out: u64,
+    /// The part of `amount_in` kept as the pool's fee.
+    pub fee_amount: u64,
+}
+
+/// Pricing behind swaps, quotes and LP shares. Every method rounds in favor of the pool.
+pub trait SwapCurve {
+    /// Prices an exact-input swap. The fee is taken from `amount_in` before pricing.
+    fn swap_exact_in(&self, amount_in: u64, reserves: &SwapReserves, fee_bps: u16) -> Result<CurveSwap>;
+
+    /// Prices an exact-output swap. The required input is rounded up.
+    fn swap_exact_out(&self, amount_out: u64, reserves: &SwapReserves, fee_bps: u16) -> Result<CurveSwap>;
+
+    /// Returns the LP shares minted for a deposit of `amount_a` and `amount_b`.
+    fn deposit_shares(&self, amount_a: u64, amount_b: u64, reserve_a: u64, reserve_b: u64, lp_supply: u64) -> Result<u64>;
+
+    /// Returns the amounts of Token A and Token B paid out for burning `lp_amount` shares.
+    /// Defaults to the pro-rata share of both reserves.
+    fn withdraw_amounts(&self, lp_amount: u64, reserve_a: u64, reserve_b: u64, lp_supply: u64) -> Result<(u64, u64)> {
+        // amount = reserve * lp_amount / lp_supply
+        // Note: Integer division truncates, favoring the pool slightly.
+        let pro_rata = |reserve: u64| -> Result<u64> {
+            let amount = (reserve as u128)
+                .checked_mul(lp_amount as u128)
+                .ok_or(SwapError::CalculationOverflow)?
+                .checked_div(lp_supply as u128)
+                .ok_or(SwapError::CalculationOverflow)?;
+            Ok(amount as u64)
+        };
+        Ok((pro_rata(reserve_a)?, pro_rata(reserve_b)?))
+    }
+}
+
+/// x * y = k
+pub struct ConstantProductCurve;
+
+impl SwapCurve for ConstantProductCurve {
+    fn swap_exact_in(&self, amount_in: u64, reserves: &SwapReserves, fee_bps: u16) -> Result<CurveSwap> {
+        let (amount_out, fee_amount) = calculate_amount_out(amount_in, reserves.reserve_in, reserves.reserve_out, fee_bps)?;
+        Ok(CurveSwap { amount_in, amount_out, fee_amount })
+    }
+
+    fn swap_exact_out(&self, amount_out: u64, reserves: &SwapReserves, fee_bps: u16) -> Result<CurveSwap> {
+        let (amount_in, fee_amount) = calculate_amount_in(amount_out, reserves.reserve_in, reserves.reserve_out, fee_bps)?;
+        Ok(CurveSwap { amount_in, amount_out, fee_amount })
+    }
+
+    fn deposit_shares(&self, amount_a: u64, amount_b: u64, reserve_a: u64, reserve_b: u64, lp_supply: u64) -> Result<u64> {
+        calculate_lp_shares(amount_a, amount_b, reserve_a, reserve_b, lp_supply)
+    }
+}
+
+/// Curve-style StableSwap for two tokens with amplification coefficient `amp`.
+pub struct StableSwapCurve {
+    pub amp: u64,
+}
+
+impl SwapCurve for StableSwapCurve {
+    fn swap_exact_in(&self, amount_in: u64, reserves: &SwapReserves, fee_bps: u16) -> Result<CurveSwap> {
+        let (amount_out, fee_amount) =
+            calculate_stable_amount_out(amount_in, reserves.reserve_in, reserves.reserve_out, fee_bps, self.amp)?;
+        Ok(CurveSwap { amount_in, amount_out, fee_amount })
+    }
+
+    fn swap_exact_out(&self, amount_out: u64, reserves: &SwapReserves, fee_bps: u16) -> Result<CurveSwap> {
+        let (amount_in, fee_amount) =
+            calculate_stable_amount_in(amount_out, reserves.reserve_in, reserves.reserve_out, fee_bps, self.amp)?;
+        Ok(CurveSwap { amount_in, amount_out, fee_amount })
+    }
+
+    fn deposit_shares(&self, amount_a: u64, amount_b: u64, reserve_a: u64, reserve_b: u64, lp_supply: u64) -> Result<u64> {
+        calculate_stable_lp_shares(amount_a, amount_b, reserve_a, reserve_b, lp_supply, self.amp)
+    }
+}
+
+/// Fixed exchange rate: one unit of Token B is worth `token_b_price` units of Token A.
+pub struct ConstantPriceCurve {
+    pub token_b_price: u64,
+}
+
+impl ConstantPriceCurve {
+    /// Values both amounts in Token A.
+    fn value_in_a(&self, amount_a: u64, amount_b: u64) -> Result<u128> {
+        (amount_b as u128)
+            .checked_mul(self.token_b_price as u128)
+            .and_then(|value_b| value_b.checked_add(amount_a as u128))
+            .ok_or_else(|| error!(SwapError::CalculationOverflow))
+    }
+}
+
+impl SwapCurve for ConstantPriceCurve {
+    fn swap_exact_in(&self, amount_in: u64, reserves: &SwapReserves, fee_bps: u16) -> Result<CurveSwap> {
+        let (amount_in_after_fee, fee_amount) = split_input_fee(amount_in as u128, fee_bps)?;
+        let price = self.token_b_price as u128;
+
+        // A -> B rounds the output down to whole units of B
+        let amount_out = if reserves.is_a_to_b {
+            amount_in_after_fee / price
+        } else {
+            amount_in_after_fee.checked_mul(price).ok_or(SwapError::CalculationOverflow)?
+        };
+        if amount_out >= reserves.reserve_out as u128 {
+            return err!(SwapError::InsufficientReserves);
+        }
+
+        Ok(CurveSwap { amount_in, amount_out: amount_out as u64, fee_amount })
+    }
+
+    fn swap_exact_out(&self, amount_out: u64, reserves: &SwapReserves, fee_bps: u16) -> Result<CurveSwap> {
+        // The pool cannot pay out its whole reserve
+        if amount_out >= reserves.reserve_out {
+            return err!(SwapError::InsufficientReserves);
+        }
+        let price = self.token_b_price as u128;
+
+        // B -> A rounds the required input up to whole units of B
+        let amount_in_after_fee = if reserves.is_a_to_b {
+            (amount_out as u128).checked_mul(price).ok_or(SwapError::CalculationOverflow)?
+        } else {
+            (amount_out as u128).div_ceil(price)
+        };
+
+        let (amount_in, fee_amount) = add_input_fee(amount_in_after_fee, fee_bps)?;
+        Ok(CurveSwap { amount_in, amount_out, fee_amount })
+    }
+
+    fn deposit_shares(&self, amount_a: u64, amount_b: u64, reserve_a: u64, reserve_b: u64, lp_supply: u64) -> Result<u64> {
+        // Shares track the deposit's value in Token A
+        let deposit_value = self.value_in_a(amount_a, amount_b)?;
+        let shares = if lp_supply == 0 {
+            deposit_value
+        } else {
+            let pool_value = self.value_in_a(reserve_a, reserve_b)?;
+            if pool_value == 0 {
+                return err!(SwapError::EmptyReserves);
+            }
+            (lp_supply as u128)
+                .checked_mul(deposit_value)
+                .ok_or(SwapError::CalculationOverflow)?
+                .checked_div(pool_value)
+                .ok_or(SwapError::CalculationOverflow)?
+        };
+
+        u64::try_from(shares).map_err(|_| error!(SwapError::CalculationOverflow))
+    }
+}
+
+/// Balancer-style weighted product `reserve_a^weight_a * reserve_b^weight_b = k`.
+/// The weights are in basis points and sum to `BPS_DENOMINATOR`.
+pub struct WeightedCurve {
+    pub weight_a_bps: u16,
+    pub weight_b_bps: u16,
+}
+
+impl WeightedCurve {
+    /// Returns the weights of the swap's input and output tokens.
+    fn swap_weights(&self, reserves: &SwapReserves) -> (u16, u16) {
+        if reserves.is_a_to_b {
+            (self.weight_a_bps, self.weight_b_bps)
+        } else {
+            (self.weight_b_bps, self.weight_a_bps)
+        }
+    }
+}
+
+impl SwapCurve for WeightedCurve {
+    fn swap_exact_in(&self, amount_in: u64, reserves: &SwapReserves, fee_bps: u16) -> Result<CurveSwap> {
+        let (weight_in, weight_out) = self.swap_weights(reserves);
+        let (amount_in_after_fee, fee_amount) = split_input_fee(amount_in as u128, fee_bps)?;
+        let amount_out = calculate_weighted_amount_out(
+            amount_in_after_fee,
+            reserves.reserve_in,
+            reserves.reserve_out,
+            weight_in,
+            weight_out,
+        )?;
+        Ok(CurveSwap { amount_in, amount_out, fee_amount })
+    }
+
+    fn swap_exact_out(&self, amount_out: u64, reserves: &SwapReserves, fee_bps: u16) -> Result<CurveSwap> {
+        let (weight_in, weight_out) = self.swap_weights(reserves);
+        let estimate = calculate_weighted_amount_in(
+            amount_out,
+            reserves.reserve_in,
+            reserves.reserve_out,
+            weight_in,
+            weight_out,
+        )?;
+        let (estimate, _) = add_input_fee(estimate, fee_bps)?;
+
+        // The powers are approximations, so the closed form only seeds the search for the smallest
+        // input whose exact-input swap pays `amount_out`. Both directions then round consistently.
+        let amount_in = smallest_satisfying(U256::from(estimate), |amount_in| {
+            let amount_in = u64::try_from(amount_in).map_err(|_| error!(SwapError::CalculationOverflow))?;
+            Ok(self.swap_exact_in(amount_in, reserves, fee_bps)?.amount_out >= amount_out)
+        })?;
+        let amount_in = u64::try_from(amount_in).map_err(|_| error!(SwapError::CalculationOverflow))?;
+
+        let CurveSwap { fee_amount, .. } = self.swap_exact_in(amount_in, reserves, fee_bps)?;
+        Ok(CurveSwap { amount_in, amount_out, fee_amount })
+    }
+
+    fn deposit_shares(&self, amount_a: u64, amount_b: u64, reserve_a: u64, reserve_b: u64, lp_supply: u64) -> Result<u64> {
+        // The first deposit mints the weighted invariant of the deposit, the weighted
+        // counterpart of sqrt(a * b). Later deposits are proportional, as for constant product.
+        require_backed_supply(reserve_a, reserve_b, lp_supply)?;
+        if lp_supply == 0 {
+            let shares = weighted_invariant(amount_a, amount_b, self.weight_a_bps, self.weight_b_bps)?;
+            return u64::try_from(shares).map_err(|_| error!(SwapError::CalculationOverflow));
+        }
+        calculate_lp_shares(amount_a, amount_b, reserve_a, reserve_b, lp_supply)
+    }
+}
+
+// --- Curve Math ---
+
+/// Integer square root (floor) using Newton's method.
+pub(crate) fn integer_sqrt(value: u128) -> u128 {
+    if value < 2 {
+        return value;
+    }
+    let mut x = value;
+    let mut y = x / 2;
+    while y < x {
+        x = y;
+        y = (x + value / x) / 2;
+    }
+    x
+}
+
+/// Calculates the output of an exact-input swap using the constant product formula.
+/// Returns the amount sent out of the pool and the fee taken from `amount_in`.
+fn calculate_amount_out(amount_in: u64, reserve_in: u64, reserve_out: u64, fee_bps: u16) -> Result<(u64, u64)> {
+    // Convert amounts to u128 for calculation to prevent intermediate overflows
+    let amount_in_u128 = amount_in as u128;
+    let reserve_in_u128 = reserve_in as u128;
+    let reserve_out_u128 = reserve_out as u128;
+
+    // --- Fee ---
+    // The pool's fee is taken from the input amount before pricing
+    let (amount_in_after_fee, fee_amount) = split_input_fee(amount_in_u128, fee_bps)?;
+
+    // Calculate the constant product (k)
+    // x * y = k
+    let constant_product = reserve_in_u128.checked_mul(reserve_out_u128).ok_or(SwapError::CalculationOverflow)?;
+
+    // Calculate the new reserve amount for the input token
+    // new_x = x + amount_in_after_fee
+    let new_reserve_in = reserve_in_u128.checked_add(amount_in_after_fee).ok_or(SwapError::CalculationOverflow)?;
+
+    // Calculate the new reserve amount for the output token based on k
+    // new_y = ceil(k / new_x)
+    // Note: Rounding the remaining reserve up rounds the output down, so k never decreases.
+    let new_reserve_out = constant_product.div_ceil(new_reserve_in);
+
+    // Calculate the amount of output tokens to send to the user
+    // amount_out = y - new_y
+    let amount_out_u128 = reserve_out_u128.checked_sub(new_reserve_out).ok_or(SwapError::CalculationOverflow)?;
+
+    // Convert amount_out back to u64
+    Ok((amount_out_u128 as u64, fee_amount))
+}
+
+/// Splits an input amount into the part that is priced and the pool's fee.
+fn split_input_fee(amount_in: u128, fee_bps: u16) -> Result<(u128, u64)> {
+    let amount_in_after_fee = amount_in
+        .checked_mul(BPS_DENOMINATOR - fee_bps as u128)
+        .ok_or(SwapError::CalculationOverflow)?
+        .checked_div(BPS_DENOMINATOR)
+        .ok_or(SwapError::CalculationOverflow)?;
+    let fee_amount = amount_in
+        .checked_sub(amount_in_after_fee)
+        .ok_or(SwapError::CalculationOverflow)? as u64;
+    Ok((amount_in_after_fee, fee_amount))
+}
+
+/// Grosses up an amount that has to reach the pricing formula by the pool's fee, rounding up.
+/// Returns the total input and the fee included in it.
+fn add_input_fee(amount_in_after_fee: u128, fee_bps: u16) -> Result<(u64, u64)> {
+    // amount_in = ceil(amount_in_after_fee * 10000 / (10000 - fee_bps))
+    let amount_in_u128 = amount_in_after_fee
+        .checked_mul(BPS_DENOMINATOR)
+        .ok_or(SwapError::CalculationOverflow)?
+        .div_ceil(BPS_DENOMINATOR - fee_bps as u128);
+    let fee_amount = amount_in_u128
+        .checked_sub(amount_in_after_fee)
+        .ok_or(SwapError::CalculationOverflow)?;
+
+    let amount_in = u64::try_from(amount_in_u128).map_err(|_| error!(SwapError::CalculationOverflow))?;
+    Ok((amount_in, fee_amount as u64))
+}
+
+/// Calculates the input required for an exact-output swap by inverting the constant product formula.
+/// Both divisions round up, so the pool never receives less than the exact-input formula requires.
+/// Returns the amount sent into the pool and the fee included in it.
+fn calculate_amount_in(amount_out: u64, reserve_in: u64, reserve_out: u64, fee_bps: u16) -> Result<(u64, u64)> {
+    let amount_out_u128 = amount_out as u128;
+    let reserve_in_u128 = reserve_in as u128;
+    let reserve_out_u128 = reserve_out as u128;
+
+    // The pool cannot pay out its whole reserve
+    if amount_out_u128 >= reserve_out_u128 {
+        return err!(SwapError::InsufficientReserves);
+    }
+
+    // amount_in_after_fee = ceil(x * amount_out / (y - amount_out))
+    let new_reserve_out = reserve_out_u128.checked_sub(amount_out_u128).ok_or(SwapError::CalculationOverflow)?;
+    let amount_in_after_fee = reserve_in_u128
+        .checked_mul(amount_out_u128)
+        .ok_or(SwapError::CalculationOverflow)?
+        .div_ceil(new_reserve_out);
+
+    add_input_fee(amount_in_after_fee, fee_bps)
+}
+
+/// Checks the constant product of a pool's balances after a flash swap took `amounts_out` from
+/// `reserves`. Whatever came back beyond the remaining reserve counts as swap input and is
+/// charged the pool's fee before pricing, as in `calculate_amount_out`:
+/// (x' * 10000 - in_x * fee) * (y' * 10000 - in_y * fee) >= x * y * 10000^2
+/// Returns the Token A and Token B legs of the flash swap.
+pub(crate) fn check_flash_swap_product(
+    reserves: (u64, u64),
+    amounts_out: (u64, u64),
+    balances: (u64, u64),
+    fee_bps: u16,
+) -> Result<(CurveSwap, CurveSwap)> {
+    let leg = |reserve: u64, amount_out: u64, balance: u64| -> Result<(CurveSwap, U256)> {
+        // The pool cannot pay out its whole reserve
+        let remaining = reserve.checked_sub(amount_out).filter(|remaining| *remaining > 0).ok_or(SwapError::InsufficientReserves)?;
+        let amount_in = balance.saturating_sub(remaining);
+        let (_, fee_amount) = split_input_fee(amount_in as u128, fee_bps)?;
+        let adjusted_balance = (balance as u128 * BPS_DENOMINATOR)
+            .checked_sub(amount_in as u128 * fee_bps as u128)
+            .ok_or(SwapError::CalculationOverflow)?;
+        Ok((CurveSwap { amount_in, amount_out, fee_amount }, U256::from(adjusted_balance)))
+    };
+    let (leg_a, adjusted_a) = leg(reserves.0, amounts_out.0, balances.0)?;
+    let (leg_b, adjusted_b) = leg(reserves.1, amounts_out.1, balances.1)?;
+
+    let bps_squared = U256::from(BPS_DENOMINATOR * BPS_DENOMINATOR);
+    let constant_product = U256::from(reserves.0) * U256::from(reserves.1) * bps_squared;
+    if adjusted_a * adjusted_b < constant_product {
+        return err!(SwapError::FlashSwapNotRepaid);
+    }
+    Ok((leg_a, leg_b))
+}
+
+/// Direction in which a solved StableSwap value is rounded.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Rounding {
+    Down,
+    Up,
+}
+
+/// Computes the StableSwap invariant D for two reserves (n = 2) with Newton's method:
+/// A * n^n * (x + y) + D = A * n^n * D + D^(n+1) / (n^n * x * y)
+/// The result is the exact floor or ceiling of the real root, as requested.
+fn stable_invariant(reserve_a: u64, reserve_b: u64, amp: u64, rounding: Rounding) -> Result<u128> {
+    let sum = reserve_a as u128 + reserve_b as u128;
+    if sum == 0 {
+        return Ok(0);
+    }
+    if reserve_a == 0 || reserve_b == 0 {
+        return err!(SwapError::PoolIsEmpty);
+    }
+
+    let (x, y) = (U256::from(reserve_a), U256::from(reserve_b));
+    let ann = U256::from(amp as u128 * 4);
+    let sum = U256::from(sum);
+    let mut d = sum;
+    for i in 0..STABLE_MAX_ITERATIONS {
+        // d_p = D^3 / (4 * x * y)
+        let d_p = checked_mul_div(d, d, x * 2)?;
+        let d_p = checked_mul_div(d_p, d, y * 2)?;
+
+        // D = (Ann * S + 2 * d_p) * D / ((Ann - 1) * D + 3 * d_p)
+        let numerator = ann
+            .checked_mul(sum)
+            .and_then(|v| v.checked_add(d_p * 2))
+            .ok_or(SwapError::CalculationOverflow)?;
+        let denominator = (ann - 1)
+            .checked_mul(d)
+            .and_then(|v| v.checked_add(d_p * 3))
+            .ok_or(SwapError::CalculationOverflow)?;
+        let d_prev = d;
+        d = checked_mul_div(numerator, d, denominator)?;
+
+        // Stop once the integer iteration settles or starts bouncing around the root
+        if abs_diff_u256(d, d_prev) <= U256::one() || (i > 0 && d >= d_prev) {
+            break;
+        }
+    }
+
+    // Newton's integer steps end close to the root; settle on its exact floor or ceiling
+    let amp = U256::from(amp);
+    let d = match rounding {
+        Rounding::Down => smallest_satisfying(d, |d| Ok(curve_side(x, y, d, amp)? == Ordering::Less))? - U256::one(),
+        Rounding::Up => smallest_satisfying(d, |d| Ok(curve_side(x, y, d, amp)? != Ordering::Greater))?,
+    };
+    u128::try_from(d).map_err(|_| error!(SwapError::CalculationOverflow))
+}
+
+/// Solves the StableSwap invariant for the other reserve, given one reserve and D (n = 2):
+/// y^2 + (b - D) * y = c, with b = x + D / Ann and c = D^3 / (4 * x * Ann)
+/// The result is rounded up, so the pool always holds at least what the invariant requires.
+fn stable_other_reserve(reserve: u128, d: u128, amp: u64) -> Result<u128> {
+    if reserve == 0 {
+        return err!(SwapError::PoolIsEmpty);
+    }
+
+    let (x, d) = (U256::from(reserve), U256::from(d));
+    let ann = U256::from(amp as u128 * 4);
+    let c = checked_mul_div(d, d, x * 2)?;
+    let c = checked_mul_div(c, d, ann * 2)?;
+    let b = x.checked_add(d / ann).ok_or(SwapError::CalculationOverflow)?;
+
+    let mut y = d;
+    for i in 0..STABLE_MAX_ITERATIONS {
+        // y = (y^2 + c) / (2y + b - D)
+        let numerator = y
+            .checked_mul(y)
+            .and_then(|v| v.checked_add(c))
+            .ok_or(SwapError::CalculationOverflow)?;
+        let denominator = (y + y + b).checked_sub(d).ok_or(SwapError::CalculationOverflow)?;
+        let y_prev = y;
+        y = numerator.checked_div(denominator).ok_or(SwapError::CalculationOverflow)?;
+
+        // Stop once the integer iteration settles or starts bouncing around the root.
+        // The first step may overshoot upwards when the root lies above D.
+        if abs_diff_u256(y, y_prev) <= U256::one() || (i > 0 && y >= y_prev) {
+            break;
+        }
+    }
+
+    // Settle on the smallest reserve that lies on or above the curve
+    let amp = U256::from(amp);
+    let y = smallest_satisfying(y, |y| Ok(curve_side(x, y, d, amp)? != Ordering::Less))?;
+    u128::try_from(y).map_err(|_| error!(SwapError::CalculationOverflow))
+}
+
+/// Returns the smallest value for which the monotone predicate holds (false below it, true from it on).
+/// Searches outwards from `hint` with doubling steps, then bisects, so a close hint costs a few evaluations.
+fn smallest_satisfying(hint: U256, holds: impl Fn(U256) -> Result<bool>) -> Result<U256> {
+    let one = U256::one();
+    let mut step = one;
+
+    // Find a bracket with `!holds(low)` and `holds(high)`
+    let (mut low, mut high) = if holds(hint)? {
+        let mut high = hint;
+        loop {
+            if high.is_zero() {
+                return Ok(high);
+            }
+            let low = high.saturating_sub(step);
+            if !holds(low)? {
+                break (low, high);
+            }
+            high = low;
+            step = step.saturating_mul(U256::from(2u8));
+        }
+    } else {
+        let mut low = hint;
+        loop {
+            let high = low.checked_add(step).ok_or(SwapError::CalculationOverflow)?;
+            if holds(high)? {
+                break (low, high);
+            }
+            low = high;
+            step = step.saturating_mul(U256::from(2u8));
+        }
+    };
+
+    while high - low > one {
+        let mid = low + (high - low) / 2;
+        if holds(mid)? {
+            high = mid;
+        } else {
+            low = mid;
+        }
+    }
+    Ok(high)
+}
+
+/// Compares both sides of the StableSwap invariant (n = 2) with every denominator cleared:
+/// 16A * x * y * (x + y) + 4D * x * y  vs.  16A * D * x * y + D^3
+/// `Less` means the reserves (x, y) lie below the curve of invariant `d`, i.e. they are worth less than D.
+fn curve_side(x: U256, y: U256, d: U256, amp: U256) -> Result<Ordering> {
+    let overflow = || error!(SwapError::CalculationOverflow);
+    let xy = x.checked_mul(y).ok_or_else(overflow)?;
+    let amp_xy = amp
+        .checked_mul(xy)
+        .and_then(|v| v.checked_mul(U256::from(16u8)))
+        .ok_or_else(overflow)?;
+
+    let lhs = amp_xy
+        .checked_mul(x + y)
+        .and_then(|v| v.checked_add(d.checked_mul(xy)?.checked_mul(U256::from(4u8))?))
+        .ok_or_else(overflow)?;
+    let rhs = amp_xy
+        .checked_mul(d)
+        .and_then(|v| v.checked_add(d.checked_mul(d)?.checked_mul(d)?))
+        .ok_or_else(overflow)?;
+    Ok(lhs.cmp(&rhs))
+}
+
+/// Returns `a * b / c` in 256-bit checked math.
+fn checked_mul_div(a: U256, b: U256, c: U256) -> Result<U256> {
+    a.checked_mul(b)
+        .and_then(|v| v.checked_div(c))
+        .ok_or_else(|| error!(SwapError::CalculationOverflow))
+}
+
+fn abs_diff_u256(a: U256, b: U256) -> U256 {
+    if a > b { a - b } else { b - a }
+}
+
+/// Calculates the output of an exact-input swap on the StableSwap curve.
+/// D and the new output reserve are both rounded up, so the output is rounded down in favor of the pool.
+/// Returns the amount out and the fee taken from the input.
+fn calculate_stable_amount_out(amount_in: u64, reserve_in: u64, reserve_out: u64, fee_bps: u16, amp: u64) -> Result<(u64, u64)> {
+    let (amount_in_after_fee, fee_amount) = split_input_fee(amount_in as u128, fee_bps)?;
+
+    let d = stable_invariant(reserve_in, reserve_out, amp, Rounding::Up)?;
+    let new_reserve_in = (reserve_in as u128).checked_add(amount_in_after_fee).ok_or(SwapError::CalculationOverflow)?;
+    let new_reserve_out = stable_other_reserve(new_reserve_in, d, amp)?;
+
+    let amount_out = (reserve_out as u128).saturating_sub(new_reserve_out);
+    Ok((amount_out as u64, fee_amount))
+}
+
+/// Calculates the input required for an exact-output swap on the StableSwap curve, rounded up.
+/// Returns the amount sent into the pool and the fee included in it.
+fn calculate_stable_amount_in(amount_out: u64, reserve_in: u64, reserve_out: u64, fee_bps: u16, amp: u64) -> Result<(u64, u64)> {
+    // The pool cannot pay out its whole reserve
+    if amount_out >= reserve_out {
+        return err!(SwapError::InsufficientReserves);
+    }
+
+    let d = stable_invariant(reserve_in, reserve_out, amp, Rounding::Up)?;
+    let new_reserve_in = stable_other_reserve((reserve_out - amount_out) as u128, d, amp)?;
+    let amount_in_after_fee = new_reserve_in
+        .checked_sub(reserve_in as u128)
+        .ok_or(SwapError::CalculationOverflow)?;
+
+    add_input_fee(amount_in_after_fee, fee_bps)
+}
+
+/// Calculates the LP shares minted for a deposit of `amount_a` and `amount_b`.
+/// The first deposit (no LP supply yet) mints sqrt(amount_a * amount_b), every later
+/// deposit mints the smaller of the two shares proportional to the existing reserves.
+fn calculate_lp_shares(
+    amount_a: u64,
+    amount_b: u64,
+    reserve_a: u64,
+    reserve_b: u64,
+    lp_supply: u64,
+) -> Result<u64> {
+    require_backed_supply(reserve_a, reserve_b, lp_supply)?;
+    let shares = if lp_supply == 0 {
+        let product = (amount_a as u128)
+            .checked_mul(amount_b as u128)
+            .ok_or(SwapError::CalculationOverflow)?;
+        integer_sqrt(product)
+    } else {
+        let share_a = (amount_a as u128)
+            .checked_mul(lp_supply as u128)
+            .ok_or(SwapError::CalculationOverflow)?
+            .checked_div(reserve_a as u128)
+            .ok_or(SwapError::CalculationOverflow)?;
+        let share_b = (amount_b as u128)
+            .checked_mul(lp_supply as u128)
+            .ok_or(SwapError::CalculationOverflow)?
+            .checked_div(reserve_b as u128)
+            .ok_or(SwapError::CalculationOverflow)?;
+        min(share_a, share_b)
+    };
+
+    u64::try_from(shares).map_err(|_| error!(SwapError::CalculationOverflow))
+}
+
+/// Rejects a deposit into a pool whose LP supply is no longer backed by both reserves, which
+/// would otherwise be priced as a first deposit against the existing shares.
+fn require_backed_supply(reserve_a: u64, reserve_b: u64, lp_supply: u64) -> Result<()> {
+    if lp_supply > 0 && (reserve_a == 0 || reserve_b == 0) {
+        return err!(SwapError::EmptyReserves);
+    }
+    Ok(())
+}
+
+/// Calculates the LP shares minted for a deposit into a StableSwap pool.
+/// The first deposit mints the invariant D of the deposit, every later deposit mints
+/// the LP supply scaled by the relative growth of D. D before the deposit is rounded up
+/// and D after it down, so rounding never mints extra shares.
+fn calculate_stable_lp_shares(
+    amount_a: u64,
+    amount_b: u64,
+    reserve_a: u64,
+    reserve_b: u64,
+    lp_supply: u64,
+    amp: u64,
+) -> Result<u64> {
+    require_backed_supply(reserve_a, reserve_b, lp_supply)?;
+    let shares = if lp_supply == 0 {
+        stable_invariant(amount_a, amount_b, amp, Rounding::Down)?
+    } else {
+        let d_before = stable_invariant(reserve_a, reserve_b, amp, Rounding::Up)?;
+        let d_after = stable_invariant(
+            reserve_a.checked_add(amount_a).ok_or(SwapError::CalculationOverflow)?,
+            reserve_b.checked_add(amount_b).ok_or(SwapError::CalculationOverflow)?,
+            amp,
+            Rounding::Down,
+        )?;
+        (lp_supply as u128)
+            .checked_mul(d_after.saturating_sub(d_before))
+            .ok_or(SwapError::CalculationOverflow)?
+            .checked_div(d_before)
+            .ok_or(SwapError::CalculationOverflow)?
+    };
+
+    u64::try_from(shares).map_err(|_| error!(SwapError::CalculationOverflow))
+}
+
+/// Calculates the output of an exact-input swap on the weighted curve:
+/// out = reserve_out * (1 - (reserve_in / (reserve_in + amount_in))^(weight_in / weight_out)).
+/// The power is rounded up, so the output is rounded down in favor of the pool.
+fn calculate_weighted_amount_out(
+    amount_in_after_fee: u128,
+    reserve_in: u64,
+    reserve_out: u64,
+    weight_in: u16,
+    weight_out: u16,
+) -> Result<u64> {
+    check_weighted_ratio(amount_in_after_fee, reserve_in)?;
+
+    let reserve_in = reserve_in as u128;
+    let new_reserve_in = reserve_in.checked_add(amount_in_after_fee).ok_or(SwapError::CalculationOverflow)?;
+    // base < 1, so rounding the base up and the exponent down both raise the power
+    let base = (reserve_in * WAD).div_ceil(new_reserve_in);
+    let exponent = weight_in as u128 * WAD / weight_out as u128;
+    let power = pow_wad_up(base, exponent)?;
+
+    let amount_out = (reserve_out as u128) * WAD.saturating_sub(power) / WAD;
+    Ok(amount_out as u64)
+}
+
+/// Estimates the input (before fees) required for an exact-output swap on the weighted curve:
+/// in = reserve_in * ((reserve_out / (reserve_out - amount_out))^(weight_out / weight_in) - 1), rounded up.
+fn calculate_weighted_amount_in(
+    amount_out: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    weight_in: u16,
+    weight_out: u16,
+) -> Result<u128> {
+    // The pool cannot pay out its whole reserve
+    if amount_out >= reserve_out {
+        return err!(SwapError::InsufficientReserves);
+    }
+    check_weighted_ratio(amount_out as u128, reserve_out)?;
+
+    let reserve_out = reserve_out as u128;
+    // base > 1, so rounding the base and the exponent up both raise the power
+    let base = (reserve_out * WAD).div_ceil(reserve_out - amount_out as u128);
+    let exponent = (weight_out as u128 * WAD).div_ceil(weight_in as u128);
+    let power = pow_wad_up(base, exponent)?;
+
+    (reserve_in as u128)
+        .checked_mul(power.saturating_sub(WAD))
+        .map(|value| value.div_ceil(WAD))
+        .ok_or_else(|| error!(SwapError::CalculationOverflow))
+}
+
+/// Rejects weighted swaps moving more than `MAX_WEIGHTED_RATIO_BPS` of a reserve,
+/// which keeps the powers well inside the fixed-point range.
+fn check_weighted_ratio(amount: u128, reserve: u64) -> Result<()> {
+    if amount * BPS_DENOMINATOR > reserve as u128 * MAX_WEIGHTED_RATIO_BPS {
+        return err!(SwapError::ExcessivePriceImpact);
+    }
+    Ok(())
+}
+
+/// Returns the weighted invariant amount_a^weight_a * amount_b^weight_b, rounded down.
+fn weighted_invariant(amount_a: u64, amount_b: u64, weight_a_bps: u16, weight_b_bps: u16) -> Result<u128> {
+    if amount_a == 0 || amount_b == 0 {
+        return Ok(0);
+    }
+    let weighted_ln = |amount: u64, weight_bps: u16| -> Result<i128> {
+        let ln = ln_wad(amount as u128 * WAD)?;
+        let weight = (weight_bps as u128 * WAD / BPS_DENOMINATOR) as i128;
+        ln.checked_mul(weight)
+            .map(|value| value / WAD as i128)
+            .ok_or_else(|| error!(SwapError::CalculationOverflow))
+    };
+    let exponent = weighted_ln(amount_a, weight_a_bps)? + weighted_ln(amount_b, weight_b_bps)?;
+    let invariant = exp_wad(exponent)?;
+
+    Ok(invariant.saturating_sub(pow_error_margin(invariant)) / WAD)
+}
+
+// --- Fixed-Point Math ---
+// Deterministic 18-decimal fixed point ("wad") logarithm and exponential for the weighted curve.
+
+/// 1.0 in wad.
+const WAD: u128 = 1_000_000_000_000_000_000;
+
+/// ln(2) in wad.
+const LN_2_WAD: i128 = 693_147_180_559_945_309;
+
+/// Bound on the relative error of `exp_wad(ln_wad(x) * y)`, in wad (1e-14).
+const MAX_POW_RELATIVE_ERROR: u128 = 10_000;
+
+/// Largest share of a reserve a single weighted swap may move in or out (30%).
+const MAX_WEIGHTED_RATIO_BPS: u128 = 3_000;
+
+/// Returns the error margin added to or removed from a power to round it safely.
+fn pow_error_margin(power: u128) -> u128 {
+    power / WAD * MAX_POW_RELATIVE_ERROR + (power % WAD) * MAX_POW_RELATIVE_ERROR / WAD + 1
+}
+
+/// Returns base^exponent for a wad `base` and `exponent`, rounded up.
+fn pow_wad_up(base: u128, exponent: u128) -> Result<u128> {
+    let exponent = i128::try_from(exponent).map_err(|_| error!(SwapError::CalculationOverflow))?;
+    let ln_power = ln_wad(base)?
+        .checked_mul(exponent)
+        .ok_or(SwapError::CalculationOverflow)?
+        / WAD as i128;
+    let power = exp_wad(ln_power)?;
+
+    power.checked_add(pow_error_margin(power)).ok_or_else(|| error!(SwapError::CalculationOverflow))
+}
+
+/// Natural logarithm of a positive wad value, in wad.
+fn ln_wad(x: u128) -> Result<i128> {
+    if x == 0 {
+        return err!(SwapError::CalculationOverflow);
+    }
+
+    // Scale x by a power of two into [1, 2): x = m * 2^k
+    let mut m = x;
+    let mut k: i128 = 0;
+    while m >= 2 * WAD {
+        m >>= 1;
+        k += 1;
+    }
+    while m < WAD {
+        m <<= 1;
+        k -= 1;
+    }
+
+    // ln(m) = 2 * atanh(z) = 2 * (z + z^3/3 + z^5/5 + ...) with z = (m - 1) / (m + 1) < 1/3
+    let z = (m - WAD) * WAD / (m + WAD);
+    let z_squared = z * z / WAD;
+    let mut term = z;
+    let mut sum = 0u128;
+    let mut denominator = 1u128;
+    while term > 0 {
+        sum += term / denominator;
+        term = term * z_squared / WAD;
+        denominator += 2;
+    }
+
+    Ok(k * LN_2_WAD + 2 * sum as i128)
+}
+
+/// e^y for a wad `y`, in wad.
+fn exp_wad(y: i128) -> Result<u128> {
+    // e^y = 2^k * e^r with r = y - k * ln(2) in [0, ln(2))
+    let k = y.div_euclid(LN_2_WAD);
+    let r = y.rem_euclid(LN_2_WAD) as u128;
+
+    // Taylor series e^r = 1 + r + r^2/2! + ...
+    let mut term = WAD;
+    let mut sum = WAD;
+    let mut n = 1u128;
+    while term > 0 {
+        term = term * r / WAD / n;
+        sum += term;
+        n += 1;
+    }
+
+    if k >= 0 {
+        u32::try_from(k)
+            .ok()
+            .and_then(|shift| 1u128.checked_shl(shift))
+            .and_then(|scale| sum.checked_mul(scale))
+            .ok_or_else(|| error!(SwapError::CalculationOverflow))
+    } else {
+        Ok(u32::try_from(-k).ok().and_then(|shift| sum.checked_shr(shift)).unwrap_or(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use proptest::test_runner::TestCaseResult;
+
+    /// The quantity a curve's swaps must never decrease, measured on the pool's reserves.
+    trait Invariant: SwapCurve {
+        fn invariant(&self, reserve_a: u64, reserve_b: u64) -> U256;
+    }
+
+    impl Invariant for ConstantProductCurve {
+        fn invariant(&self, reserve_a: u64, reserve_b: u64) -> U256 {
+            U256::from(reserve_a as u128 * reserve_b as u128)
+        }
+    }
+
+    impl Invariant for StableSwapCurve {
+        fn invariant(&self, reserve_a: u64, reserve_b: u64) -> U256 {
+            U256::from(stable_invariant(reserve_a, reserve_b, self.amp, Rounding::Down).unwrap())
+        }
+    }
+
+    impl Invariant for ConstantPriceCurve {
+        fn invariant(&self, reserve_a: u64, reserve_b: u64) -> U256 {
+            U256::from(self.value_in_a(reserve_a, reserve_b).unwrap())
+        }
+    }
+
+    /// Exact weighted invariant: both weights are reduced by their gcd and the invariant raised
+    /// to the resulting integer powers, which preserves its ordering.
+    impl Invariant for WeightedCurve {
+        fn invariant(&self, reserve_a: u64, reserve_b: u64) -> U256 {
+            let gcd = |mut a: u16, mut b: u16| {
+                while b != 0 {
+                    (a, b) = (b, a % b);
+                }
+                a
+            };
+            let divisor = gcd(self.weight_a_bps, self.weight_b_bps);
+            let power = |reserve: u64, exponent: u16| U256::from(reserve).pow(U256::from(exponent / divisor));
+            power(reserve_a, self.weight_a_bps) * power(reserve_b, self.weight_b_bps)
+        }
+    }
+
+    fn reserves(is_a_to_b: bool, reserve_a: u64, reserve_b: u64) -> SwapReserves {
+        let (reserve_in, reserve_out) = if is_a_to_b { (reserve_a, reserve_b) } else { (reserve_b, reserve_a) };
+        SwapReserves { is_a_to_b, reserve_in, reserve_out }
+    }
+
+    /// Reserves after the pool received `amount_in` and paid out `amount_out`, in pool order.
+    fn reserves_after(reserves: &SwapReserves, swap: &CurveSwap) -> (u64, u64) {
+        let settled = SwapReserves {
+            is_a_to_b: reserves.is_a_to_b,
+            reserve_in: reserves.reserve_in + swap.amount_in,
+            reserve_out: reserves.reserve_out - swap.amount_out,
+        };
+        settled.a_b()
+    }
+
+    fn check_exact_in_preserves_invariant<C: Invariant>(
+        curve: &C,
+        amount_in: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        is_a_to_b: bool,
+        fee_bps: u16,
+    ) -> TestCaseResult {
+        let reserves = reserves(is_a_to_b, reserve_a, reserve_b);
+        let Ok(swap) = curve.swap_exact_in(amount_in, &reserves, fee_bps) else { return Ok(()) };
+        prop_assert_eq!(swap.amount_in, amount_in);
+        prop_assert!(swap.amount_out < reserves.reserve_out);
+
+        let (new_a, new_b) = reserves_after(&reserves, &swap);
+        prop_assert!(curve.invariant(new_a, new_b) >= curve.invariant(reserve_a, reserve_b));
+        Ok(())
+    }
+
+    fn check_exact_out_preserves_invariant<C: Invariant>(
+        curve: &C,
+        amount_out: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        is_a_to_b: bool,
+        fee_bps: u16,
+    ) -> TestCaseResult {
+        let reserves = reserves(is_a_to_b, reserve_a, reserve_b);
+        let Ok(swap) = curve.swap_exact_out(amount_out, &reserves, fee_bps) else { return Ok(()) };
+        prop_assert_eq!(swap.amount_out, amount_out);
+
+        let (new_a, new_b) = reserves_after(&reserves, &swap);
+        prop_assert!(curve.invariant(new_a, new_b) >= curve.invariant(reserve_a, reserve_b));
+        Ok(())
+    }
+
+    /// Paying the exact-out price as an exact-in swap must buy at least the requested amount,
+    /// i.e. exact-out rounds the input up and never undercharges.
+    fn check_exact_out_rounds_up<C: Invariant>(
+        curve: &C,
+        amount_out: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        is_a_to_b: bool,
+        fee_bps: u16,
+    ) -> TestCaseResult {
+        let reserves = reserves(is_a_to_b, reserve_a, reserve_b);
+        let Ok(exact_out) = curve.swap_exact_out(amount_out, &reserves, fee_bps) else { return Ok(()) };
+        let Ok(exact_in) = curve.swap_exact_in(exact_out.amount_in, &reserves, fee_bps) else { return Ok(()) };
+        prop_assert!(exact_in.amount_out >= amount_out);
+        Ok(())
+    }
+
+    /// Asking exact-out for what an exact-in swap paid must not cost more than that swap's input,
+    /// i.e. exact-in rounds the output down and never overpays.
+    fn check_exact_in_rounds_down<C: Invariant>(
+        curve: &C,
+        amount_in: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        is_a_to_b: bool,
+        fee_bps: u16,
+    ) -> TestCaseResult {
+        let reserves = reserves(is_a_to_b, reserve_a, reserve_b);
+        let Ok(exact_in) = curve.swap_exact_in(amount_in, &reserves, fee_bps) else { return Ok(()) };
+        if exact_in.amount_out == 0 {
+            return Ok(());
+        }
+        let Ok(exact_out) = curve.swap_exact_out(exact_in.amount_out, &reserves, fee_bps) else { return Ok(()) };
+        prop_assert!(exact_out.amount_in <= amount_in);
+        Ok(())
+    }
+
+    /// Withdrawing the shares of a proportional deposit must not return more than was deposited,
+    /// measured by the curve's own invariant.
+    fn check_withdraw_after_deposit<C: Invariant>(curve: &C, amount_a: u64, reserve_a: u64, reserve_b: u64) -> TestCaseResult {
+        let amount_b = (amount_a as u128 * reserve_b as u128 / reserve_a as u128) as u64;
+        let lp_supply = curve.deposit_shares(reserve_a, reserve_b, 0, 0, 0).unwrap();
+        let shares = curve.deposit_shares(amount_a, amount_b, reserve_a, reserve_b, lp_supply).unwrap();
+
+        let (out_a, out_b) = curve
+            .withdraw_amounts(shares, reserve_a + amount_a, reserve_b + amount_b, lp_supply + shares)
+            .unwrap();
+        prop_assert!(curve.invariant(out_a, out_b) <= curve.invariant(amount_a, amount_b));
+        Ok(())
+    }
+
+    /// Runs the shared property suite against one curve implementation.
+    macro_rules! curve_properties {
+        ($name:ident, $curve:expr) => {
+            mod $name {
+                use super::*;
+
+                proptest! {
+                    #[test]
+                    fn exact_in_preserves_invariant(
+                        amount in 1u64..100_000_000_000,
+                        reserve_a in 1_000_000u64..1_000_000_000_000,
+                        reserve_b in 1_000_000u64..1_000_000_000_000,
+                        is_a_to_b: bool,
+                        fee_bps in 0u16..=1_000,
+                    ) {
+                        check_exact_in_preserves_invariant(&$curve, amount, reserve_a, reserve_b, is_a_to_b, fee_bps)?;
+                    }
+
+                    #[test]
+                    fn exact_out_preserves_invariant(
+                        amount in 1u64..100_000_000_000,
+                        reserve_a in 1_000_000u64..1_000_000_000_000,
+                        reserve_b in 1_000_000u64..1_000_000_000_000,
+                        is_a_to_b: bool,
+                        fee_bps in 0u16..=1_000,
+                    ) {
+                        check_exact_out_preserves_invariant(&$curve, amount, reserve_a, reserve_b, is_a_to_b, fee_bps)?;
+                    }
+
+                    #[test]
+                    fn exact_out_rounds_up(
+                        amount in 1u64..100_000_000_000,
+                        reserve_a in 1_000_000u64..1_000_000_000_000,
+                        reserve_b in 1_000_000u64..1_000_000_000_000,
+                        is_a_to_b: bool,
+                        fee_bps in 0u16..=1_000,
+                    ) {
+                        check_exact_out_rounds_up(&$curve, amount, reserve_a, reserve_b, is_a_to_b, fee_bps)?;
+                    }
+
+                    #[test]
+                    fn exact_in_rounds_down(
+                        amount in 1u64..100_000_000_000,
+                        reserve_a in 1_000_000u64..1_000_000_000_000,
+                        reserve_b in 1_000_000u64..1_000_000_000_000,
+                        is_a_to_b: bool,
+                        fee_bps in 0u16..=1_000,
+                    ) {
+                        check_exact_in_rounds_down(&$curve, amount, reserve_a, reserve_b, is_a_to_b, fee_bps)?;
+                    }
+
+                    #[test]
+                    fn withdraw_after_deposit_returns_no_more(
+                        amount_a in 1u64..100_000_000_000,
+                        reserve_a in 1_000_000u64..1_000_000_000_000,
+                        reserve_b in 1_000_000u64..1_000_000_000_000,
+                    ) {
+                        check_withdraw_after_deposit(&$curve, amount_a, reserve_a, reserve_b)?;
+                    }
+                }
+            }
+        };
+    }
+
+    curve_properties!(constant_product, ConstantProductCurve);
+    curve_properties!(stable_swap_low_amp, StableSwapCurve { amp: 1 });
+    curve_properties!(stable_swap_high_amp, StableSwapCurve { amp: 2_000 });
+    curve_properties!(constant_price, ConstantPriceCurve { token_b_price: 3 });
+    curve_properties!(weighted_even, WeightedCurve { weight_a_bps: 5_000, weight_b_bps: 5_000 });
+    curve_properties!(weighted_80_20, WeightedCurve { weight_a_bps: 8_000, weight_b_bps: 2_000 });
+
+    proptest! {
+        /// Repaying a flash swap with the input of the equivalent exact-input swap passes the
+        /// product check, and returning only what was taken out does not.
+        #[test]
+        fn flash_swap_repaid_like_exact_in_swap(
+            amount in 1u64..100_000_000_000,
+            reserve_a in 1_000_000u64..1_000_000_000_000,
+            reserve_b in 1_000_000u64..1_000_000_000_000,
+            fee_bps in 0u16..=1_000,
+        ) {
+            let swap = ConstantProductCurve.swap_exact_in(amount, &reserves(true, reserve_a, reserve_b), fee_bps).unwrap();
+            prop_assume!(swap.amount_out > 0);
+            let amounts_out = (0, swap.amount_out);
+
+            let repaid = (reserve_a + swap.amount_in, reserve_b - swap.amount_out);
+            let (leg_a, leg_b) = check_flash_swap_product((reserve_a, reserve_b), amounts_out, repaid, fee_bps).unwrap();
+            prop_assert_eq!(leg_a, CurveSwap { amount_out: 0, ..swap });
+            prop_assert_eq!(leg_b, CurveSwap { amount_in: 0, amount_out: swap.amount_out, fee_amount: 0 });
+
+            let unpaid = (reserve_a, reserve_b - swap.amount_out);
+            prop_assert!(check_flash_swap_product((reserve_a, reserve_b), amounts_out, unpaid, fee_bps).is_err());
+        }
+
+        /// A deposit into a pool whose LP supply lost one of its reserves is rejected instead of
+        /// being priced like a first deposit.
+        #[test]
+        fn deposit_against_empty_reserve_fails(
+            amount in 1u64..100_000_000_000,
+            reserve in 1_000_000u64..1_000_000_000_000,
+            lp_supply in 1u64..1_000_000_000_000,
+        ) {
+            prop_assert!(ConstantProductCurve.deposit_shares(amount, amount, 0, reserve, lp_supply).is_err());
+            let stable = StableSwapCurve { amp: 100 };
+            prop_assert!(stable.deposit_shares(amount, amount, reserve, 0, lp_supply).is_err());
+            let weighted = WeightedCurve { weight_a_bps: 8_000, weight_b_bps: 2_000 };
+            prop_assert!(weighted.deposit_shares(amount, amount, 0, reserve, lp_supply).is_err());
+        }
+    }
+}
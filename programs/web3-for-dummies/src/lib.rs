@@ -1,25 +1,33 @@
-use anchor_lang::{prelude::*, system_program};
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{
+    instruction::{get_stack_height, Instruction, TRANSACTION_LEVEL_STACK_HEIGHT},
+    program::invoke,
+    sysvar::instructions::{load_current_index_checked, load_instruction_at_checked},
+};
 use anchor_spl::{
     associated_token::AssociatedToken,
     token_interface::{
-        self, // Using the newer token_interface standard
         transfer_checked, // For transfers that check mint decimals
         Mint,
         TokenAccount,
         TokenInterface,
         TransferChecked, // Struct for transfer_checked CPI
-        MintTo, // Struct for mint_to CPI (not used here, but good practice)
-        mint_to, // Function for mint_to CPI (not used here)
-        Burn, // Struct for burn CPI (not used here)
-        burn // Function for burn CPI (not used here)
+        MintTo, // Struct for mint_to CPI (used to mint LP shares)
+        mint_to, // Function for mint_to CPI
+        Burn, // Struct for burn CPI (used to burn LP shares)
+        burn, // Function for burn CPI
     },
 
 };
 
-// Required for getting mutable access to account data, e.g., pool state
-use std::ops::DerefMut;
-// Required for min/max functions used in PDA seed generation
-use std::cmp::{max, min};
+// Pricing curves behind the `SwapCurve` trait
+mod curve;
+pub use curve::*;
+use curve::{integer_sqrt, U256};
+// Tick and sqrt-price math for concentrated-liquidity pools
+mod concentrated;
+pub use concentrated::*;
+
 
 
 // Declare the program's on-chain address (ID)
@@ -30,39 +38,169 @@ pub mod web3_for_dummies {
 
     use super::*; // Imports items from the outer scope (like structs, errors, etc.)
 
-    /// CHANGED
-    /// Creates the pool state account and associated token accounts (vaults) to hold the tokens.
-    pub fn initialize_pool(ctx: Context<InitializePool>) -> Result<()> {
-        // Get mutable access to the newly created pool account
-        let pool = &mut ctx.accounts.pool;
+    /// Creates the singleton program config holding the protocol admin, the treasury
+    /// that receives protocol fees and the protocol parameters new pools start from.
+    pub fn initialize_config(ctx: Context<InitializeConfig>, treasury: Pubkey, params: ConfigParams) -> Result<()> {
+        params.validate()?;
 
-        let (smaller_mint, larger_mint) = if ctx.accounts.token_a_mint.key() < ctx.accounts.token_b_mint.key() {
-            (ctx.accounts.token_a_mint.key(), ctx.accounts.token_b_mint.key())
-            
-        } else {
-            (ctx.accounts.token_b_mint.key(), ctx.accounts.token_a_mint.key())            
+        let config = &mut ctx.accounts.config;
+        config.admin = ctx.accounts.admin.key();
+        config.treasury = treasury;
+        config.bump = ctx.bumps.config;
+        config.apply(&params);
 
-        };
+        msg!("Config Initialized!");
+        msg!("Admin: {}", config.admin);
+        msg!("Treasury: {}", config.treasury);
+        msg!("Protocol Fee Share (bps): {}", config.protocol_fee_share_bps);
+        msg!("Default Fee (bps): {}", config.default_fee_bps);
+        msg!("Max Fee (bps): {}", config.max_fee_bps);
+
+        Ok(())
+    }
+
+    /// Replaces the treasury and protocol parameters. Only the protocol admin can call this.
+    /// Existing pools keep the values they were created with.
+    pub fn update_config(ctx: Context<UpdateConfig>, treasury: Pubkey, params: ConfigParams) -> Result<()> {
+        params.validate()?;
+
+        let config = &mut ctx.accounts.config;
+        config.treasury = treasury;
+        config.apply(&params);
+
+        emit!(ConfigUpdatedEvent {
+            admin: config.admin,
+            treasury,
+            params,
+        });
+
+        Ok(())
+    }
+
+    /// Starts a handover of the protocol admin role to `new_admin`. Only the current admin can call this.
+    pub fn propose_config_admin(ctx: Context<UpdateConfig>, new_admin: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        let target = config.key();
+        propose_admin_transfer(target, config.admin, &mut config.pending_admin, new_admin)
+    }
 
-        pool.token_a_mint = smaller_mint;
+    /// Completes a protocol admin handover. Must be signed by the proposed admin.
+    pub fn accept_config_admin(ctx: Context<AcceptConfigAdmin>) -> Result<()> {
+        let target = ctx.accounts.config.key();
+        let config: &mut ProgramConfig = &mut ctx.accounts.config;
+        accept_admin_transfer(target, &mut config.admin, &mut config.pending_admin)
+    }
 
-        pool.token_b_mint = larger_mint;
+    /// Withdraws a pending protocol admin handover. Only the current admin can call this.
+    pub fn cancel_config_admin_transfer(ctx: Context<UpdateConfig>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        let target = config.key();
+        cancel_pending_admin(target, config.admin, &mut config.pending_admin)
+    }
 
-        if ctx.accounts.token_a_mint.key() == smaller_mint {
-            pool.token_a_vault = ctx.accounts.token_a_vault.key();
-            pool.token_b_vault = ctx.accounts.token_b_vault.key();
-        }else {
-            pool.token_a_vault = ctx.accounts.token_b_vault.key();
-            pool.token_b_vault = ctx.accounts.token_a_vault.key();
+    /// CHANGED
+    /// Creates the pool state account and associated token accounts (vaults) to hold the tokens.
+    /// `fee_bps` is the swap fee charged on the input amount, bounded by the config's `max_fee_bps`.
+    /// Passing `None` uses the config's `default_fee_bps`.
+    /// `curve_param` is the StableSwap `amp`, the constant-price `token_b_price`, or the weighted
+    /// pool's Token A weight in basis points (Token B gets the rest). It must be zero for
+    /// constant-product and liquidity bootstrapping pools.
+    /// `weight_schedule` is required for liquidity bootstrapping pools and rejected for all others.
+    pub fn initialize_pool(
+        ctx: Context<InitializePool>,
+        fee_bps: Option<u16>,
+        curve_kind: CurveKind,
+        curve_param: u64,
+        weight_schedule: Option<WeightSchedule>,
+    ) -> Result<()> {
+        let config = &ctx.accounts.config;
+        if !config.pool_creation_enabled {
+            return err!(SwapError::PoolCreationDisabled);
         }
-        // Store the public keys of the token mints and vaults in the pool state
-        // pool.token_a_mint = ctx.accounts.token_a_mint.key();
-        // pool.token_b_mint = ctx.accounts.token_b_mint.key();
-        // pool.token_a_vault = ctx.accounts.token_a_vault.key();
-        // pool.token_b_vault = ctx.accounts.token_b_vault.key();
+        let fee_bps = fee_bps.unwrap_or(config.default_fee_bps);
+        if fee_bps > config.max_fee_bps {
+            return err!(SwapError::FeeTooHigh);
+        }
+        match curve_kind {
+            CurveKind::ConstantProduct if curve_param != 0 => return err!(SwapError::InvalidCurveParams),
+            // The invariant compares raw amounts, so both tokens must use the same decimals
+            CurveKind::StableSwap if !(MIN_AMP..=MAX_AMP).contains(&curve_param)
+                || ctx.accounts.token_a_mint.decimals != ctx.accounts.token_b_mint.decimals =>
+            {
+                return err!(SwapError::InvalidCurveParams)
+            }
+            CurveKind::ConstantPrice if curve_param == 0 => return err!(SwapError::InvalidCurveParams),
+            CurveKind::Weighted if !(MIN_WEIGHT_BPS..=BPS_DENOMINATOR as u64 - MIN_WEIGHT_BPS).contains(&curve_param) => {
+                return err!(SwapError::InvalidCurveParams)
+            }
+            CurveKind::LiquidityBootstrapping if curve_param != 0 => return err!(SwapError::InvalidCurveParams),
+            _ => {}
+        }
+        let now = Clock::get()?.unix_timestamp;
+        let weight_schedule = match (curve_kind, weight_schedule) {
+            (CurveKind::LiquidityBootstrapping, Some(schedule)) => {
+                schedule.validate(now)?;
+                schedule
+            }
+            (CurveKind::LiquidityBootstrapping, None) | (_, Some(_)) => return err!(SwapError::InvalidCurveParams),
+            (_, None) => WeightSchedule::default(),
+        };
+        let amp = if curve_kind == CurveKind::StableSwap { curve_param } else { 0 };
+
+        // Get mutable access to the newly created pool account
+        let pool = &mut ctx.accounts.pool;
+
+        // Store the public keys of the token mints and vaults in the pool state.
+        // The mints are already in canonical (sorted) order, enforced by the InitializePool constraints,
+        // so the pool PDA seeds match the order every other instruction re-derives them in.
+        pool.token_a_mint = ctx.accounts.token_a_mint.key();
+        pool.token_b_mint = ctx.accounts.token_b_mint.key();
+        pool.token_a_vault = ctx.accounts.token_a_vault.key();
+        pool.token_b_vault = ctx.accounts.token_b_vault.key();
         // Store the bump seed for the pool's PDA, needed for signing CPIs later
         // Use the bump specific to the 'pool' account derivation
         pool.bump = ctx.bumps.pool; // Anchor still provides the bump used for init
+        // The LP share mint is created alongside the pool, with the pool authority PDA as mint authority
+        pool.lp_mint = ctx.accounts.lp_mint.key();
+        pool.fee_bps = fee_bps;
+        pool.protocol_fee_share_bps = config.protocol_fee_share_bps;
+        pool.max_price_impact_bps = config.default_max_price_impact_bps;
+        pool.deposit_ratio_tolerance_bps = config.deposit_ratio_tolerance_bps;
+        // The pool creator administers the pool, which starts fully active
+        pool.admin = ctx.accounts.initializer.key();
+        pool.creator = ctx.accounts.initializer.key();
+        pool.pending_admin = Pubkey::default();
+        pool.status = 0;
+        // The price oracle starts accumulating from pool creation
+        pool.last_update_ts = now;
+        pool.curve_kind = curve_kind;
+        pool.amp_initial = amp;
+        pool.amp_target = amp;
+        pool.amp_ramp_start_ts = pool.last_update_ts;
+        pool.amp_ramp_end_ts = pool.last_update_ts;
+        pool.token_b_price = if curve_kind == CurveKind::ConstantPrice { curve_param } else { 0 };
+        // Weights are normalized to sum to BPS_DENOMINATOR
+        (pool.weight_a_bps, pool.weight_b_bps) = if curve_kind == CurveKind::Weighted {
+            (curve_param as u16, (BPS_DENOMINATOR as u64 - curve_param) as u16)
+        } else {
+            (0, 0)
+        };
+        pool.start_weights = weight_schedule.start_weights;
+        pool.end_weights = weight_schedule.end_weights;
+        pool.sale_start_ts = weight_schedule.start_ts;
+        pool.sale_end_ts = weight_schedule.end_ts;
+
+        // The observation buffer starts with a single slot holding the creation-time observation
+        let observations = &mut ctx.accounts.observations;
+        observations.pool = pool.key();
+        observations.index = 0;
+        observations.cardinality = 1;
+        observations.last_write_slot = Clock::get()?.slot;
+        observations.bump = ctx.bumps.observations;
+        observations.observations = vec![Observation {
+            timestamp: pool.last_update_ts,
+            ..Observation::default()
+        }];
 
         // Log the details of the initialized pool (useful for debugging)
         msg!("Pool Initialized!");
@@ -70,147 +208,454 @@ pub mod web3_for_dummies {
         msg!("Mint B: {}", pool.token_b_mint);
         msg!("Vault A: {}", pool.token_a_vault);
         msg!("Vault B: {}", pool.token_b_vault);
+        msg!("LP Mint: {}", pool.lp_mint);
+        msg!("Fee (bps): {}", pool.fee_bps);
+        msg!("Protocol Fee Share (bps): {}", pool.protocol_fee_share_bps);
+        msg!("Max Price Impact (bps): {}", pool.max_price_impact_bps);
+        msg!("Curve: {:?}, Param: {}", pool.curve_kind, curve_param);
+        msg!("Admin: {}", pool.admin);
         msg!("Pool Bump: {}", pool.bump);
 
 
         Ok(()) // Indicate successful execution
     }
 
-    /// Swaps one token for another using the constant product formula.
-    /// Requires the amount of token to send in and the minimum amount of token expected out (slippage protection).
-    pub fn swap(ctx: Context<Swap>, amount_in: u64, min_amount_out: u64) -> Result<()> {
-        // Get immutable access to the pool state
-        let pool = &ctx.accounts.pool;
+    /// Grows a pool created with the original 201-byte layout to `POOL_ACCOUNT_SIZE` and fills in
+    /// the fields added since, as `initialize_pool` would for a constant-product pool using the
+    /// config's defaults. Also creates the pool's LP mint and observation buffer.
+    /// Only the protocol admin can migrate a pool, and becomes its admin.
+    pub fn migrate_pool(ctx: Context<MigratePool>) -> Result<()> {
+        let config = &ctx.accounts.config;
+        let pool_info = ctx.accounts.pool.to_account_info();
+        if pool_info.data_len() >= POOL_ACCOUNT_SIZE {
+            return err!(SwapError::PoolAlreadyMigrated);
+        }
 
-        // --- Input Validation ---
-        // Ensure the user's source token account mint matches one of the pool's tokens
-        // This check is partially redundant due to constraints but good for clarity
-        if ctx.accounts.user_source_token_account.mint != pool.token_a_mint && ctx.accounts.user_source_token_account.mint != pool.token_b_mint {
-            return err!(SwapError::InvalidMint);
+        // Top the account up to the rent-exempt minimum for its new size, then grow it
+        let rent_due = Rent::get()?
+            .minimum_balance(POOL_ACCOUNT_SIZE)
+            .saturating_sub(pool_info.lamports());
+        if rent_due > 0 {
+            let rent_cpi = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.admin.to_account_info(),
+                    to: pool_info.clone(),
+                },
+            );
+            anchor_lang::system_program::transfer(rent_cpi, rent_due)?;
         }
+        pool_info.realloc(POOL_ACCOUNT_SIZE, true)?;
 
-        
-
-        // --- Determine Source/Destination Vaults ---
-        // Figure out which pool vault receives tokens (source) and which sends tokens (destination)
-        // based on the mint of the user's source token account.
-        // Also retrieve the decimals of the source mint for transfer_checked.
-        let (source_vault_account, dest_vault_account, source_mint_decimals) = {
-            if ctx.accounts.user_source_token_account.mint == pool.token_a_mint {
-                // User is sending Token A, wants Token B
-                (
-                    &mut ctx.accounts.token_a_vault, // Pool's vault A is the source
-                    &mut ctx.accounts.token_b_vault, // Pool's vault B is the destination
-                    ctx.accounts.source_mint.decimals, // Decimals of Token A
-                )
-            } else {
-                // User is sending Token B, wants Token A (since we already validated the mint)
-                (
-                    &mut ctx.accounts.token_b_vault, // Pool's vault B is the source
-                    &mut ctx.accounts.token_a_vault, // Pool's vault A is the destination
-                    ctx.accounts.source_mint.decimals, // Decimals of Token B
-                )
-            }
+        // The new bytes are zeroed, so every field past the original ones reads as its default
+        let mut pool = LiquidityPool::try_deserialize(&mut &pool_info.try_borrow_data()?[..])?;
+        if ctx.accounts.token_a_vault.key() != pool.token_a_vault || ctx.accounts.token_b_vault.key() != pool.token_b_vault {
+            return err!(SwapError::InvalidVault);
+        }
+
+        let now = Clock::get()?.unix_timestamp;
+        pool.lp_mint = ctx.accounts.lp_mint.key();
+        pool.fee_bps = config.default_fee_bps;
+        pool.protocol_fee_share_bps = config.protocol_fee_share_bps;
+        pool.max_price_impact_bps = config.default_max_price_impact_bps;
+        pool.deposit_ratio_tolerance_bps = config.deposit_ratio_tolerance_bps;
+        pool.admin = ctx.accounts.admin.key();
+        pool.creator = ctx.accounts.admin.key();
+        pool.last_update_ts = now;
+        pool.amp_ramp_start_ts = now;
+        pool.amp_ramp_end_ts = now;
+
+        // Liquidity deposited before LP shares existed has no owner, so it is locked like the
+        // first deposit's minimum liquidity
+        let (reserve_a, reserve_b) = (ctx.accounts.token_a_vault.amount, ctx.accounts.token_b_vault.amount);
+        pool.locked_lp_amount = if reserve_a > 0 && reserve_b > 0 {
+            ConstantProductCurve.deposit_shares(reserve_a, reserve_b, 0, 0, 0)?
+        } else {
+            0
         };
+        pool.try_serialize(&mut &mut pool_info.try_borrow_mut_data()?[..])?;
 
+        let observations = &mut ctx.accounts.observations;
+        observations.pool = pool_info.key();
+        observations.index = 0;
+        observations.cardinality = 1;
+        observations.last_write_slot = Clock::get()?.slot;
+        observations.bump = ctx.bumps.observations;
+        observations.observations = vec![Observation {
+            timestamp: now,
+            ..Observation::default()
+        }];
 
-        // --- Destination Mint Check ---
-        // Ensure the user's destination token account matches the mint of the pool's destination vault
-        // This check is partially redundant due to constraints but good for clarity
-        if ctx.accounts.user_destination_token_account.mint != dest_vault_account.mint {
-            return err!(SwapError::InvalidDestinationMint);
-        }
+        msg!("Pool Migrated: {}", pool_info.key());
+        msg!("LP Mint: {}", pool.lp_mint);
+        msg!("Locked LP: {}", pool.locked_lp_amount);
+
+        Ok(())
+    }
+
+    /// Swaps one token for another using the constant product formula.
+    /// Requires the amount of token to send in and the minimum amount of token expected out (slippage protection).
+    /// An optional `deadline_unix_ts` rejects the swap once the cluster clock has passed it.
+    pub fn swap(ctx: Context<Swap>, amount_in: u64, min_amount_out: u64, deadline_unix_ts: Option<i64>) -> Result<()> {
+        check_deadline(deadline_unix_ts)?;
+        ctx.accounts.pool.require_not_paused(POOL_STATUS_SWAPS_PAUSED)?;
+        ctx.accounts.pool.require_unlocked()?;
 
         // --- Get Reserves ---
-        // Reload vault accounts to get the latest balance data on-chain
-        // It's crucial to reload *before* calculations to prevent race conditions.
-        source_vault_account.reload()?;
-        dest_vault_account.reload()?;
-        let reserve_in = source_vault_account.amount; // Current balance of the token being sent *in*
-        let reserve_out = dest_vault_account.amount; // Current balance of the token being sent *out*
+        let reserves = ctx.accounts.load_reserves()?;
+
+        // --- Update Price Oracle ---
+        // Accumulate the pre-trade price for the time elapsed since the last update
+        let (reserve_a, reserve_b) = reserves.a_b();
+        ctx.accounts.pool.update_price_accumulators(reserve_a, reserve_b)?;
+        ctx.accounts.observations.write(&ctx.accounts.pool, reserve_a, reserve_b)?;
 
         // --- Swap Calculation (Constant Product: x * y = k) ---
-        // Convert amounts to u128 for calculation to prevent intermediate overflows
-        let amount_in_u128 = amount_in as u128;
-        let reserve_in_u128 = reserve_in as u128;
-        let reserve_out_u128 = reserve_out as u128;
+        let quote = quote_exact_in(amount_in, &reserves, &ctx.accounts.pool)?;
 
-        // Basic checks before calculation
-        if reserve_in == 0 || reserve_out == 0 {
-            return err!(SwapError::PoolIsEmpty); // Cannot swap if a pool is empty
+        // --- Slippage Check ---
+        // Ensure the calculated amount_out meets the user's minimum requirement
+        if quote.amount_out < min_amount_out {
+            return err!(SwapError::SlippageExceeded);
+        }
+
+        // --- Perform Transfers and Emit Event ---
+        ctx.accounts.settle(reserves.is_a_to_b, quote.amount_in, quote.amount_out, quote.fee_amount)
+    }
+
+    /// Swaps one token for an exact amount of the other using the constant product formula.
+    /// Requires the exact amount of token to receive and the maximum amount of token to send in (slippage protection).
+    /// An optional `deadline_unix_ts` rejects the swap once the cluster clock has passed it.
+    pub fn swap_exact_out(ctx: Context<Swap>, amount_out: u64, max_amount_in: u64, deadline_unix_ts: Option<i64>) -> Result<()> {
+        check_deadline(deadline_unix_ts)?;
+        ctx.accounts.pool.require_not_paused(POOL_STATUS_SWAPS_PAUSED)?;
+        ctx.accounts.pool.require_unlocked()?;
+
+        // --- Get Reserves ---
+        let reserves = ctx.accounts.load_reserves()?;
+
+        // --- Update Price Oracle ---
+        // Accumulate the pre-trade price for the time elapsed since the last update
+        let (reserve_a, reserve_b) = reserves.a_b();
+        ctx.accounts.pool.update_price_accumulators(reserve_a, reserve_b)?;
+        ctx.accounts.observations.write(&ctx.accounts.pool, reserve_a, reserve_b)?;
+
+        // --- Swap Calculation (Inverted Constant Product) ---
+        let quote = quote_exact_out(amount_out, &reserves, &ctx.accounts.pool)?;
+
+        // --- Slippage Check ---
+        // Ensure the required amount_in does not exceed the user's maximum
+        if quote.amount_in > max_amount_in {
+            return err!(SwapError::SlippageExceeded);
+        }
+
+        // --- Perform Transfers and Emit Event ---
+        ctx.accounts.settle(reserves.is_a_to_b, quote.amount_in, quote.amount_out, quote.fee_amount)
+    }
+
+    /// Read-only preview of `swap`: prices `amount_in` through the same code path, including the
+    /// fee and the price impact cap, and returns the result as return data (use `simulateTransaction`).
+    pub fn quote_swap(ctx: Context<QuoteSwap>, amount_in: u64, direction: SwapDirection) -> Result<SwapQuote> {
+        let reserves = ctx.accounts.pool.swap_reserves(
+            ctx.accounts.token_a_vault.amount,
+            ctx.accounts.token_b_vault.amount,
+            direction == SwapDirection::AToB,
+        )?;
+        quote_exact_in(amount_in, &reserves, &ctx.accounts.pool)
+    }
+
+    /// Read-only preview of `swap_exact_out`: returns the input required to receive exactly `amount_out`.
+    pub fn quote_swap_exact_out(ctx: Context<QuoteSwap>, amount_out: u64, direction: SwapDirection) -> Result<SwapQuote> {
+        let reserves = ctx.accounts.pool.swap_reserves(
+            ctx.accounts.token_a_vault.amount,
+            ctx.accounts.token_b_vault.amount,
+            direction == SwapDirection::AToB,
+        )?;
+        quote_exact_out(amount_out, &reserves, &ctx.accounts.pool)
+    }
+
+    /// Read-only TWAP view: returns the average prices (Q64.64) between the caller's earlier
+    /// `start` observation and now. A pool's stored accumulators and `last_update_ts` can be
+    /// used as an observation.
+    pub fn get_twap(ctx: Context<GetTwap>, start: PriceObservation) -> Result<TwapPrices> {
+        let (reserve_a, reserve_b) = ctx.accounts.pool.available_reserves(
+            ctx.accounts.token_a_vault.amount,
+            ctx.accounts.token_b_vault.amount,
+        )?;
+        let now = ctx.accounts.pool.observe(reserve_a, reserve_b, Clock::get()?.unix_timestamp);
+        compute_twap(&start, &now)
+    }
+
+    /// Read-only TWAP view over the last `seconds_ago` seconds, using the pool's observation buffer.
+    /// Fails if the buffer does not reach back far enough.
+    pub fn observe_twap(ctx: Context<ObserveTwap>, seconds_ago: u32) -> Result<ObservedTwap> {
+        if seconds_ago == 0 {
+            return err!(SwapError::InvalidObservation);
+        }
+
+        let pool = &ctx.accounts.pool;
+        let (reserve_a, reserve_b) = pool.available_reserves(
+            ctx.accounts.token_a_vault.amount,
+            ctx.accounts.token_b_vault.amount,
+        )?;
+        let now = Clock::get()?.unix_timestamp;
+        let observations = &ctx.accounts.observations;
+
+        let end = observations.current(pool, reserve_a, reserve_b, now)?;
+        let start = observations.observation_at(&end, now - seconds_ago as i64)?;
+        compute_observed_twap(&start, &end)
+    }
+
+    /// Grows a pool's observation buffer to `new_cardinality` slots. Anyone can pay for the extra space.
+    pub fn increase_observation_cardinality(ctx: Context<IncreaseObservationCardinality>, new_cardinality: u16) -> Result<()> {
+        let observations = &mut ctx.accounts.observations;
+        let old_cardinality = observations.observations.len() as u16;
+        if new_cardinality <= old_cardinality || new_cardinality > MAX_OBSERVATION_CARDINALITY {
+            return err!(SwapError::InvalidObservationCardinality);
         }
+
+        // New slots stay empty (timestamp 0) until the ring buffer wraps into them
+        observations.observations.resize(new_cardinality as usize, Observation::default());
+
+        emit!(ObservationCardinalityIncreasedEvent {
+            pool: ctx.accounts.pool.key(),
+            old_cardinality,
+            new_cardinality,
+        });
+
+        Ok(())
+    }
+
+    /// Swaps through an ordered list of pools in one instruction (e.g. A -> B -> C).
+    /// Each hop is passed as `ROUTE_ACCOUNTS_PER_HOP` remaining accounts:
+    /// [pool, pool_authority, vault_in, vault_out, mint_in, mint_out, observations].
+    /// Intermediate tokens move directly from one pool's vault to the next, and a single
+    /// slippage check is applied to the final output.
+    pub fn route_swap<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RouteSwap<'info>>,
+        amount_in: u64,
+        min_final_out: u64,
+        hops: u8,
+        deadline_unix_ts: Option<i64>,
+    ) -> Result<()> {
+        check_deadline(deadline_unix_ts)?;
+
         if amount_in == 0 {
-            return err!(SwapError::ZeroAmount); // Input amount must be positive
+            return err!(SwapError::ZeroAmount);
         }
 
-        let fee_numerator = 3;
-        let fee_denomiantor = 1000;
-        let amount_in_after_fee = amount_in_u128
-            .checked_mul(fee_denomiantor - fee_numerator)
-            .ok_or(SwapError::CalculationOverflow)?
-            .checked_div(fee_denomiantor)
-            .ok_or(SwapError::CalculationOverflow)?;
+        // --- Route Validation ---
+        let hops = hops as usize;
+        if hops == 0
+            || hops > MAX_ROUTE_HOPS as usize
+            || ctx.remaining_accounts.len() != hops * ROUTE_ACCOUNTS_PER_HOP
+        {
+            return err!(SwapError::InvalidRoute);
+        }
+
+        let mut amount = amount_in;
+        let mut expected_mint_in = ctx.accounts.user_source_token_account.mint;
+
+        for (hop, hop_accounts) in ctx.remaining_accounts.chunks(ROUTE_ACCOUNTS_PER_HOP).enumerate() {
+            let is_last_hop = hop + 1 == hops;
+            let pool_info = &hop_accounts[0];
+            let pool_authority_info = &hop_accounts[1];
+            let vault_in_info = &hop_accounts[2];
+            let vault_out_info = &hop_accounts[3];
+
+            let mut pool = Account::<LiquidityPool>::try_from(pool_info)?;
+            pool.require_not_paused(POOL_STATUS_SWAPS_PAUSED)?;
+            pool.require_unlocked()?;
+            let mint_in = InterfaceAccount::<Mint>::try_from(&hop_accounts[4])?;
+            let mint_out = InterfaceAccount::<Mint>::try_from(&hop_accounts[5])?;
+            let mut observations = Account::<PoolObservations>::try_from(&hop_accounts[6])?;
+            if observations.pool != pool.key() {
+                return err!(SwapError::InvalidRoute);
+            }
+
+            // The token entering this hop must be the one that left the previous hop
+            if mint_in.key() != expected_mint_in {
+                return err!(SwapError::InvalidRoute);
+            }
+
+            // --- Determine Direction and Validate Vaults ---
+            let is_a_to_b = if mint_in.key() == pool.token_a_mint && mint_out.key() == pool.token_b_mint {
+                true
+            } else if mint_in.key() == pool.token_b_mint && mint_out.key() == pool.token_a_mint {
+                false
+            } else {
+                return err!(SwapError::InvalidMint);
+            };
+            let (expected_vault_in, expected_vault_out, owed_in, owed_out) = if is_a_to_b {
+                (pool.token_a_vault, pool.token_b_vault, pool.protocol_fees_owed_a, pool.protocol_fees_owed_b)
+            } else {
+                (pool.token_b_vault, pool.token_a_vault, pool.protocol_fees_owed_b, pool.protocol_fees_owed_a)
+            };
+            if vault_in_info.key() != expected_vault_in || vault_out_info.key() != expected_vault_out {
+                return err!(SwapError::InvalidVault);
+            }
+
+            // The pool authority PDA must match the seeds stored in the pool
+            let pool_bump_slice = &[pool.bump];
+            let pool_signer_seeds: &[&[u8]] = &[
+                b"pool",
+                pool.token_a_mint.as_ref(),
+                pool.token_b_mint.as_ref(),
+                pool_bump_slice
+            ];
+            let expected_authority = Pubkey::create_program_address(pool_signer_seeds, ctx.program_id)
+                .map_err(|_| error!(SwapError::InvalidVault))?;
+            if pool_authority_info.key() != expected_authority {
+                return err!(SwapError::InvalidVault);
+            }
+
+            // 1. Transfer IN: only the first hop pulls from the user, later hops were
+            // already paid by the previous pool's vault
+            if hop == 0 {
+                let transfer_in_accounts = TransferChecked {
+                    from: ctx.accounts.user_source_token_account.to_account_info(),
+                    mint: mint_in.to_account_info(),
+                    to: vault_in_info.clone(),
+                    authority: ctx.accounts.user_authority.to_account_info(),
+                };
+                let transfer_in_cpi = CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    transfer_in_accounts,
+                );
+                transfer_checked(transfer_in_cpi, amount, mint_in.decimals)?;
+            }
+
+            // --- Get Reserves ---
+            // The input has already landed in vault_in, so it is excluded from the reserve
+            let vault_in = InterfaceAccount::<TokenAccount>::try_from(vault_in_info)?;
+            let vault_out = InterfaceAccount::<TokenAccount>::try_from(vault_out_info)?;
+            let reserve_in = vault_in.amount
+                .checked_sub(owed_in)
+                .and_then(|reserve| reserve.checked_sub(amount))
+                .ok_or(SwapError::CalculationOverflow)?;
+            let reserve_out = vault_out.amount.checked_sub(owed_out).ok_or(SwapError::CalculationOverflow)?;
+            if reserve_in == 0 || reserve_out == 0 {
+                return err!(SwapError::PoolIsEmpty);
+            }
 
-        let constant_product = reserve_in_u128.checked_mul(reserve_out_u128).ok_or(SwapError::CalculationOverflow)?;
-        let new_reserve_in = reserve_in_u128.checked_add(amount_in_after_fee).ok_or(SwapError::CalculationOverflow)?;
+            // --- Swap Calculation (Constant Product: x * y = k) ---
+            let reserves = SwapReserves { is_a_to_b, reserve_in, reserve_out };
+            let SwapQuote { amount_out, fee_amount, .. } = quote_exact_in(amount, &reserves, &pool)?;
+
+            // 2. Transfer OUT: Pool Vault -> next pool's input vault, or the user on the last hop
+            let destination = if is_last_hop {
+                ctx.accounts.user_destination_token_account.to_account_info()
+            } else {
+                // vault_in of the next hop, validated against its pool on the next iteration
+                ctx.remaining_accounts[(hop + 1) * ROUTE_ACCOUNTS_PER_HOP + 2].clone()
+            };
+            let signer = &[pool_signer_seeds];
+            let transfer_out_accounts = TransferChecked {
+                from: vault_out_info.clone(),
+                mint: mint_out.to_account_info(),
+                to: destination,
+                authority: pool_authority_info.clone(),
+            };
+            let transfer_out_cpi = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                transfer_out_accounts,
+                signer,
+            );
+            transfer_checked(transfer_out_cpi, amount_out, mint_out.decimals)?;
 
-        // Calculate the constant product (k)
-        // x * y = k
-        let constant_product = reserve_in_u128.checked_mul(reserve_out_u128).ok_or(SwapError::CalculationOverflow)?;
+            // --- Update Price Oracle ---
+            // Accumulate the pre-trade price for the time elapsed since the last update
+            let (reserve_a, reserve_b) = reserves.a_b();
+            pool.update_price_accumulators(reserve_a, reserve_b)?;
+            observations.write(&pool, reserve_a, reserve_b)?;
+            observations.exit(ctx.program_id)?;
 
-        // Calculate the new reserve amount for the input token
-        // new_x = x + amount_in
-        let new_reserve_in = reserve_in_u128.checked_add(amount_in_u128).ok_or(SwapError::CalculationOverflow)?;
+            // --- Accrue Protocol Fee ---
+            let protocol_fee = calculate_protocol_fee(fee_amount, pool.protocol_fee_share_bps)?;
+            if is_a_to_b {
+                pool.protocol_fees_owed_a = pool.protocol_fees_owed_a.checked_add(protocol_fee).ok_or(SwapError::CalculationOverflow)?;
+            } else {
+                pool.protocol_fees_owed_b = pool.protocol_fees_owed_b.checked_add(protocol_fee).ok_or(SwapError::CalculationOverflow)?;
+            }
+            // Persist the pool so a later hop through the same pool sees the update
+            pool.exit(ctx.program_id)?;
 
-        // Calculate the new reserve amount for the output token based on k
-        // new_y = k / new_x
-        // Note: Integer division truncates, favoring the pool slightly.
-        let new_reserve_out = constant_product.checked_div(new_reserve_in).ok_or(SwapError::CalculationOverflow)?;
+            emit!(SwapEvent {
+                pool: pool.key(),
+                user: ctx.accounts.user_authority.key(),
+                amount_in: amount,
+                amount_out,
+                fee_amount,
+                source_mint: mint_in.key(),
+                destination_mint: mint_out.key()
+            });
 
-        // Calculate the amount of output tokens to send to the user
-        // amount_out = y - new_y
-        let amount_out_u128 = reserve_out_u128.checked_sub(new_reserve_out).ok_or(SwapError::CalculationOverflow)?;
+            amount = amount_out;
+            expected_mint_in = mint_out.key();
+        }
 
-        // Convert amount_out back to u64
-        let amount_out = amount_out_u128 as u64;
+        // The route must end in the user's destination token
+        if expected_mint_in != ctx.accounts.user_destination_token_account.mint {
+            return err!(SwapError::InvalidRoute);
+        }
 
         // --- Slippage Check ---
-        // Ensure the calculated amount_out meets the user's minimum requirement
-        if amount_out < min_amount_out {
+        if amount < min_final_out {
             return err!(SwapError::SlippageExceeded);
         }
 
-        let price_impact_bs = amount_out_u128
-        .checked_mul(10000)
-        .ok_or(SwapError::CalculationOverflow)?
-        .checked_div(reserve_out_u128)
-        .ok_or(SwapError::CalculationOverflow)?;
+        Ok(())
+    }
 
-        const MAX_PRICE_IMPACT_BPS: u128 = 1000;
-        if price_impact_bs > MAX_PRICE_IMPACT_BPS {
-            return err!(SwapError::ExcessivePriceImpact);
+    /// Sends `amount_a_out` and `amount_b_out` from the pool vaults to the receiver's token accounts,
+    /// then calls `flash_swap_callback` on `receiver_program` with `callback_data` and every remaining
+    /// account passed through. By the time the callback returns the vaults must hold enough to keep
+    /// the constant product, with the pool's fee charged on whatever was paid back, just like a swap.
+    /// The pool stays locked while the callback runs.
+    pub fn flash_swap<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FlashSwap<'info>>,
+        amount_a_out: u64,
+        amount_b_out: u64,
+        callback_data: Vec<u8>,
+    ) -> Result<()> {
+        ctx.accounts.pool.require_not_paused(POOL_STATUS_SWAPS_PAUSED)?;
+        ctx.accounts.pool.require_unlocked()?;
+
+        // The repayment check is the constant product, so other curves cannot be flash swapped
+        if ctx.accounts.pool.curve_kind != CurveKind::ConstantProduct {
+            return err!(SwapError::UnsupportedCurve);
+        }
+        if amount_a_out == 0 && amount_b_out == 0 {
+            return err!(SwapError::ZeroAmount);
         }
 
-        // --- Perform Transfers via CPI ---
+        // --- Get Reserves ---
+        ctx.accounts.token_a_vault.reload()?;
+        ctx.accounts.token_b_vault.reload()?;
+        let (reserve_a, reserve_b) = ctx.accounts.pool.available_reserves(
+            ctx.accounts.token_a_vault.amount,
+            ctx.accounts.token_b_vault.amount,
+        )?;
+        if reserve_a == 0 || reserve_b == 0 {
+            return err!(SwapError::PoolIsEmpty);
+        }
+        if amount_a_out >= reserve_a || amount_b_out >= reserve_b {
+            return err!(SwapError::InsufficientReserves);
+        }
 
-        // 1. Transfer IN: User -> Pool Source Vault
-        let transfer_in_accounts = TransferChecked {
-            from: ctx.accounts.user_source_token_account.to_account_info(), // User's source ATA
-            mint: ctx.accounts.source_mint.to_account_info(), // Mint of the token being sent in
-            to: source_vault_account.to_account_info(), // Pool's vault for receiving the token
-            authority: ctx.accounts.user_authority.to_account_info(), // User signing the transaction
-        };
-        let transfer_in_cpi = CpiContext::new(
-            ctx.accounts.token_program.to_account_info(), // Target program (Token Program)
-            transfer_in_accounts, // Accounts required by transfer_checked
-        );
-        // Execute the CPI
-        transfer_checked(transfer_in_cpi, amount_in, source_mint_decimals)?;
+        // --- Update Price Oracle ---
+        // Accumulate the pre-trade price for the time elapsed since the last update
+        ctx.accounts.pool.update_price_accumulators(reserve_a, reserve_b)?;
+        ctx.accounts.observations.write(&ctx.accounts.pool, reserve_a, reserve_b)?;
 
+        // --- Lock the Pool ---
+        // Persisted before the callback so a nested instruction on this pool sees the flag
+        let fee_bps = ctx.accounts.pool.fee_bps;
+        ctx.accounts.pool.locked = true;
+        ctx.accounts.pool.exit(ctx.program_id)?;
 
-        // 2. Transfer OUT: Pool Destination Vault -> User
-        // Define the PDA signer seeds for the pool authority
-        // Use the bump stored in the pool account state
+        // --- Transfer OUT: Pool Vaults -> Receiver ---
+        let pool = &ctx.accounts.pool;
         let pool_bump_slice = &[pool.bump];
         let pool_signer_seeds: &[&[u8]] = &[
             b"pool",
@@ -218,37 +663,200 @@ pub mod web3_for_dummies {
             pool.token_b_mint.as_ref(),
             pool_bump_slice
         ];
-        // Add another layer of &[&[u8]] for the signer seeds argument
-        let signer = &[&pool_signer_seeds[..]];
+        let signer = &[pool_signer_seeds];
+        let legs = [
+            (&ctx.accounts.token_a_vault, &ctx.accounts.token_a_mint, &ctx.accounts.receiver_token_a_account, amount_a_out),
+            (&ctx.accounts.token_b_vault, &ctx.accounts.token_b_mint, &ctx.accounts.receiver_token_b_account, amount_b_out),
+        ];
+        for (vault, mint, destination, amount) in legs {
+            if amount == 0 {
+                continue;
+            }
+            let transfer_out_accounts = TransferChecked {
+                from: vault.to_account_info(),
+                mint: mint.to_account_info(),
+                to: destination.to_account_info(),
+                authority: ctx.accounts.pool_authority.to_account_info(),
+            };
+            let transfer_out_cpi = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                transfer_out_accounts,
+                signer,
+            );
+            transfer_checked(transfer_out_cpi, amount, mint.decimals)?;
+        }
+
+        // --- Receiver Callback ---
+        let callback = FlashSwapCallback {
+            pool: ctx.accounts.pool.key(),
+            initiator: ctx.accounts.user_authority.key(),
+            amount_a_out,
+            amount_b_out,
+            fee_bps,
+            data: callback_data,
+        };
+        let mut data = FLASH_SWAP_CALLBACK_DISCRIMINATOR.to_vec();
+        callback.serialize(&mut data)?;
+        let accounts = ctx.remaining_accounts
+            .iter()
+            .map(|account| if account.is_writable {
+                AccountMeta::new(account.key(), account.is_signer)
+            } else {
+                AccountMeta::new_readonly(account.key(), account.is_signer)
+            })
+            .collect();
+        let instruction = Instruction {
+            program_id: ctx.accounts.receiver_program.key(),
+            accounts,
+            data,
+        };
+        let mut account_infos = ctx.remaining_accounts.to_vec();
+        account_infos.push(ctx.accounts.receiver_program.to_account_info());
+        invoke(&instruction, &account_infos)?;
+
+        // --- Repayment Check ---
+        // Nested instructions may have executed admin changes on the pool, pick them up
+        ctx.accounts.pool.reload()?;
+        ctx.accounts.token_a_vault.reload()?;
+        ctx.accounts.token_b_vault.reload()?;
+        let pool = &mut ctx.accounts.pool;
+        let balance_a = ctx.accounts.token_a_vault.amount.saturating_sub(pool.protocol_fees_owed_a);
+        let balance_b = ctx.accounts.token_b_vault.amount.saturating_sub(pool.protocol_fees_owed_b);
+        let (leg_a, leg_b) = check_flash_swap_product(
+            (reserve_a, reserve_b),
+            (amount_a_out, amount_b_out),
+            (balance_a, balance_b),
+            fee_bps,
+        )?;
+
+        // --- Accrue Protocol Fee ---
+        let protocol_fee_a = calculate_protocol_fee(leg_a.fee_amount, pool.protocol_fee_share_bps)?;
+        let protocol_fee_b = calculate_protocol_fee(leg_b.fee_amount, pool.protocol_fee_share_bps)?;
+        pool.protocol_fees_owed_a = pool.protocol_fees_owed_a.checked_add(protocol_fee_a).ok_or(SwapError::CalculationOverflow)?;
+        pool.protocol_fees_owed_b = pool.protocol_fees_owed_b.checked_add(protocol_fee_b).ok_or(SwapError::CalculationOverflow)?;
+        pool.locked = false;
+
+        emit!(FlashSwapEvent {
+            pool: pool.key(),
+            user: ctx.accounts.user_authority.key(),
+            receiver_program: ctx.accounts.receiver_program.key(),
+            amount_a_out,
+            amount_b_out,
+            amount_a_in: leg_a.amount_in,
+            amount_b_in: leg_b.amount_in,
+        });
+
+        Ok(())
+    }
+
+    /// Lends `amount` of one of the pool's tokens out of its vault for the rest of the transaction.
+    /// A `flash_repay` for the same pool and vault has to follow later in the same transaction; it pays
+    /// back the principal plus the pool's fee, which stays in the vault for the LPs. The pool is
+    /// locked until then.
+    pub fn flash_borrow(ctx: Context<FlashLoan>, amount: u64, token: PoolToken) -> Result<()> {
+        // A borrow made through CPI, e.g. from a flash swap callback, is invisible to the introspection below
+        require_top_level()?;
+
+        let pool = &ctx.accounts.pool;
+        pool.require_not_paused(POOL_STATUS_SWAPS_PAUSED)?;
+        pool.require_unlocked()?;
+
+        if amount == 0 {
+            return err!(SwapError::ZeroAmount);
+        }
+        let (expected_vault, owed) = match token {
+            PoolToken::A => (pool.token_a_vault, pool.protocol_fees_owed_a),
+            PoolToken::B => (pool.token_b_vault, pool.protocol_fees_owed_b),
+        };
+        if ctx.accounts.vault.key() != expected_vault {
+            return err!(SwapError::InvalidVault);
+        }
+        // The pool cannot lend out its whole reserve
+        let reserve = ctx.accounts.vault.amount.checked_sub(owed).ok_or(SwapError::CalculationOverflow)?;
+        if amount >= reserve {
+            return err!(SwapError::InsufficientReserves);
+        }
+
+        // --- Repayment Introspection ---
+        require_flash_repay(&ctx.accounts.instructions, ctx.accounts.pool.key(), ctx.accounts.vault.key())?;
 
+        // --- Record the Loan ---
+        // fee = ceil(amount * fee_bps / 10000)
+        let fee = (amount as u128 * ctx.accounts.pool.fee_bps as u128).div_ceil(BPS_DENOMINATOR) as u64;
+        let pool = &mut ctx.accounts.pool;
+        pool.flash_loan_owed = amount.checked_add(fee).ok_or(SwapError::CalculationOverflow)?;
+        pool.flash_loan_vault = expected_vault;
+        pool.locked = true;
+        match token {
+            PoolToken::A => pool.flash_loan_volume_a = pool.flash_loan_volume_a.checked_add(amount as u128).ok_or(SwapError::CalculationOverflow)?,
+            PoolToken::B => pool.flash_loan_volume_b = pool.flash_loan_volume_b.checked_add(amount as u128).ok_or(SwapError::CalculationOverflow)?,
+        }
 
+        // --- Transfer OUT: Pool Vault -> User ---
+        let pool = &ctx.accounts.pool;
+        let pool_bump_slice = &[pool.bump];
+        let pool_signer_seeds: &[&[u8]] = &[
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool_bump_slice
+        ];
+        let signer = &[pool_signer_seeds];
         let transfer_out_accounts = TransferChecked {
-            from: dest_vault_account.to_account_info(), // Pool's vault sending the token
-            mint: ctx.accounts.destination_mint.to_account_info(), // Mint of the token being sent out
-            to: ctx.accounts.user_destination_token_account.to_account_info(), // User's destination ATA
-            authority: ctx.accounts.pool_authority.to_account_info(), // The pool's PDA authority
+            from: ctx.accounts.vault.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.user_token_account.to_account_info(),
+            authority: ctx.accounts.pool_authority.to_account_info(),
         };
-        // Create CPI context *with signer* because the authority is a PDA
         let transfer_out_cpi = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(), // Target program (Token Program)
-            transfer_out_accounts, // Accounts required by transfer_checked
-            signer, // Pass the &[&[&[u8]]] signer seeds
+            ctx.accounts.token_program.to_account_info(),
+            transfer_out_accounts,
+            signer,
         );
-        // Execute the CPI
-        transfer_checked(transfer_out_cpi, amount_out, ctx.accounts.destination_mint.decimals)?;
+        transfer_checked(transfer_out_cpi, amount, ctx.accounts.mint.decimals)?;
 
-        // --- Emit Event ---
-        // Log the details of the swap event
-        emit!(SwapEvent {
-            pool: ctx.accounts.pool.key(),
+        emit!(FlashLoanEvent {
+            pool: pool.key(),
             user: ctx.accounts.user_authority.key(),
-            amount_in,
-            amount_out,
-            source_mint: ctx.accounts.source_mint.key(),
-            destination_mint: ctx.accounts.destination_mint.key()
+            mint: ctx.accounts.mint.key(),
+            amount,
+            fee_amount: fee,
         });
 
-        Ok(()) // Indicate successful execution
+        Ok(())
+    }
+
+    /// Pays back the outstanding flash loan of the pool, principal plus fee, into the vault it
+    /// came from and unlocks the pool. Like `flash_borrow`, this must be a top-level instruction.
+    pub fn flash_repay(ctx: Context<FlashLoan>) -> Result<()> {
+        let owed = ctx.accounts.pool.flash_loan_owed;
+        if owed == 0 {
+            return err!(SwapError::NoFlashLoan);
+        }
+        if ctx.accounts.vault.key() != ctx.accounts.pool.flash_loan_vault {
+            return err!(SwapError::InvalidVault);
+        }
+        // A repay made through CPI is invisible to the introspection in `flash_borrow`
+        require_top_level()?;
+
+        // --- Transfer IN: User -> Pool Vault ---
+        let transfer_in_accounts = TransferChecked {
+            from: ctx.accounts.user_token_account.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.vault.to_account_info(),
+            authority: ctx.accounts.user_authority.to_account_info(),
+        };
+        let transfer_in_cpi = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_in_accounts,
+        );
+        transfer_checked(transfer_in_cpi, owed, ctx.accounts.mint.decimals)?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.flash_loan_owed = 0;
+        pool.flash_loan_vault = Pubkey::default();
+        pool.locked = false;
+        Ok(())
     }
 
     /// A simple example instruction to transfer tokens between two accounts.
@@ -278,22 +886,101 @@ pub mod web3_for_dummies {
         Ok(()) // Indicate successful execution
     }
 
-    pub fn add_liquidity(ctx: Context<AddLiquidity>, amount_a: u64, amount_b: u64) -> Result<()> {
+    /// Deposits both tokens into the pool vaults and mints LP shares to the depositor.
+    /// The first deposit mints sqrt(amount_a * amount_b) shares, of which `MINIMUM_LIQUIDITY` stay
+    /// locked in the pool; later deposits mint the smaller of the two proportional shares of the
+    /// current LP supply. Fails if fewer than `min_lp_amount` shares would be minted.
+    /// An optional `deadline_unix_ts` rejects the deposit once the cluster clock has passed it.
+    pub fn add_liquidity(
+        ctx: Context<AddLiquidity>,
+        amount_a: u64,
+        amount_b: u64,
+        min_lp_amount: u64,
+        deadline_unix_ts: Option<i64>,
+    ) -> Result<()> {
+        check_deadline(deadline_unix_ts)?;
+        ctx.accounts.pool.require_not_paused(POOL_STATUS_DEPOSITS_PAUSED)?;
+        ctx.accounts.pool.require_unlocked()?;
+
         if amount_a == 0 || amount_b == 0 {
             return err!(SwapError::ZeroAmount);
         }
     
+        // Reserves are read before the deposit lands in the vaults
+        let (reserve_a_before, reserve_b_before) = ctx.accounts.pool.available_reserves(
+            ctx.accounts.token_a_vault.amount,
+            ctx.accounts.token_b_vault.amount,
+        )?;
+
+        // --- Update Price Oracle ---
+        // Accumulate the pre-deposit price for the time elapsed since the last update
+        ctx.accounts.pool.update_price_accumulators(reserve_a_before, reserve_b_before)?;
+        ctx.accounts.observations.write(&ctx.accounts.pool, reserve_a_before, reserve_b_before)?;
+
         let pool = &ctx.accounts.pool;
+        let now = Clock::get()?.unix_timestamp;
+
+        // Only the pool creator may add liquidity to a liquidity bootstrapping pool until its sale ends
+        if pool.curve_kind == CurveKind::LiquidityBootstrapping
+            && now < pool.sale_end_ts
+            && ctx.accounts.user_authority.key() != pool.creator
+        {
+            return err!(SwapError::Unauthorized);
+        }
+
+        // --- LP Share Calculation ---
+        let lp_supply = pool.lp_supply(ctx.accounts.lp_mint.supply)?;
+
+        let shares = pool.curve(now).deposit_shares(
+            amount_a,
+            amount_b,
+            reserve_a_before,
+            reserve_b_before,
+            lp_supply,
+        )?;
+        // The first deposit leaves MINIMUM_LIQUIDITY of its shares locked in the pool
+        let locked_lp_amount = if lp_supply == 0 { MINIMUM_LIQUIDITY } else { 0 };
+        let lp_amount = shares.saturating_sub(locked_lp_amount);
+        if lp_amount == 0 {
+            return err!(SwapError::InsufficientLiquidityMinted);
+        }
+
+        // --- Slippage Check ---
+        if lp_amount < min_lp_amount {
+            return err!(SwapError::SlippageExceeded);
+        }
     
-        // Transfer token A
-        let transfer_a_accounts = TransferChecked {
-            from: ctx.accounts.user_token_a_account.to_account_info(),
-            mint: ctx.accounts.token_a_mint.to_account_info(),
-            to: ctx.accounts.token_a_vault.to_account_info(),
-            authority: ctx.accounts.user_authority.to_account_info(),
-        };
-        let transfer_a_cpi = CpiContext::new(
-            ctx.accounts.token_program.to_account_info(), 
+        // --- Deposit Ratio Check ---
+        // Only checked once the pool has liquidity, and against the reserves before the deposit.
+        // Adding the same fraction of both reserves leaves the price where it was on every curve,
+        // so this holds for weighted and liquidity bootstrapping pools whatever their current weights.
+        if reserve_a_before > 0 && reserve_b_before > 0 {
+            let expected_b = (amount_a as u128)
+                .checked_mul(reserve_b_before as u128)
+                .ok_or(SwapError::CalculationOverflow)?
+                .checked_div(reserve_a_before as u128)
+                .ok_or(SwapError::CalculationOverflow)?;
+
+            // Allow the pool's deposit-ratio tolerance as slippage on the ratio
+            let tolerance_bps = pool.deposit_ratio_tolerance_bps as u128;
+            let min_expected_b = expected_b.saturating_mul(BPS_DENOMINATOR - tolerance_bps) / BPS_DENOMINATOR;
+            let max_expected_b = expected_b.saturating_mul(BPS_DENOMINATOR + tolerance_bps) / BPS_DENOMINATOR;
+
+            let amount_b_u128 = amount_b as u128;
+            if amount_b_u128 < min_expected_b || amount_b_u128 > max_expected_b {
+                return err!(SwapError::DisproportionateLiquidity);
+            }
+        }
+
+        // Transfer token A
+        let transfer_a_accounts = TransferChecked {
+            from: ctx.accounts.user_token_a_account.to_account_info(),
+            mint: ctx.accounts.token_a_mint.to_account_info(),
+            to: ctx.accounts.token_a_vault.to_account_info(),
+            authority: ctx.accounts.user_authority.to_account_info(),
+        };
+        let transfer_a_cpi = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(), 
             transfer_a_accounts
         );
         transfer_checked(transfer_a_cpi, amount_a, ctx.accounts.token_a_mint.decimals)?;
@@ -311,96 +998,3823 @@ pub mod web3_for_dummies {
         );
         transfer_checked(transfer_b_cpi, amount_b, ctx.accounts.token_b_mint.decimals)?;
     
-        // Check for proportional deposits if pool already has liquidity
-        ctx.accounts.token_a_vault.reload()?;
-        ctx.accounts.token_b_vault.reload()?;
-        let reserve_a = ctx.accounts.token_a_vault.amount;
-        let reserve_b = ctx.accounts.token_b_vault.amount;
-    
-        // Only check proportions if we already have liquidity
-        let too_small: bool;
-        let too_large: bool;
-        
-        if reserve_a > 0 && reserve_b > 0 {
-            let expected_b = (amount_a as u128)
-                .checked_mul(reserve_b as u128)
-                .ok_or(SwapError::CalculationOverflow)?
-                .checked_div(reserve_a as u128)
-                .ok_or(SwapError::CalculationOverflow)?;
-                    
-            // Allow 1% slippage on the ratio
-            let min_expected_b = expected_b.saturating_mul(99).checked_div(100).unwrap_or(0);
-            let max_expected_b = expected_b.saturating_mul(101).checked_div(100).unwrap_or(u128::MAX);
+        // Mint LP shares to the depositor, signed by the pool authority PDA
+        let pool_bump_slice = &[pool.bump];
+        let pool_signer_seeds: &[&[u8]] = &[
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool_bump_slice
+        ];
+        let signer = &[pool_signer_seeds];
 
-            let amount_b_u128 = amount_b as u128;
-            
-            too_small = amount_b_u128 < min_expected_b;
-            too_large = amount_b_u128 > max_expected_b;
-            
-            if too_small || too_large {
-                return err!(SwapError::DisproportionateLiquidity);
-            }
-        }
+        let mint_lp_accounts = MintTo {
+            mint: ctx.accounts.lp_mint.to_account_info(),
+            to: ctx.accounts.user_lp_token_account.to_account_info(),
+            authority: ctx.accounts.pool_authority.to_account_info(),
+        };
+        let mint_lp_cpi = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            mint_lp_accounts,
+            signer,
+        );
+        mint_to(mint_lp_cpi, lp_amount)?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.locked_lp_amount = pool.locked_lp_amount.checked_add(locked_lp_amount).ok_or(SwapError::CalculationOverflow)?;
     
         emit!(LiquidityAddedEvent {
             pool: pool.key(),
             user: ctx.accounts.user_authority.key(),
             amount_a,
             amount_b,
+            lp_amount,
         });
     
         Ok(())
     }
-}
 
+    /// Burns LP shares and returns the pro-rata share of both reserves to the user.
+    /// Requires the amount of LP shares to burn and the minimum amounts of each token expected out (slippage protection).
+    /// An optional `deadline_unix_ts` rejects the withdrawal once the cluster clock has passed it.
+    pub fn remove_liquidity(
+        ctx: Context<RemoveLiquidity>,
+        lp_amount: u64,
+        min_a: u64,
+        min_b: u64,
+        deadline_unix_ts: Option<i64>,
+    ) -> Result<()> {
+        check_deadline(deadline_unix_ts)?;
+        ctx.accounts.pool.require_not_paused(POOL_STATUS_WITHDRAWALS_PAUSED)?;
+        ctx.accounts.pool.require_unlocked()?;
 
-// --- Account Data Structures ---
+        if lp_amount == 0 {
+            return err!(SwapError::ZeroAmount);
+        }
 
-/// Stores the state of a single liquidity pool.
-#[account]
-#[derive(Default)] // Allows initializing with default values (zeros, null pubkeys)
-pub struct LiquidityPool {
-    /// The mint address of the first token (Token A).
-    pub token_a_mint: Pubkey,
-    /// The mint address of the second token (Token B).
-    pub token_b_mint: Pubkey,
-    /// The address of the pool's vault (ATA) for Token A.
-    pub token_a_vault: Pubkey,
-    /// The address of the pool's vault (ATA) for Token B.
-    pub token_b_vault: Pubkey,
-    /// The bump seed used for the pool's PDA.
-    pub bump: u8,
+        // --- Get Reserves and Supply ---
+        ctx.accounts.token_a_vault.reload()?;
+        ctx.accounts.token_b_vault.reload()?;
+        let (reserve_a, reserve_b) = ctx.accounts.pool.available_reserves(
+            ctx.accounts.token_a_vault.amount,
+            ctx.accounts.token_b_vault.amount,
+        )?;
+
+        // --- Update Price Oracle ---
+        // Accumulate the pre-withdrawal price for the time elapsed since the last update
+        ctx.accounts.pool.update_price_accumulators(reserve_a, reserve_b)?;
+        ctx.accounts.observations.write(&ctx.accounts.pool, reserve_a, reserve_b)?;
+
+        let pool = &ctx.accounts.pool;
+        let lp_supply = pool.lp_supply(ctx.accounts.lp_mint.supply)?;
+
+        if lp_supply == 0 {
+            return err!(SwapError::PoolIsEmpty);
+        }
+
+        // --- Payout ---
+        // The curve decides what the burnt shares are worth, rounding in favor of the pool
+        let (amount_a, amount_b) = pool
+            .curve(Clock::get()?.unix_timestamp)
+            .withdraw_amounts(lp_amount, reserve_a, reserve_b, lp_supply)?;
+
+        if amount_a == 0 || amount_b == 0 {
+            return err!(SwapError::ZeroAmount);
+        }
+
+        // --- Slippage Check ---
+        if amount_a < min_a || amount_b < min_b {
+            return err!(SwapError::SlippageExceeded);
+        }
+
+        // --- Burn LP Shares ---
+        // The user owns the LP token account, so the user signs the burn
+        let burn_accounts = Burn {
+            mint: ctx.accounts.lp_mint.to_account_info(),
+            from: ctx.accounts.user_lp_token_account.to_account_info(),
+            authority: ctx.accounts.user_authority.to_account_info(),
+        };
+        let burn_cpi = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            burn_accounts,
+        );
+        burn(burn_cpi, lp_amount)?;
+
+        // --- Transfer OUT: Pool Vaults -> User ---
+        let pool_bump_slice = &[pool.bump];
+        let pool_signer_seeds: &[&[u8]] = &[
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool_bump_slice
+        ];
+        let signer = &[pool_signer_seeds];
+
+        let transfer_a_accounts = TransferChecked {
+            from: ctx.accounts.token_a_vault.to_account_info(),
+            mint: ctx.accounts.token_a_mint.to_account_info(),
+            to: ctx.accounts.user_token_a_account.to_account_info(),
+            authority: ctx.accounts.pool_authority.to_account_info(),
+        };
+        let transfer_a_cpi = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_a_accounts,
+            signer,
+        );
+        transfer_checked(transfer_a_cpi, amount_a, ctx.accounts.token_a_mint.decimals)?;
+
+        let transfer_b_accounts = TransferChecked {
+            from: ctx.accounts.token_b_vault.to_account_info(),
+            mint: ctx.accounts.token_b_mint.to_account_info(),
+            to: ctx.accounts.user_token_b_account.to_account_info(),
+            authority: ctx.accounts.pool_authority.to_account_info(),
+        };
+        let transfer_b_cpi = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_b_accounts,
+            signer,
+        );
+        transfer_checked(transfer_b_cpi, amount_b, ctx.accounts.token_b_mint.decimals)?;
+
+        emit!(LiquidityRemovedEvent {
+            pool: pool.key(),
+            user: ctx.accounts.user_authority.key(),
+            amount_a,
+            amount_b,
+            lp_amount,
+        });
+
+        Ok(())
+    }
+
+    /// Emergency pause: sets additional pause flags on the pool without waiting for the timelock.
+    /// Only the pool admin can call this. Clearing flags goes through `queue_change`.
+    /// `status` is a combination of the `POOL_STATUS_*` flags.
+    pub fn set_pool_status(ctx: Context<SetPoolStatus>, status: u8) -> Result<()> {
+        if status & !POOL_STATUS_ALL_PAUSED != 0 {
+            return err!(SwapError::InvalidPoolStatus);
+        }
+        if status & ctx.accounts.pool.status != ctx.accounts.pool.status {
+            return err!(SwapError::TimelockRequired);
+        }
+
+        let pool = &mut ctx.accounts.pool;
+        let old_status = pool.status;
+        pool.status = status;
+
+        emit!(PoolStatusChangedEvent {
+            pool: pool.key(),
+            admin: ctx.accounts.admin.key(),
+            old_status,
+            new_status: status,
+        });
+
+        Ok(())
+    }
+
+    /// Moves a StableSwap pool's `amp` linearly towards `target_amp`, reaching it at `ramp_end_ts`.
+    /// Only the pool admin can call this.
+    pub fn ramp_amp(ctx: Context<ManagePool>, target_amp: u64, ramp_end_ts: i64) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let now = Clock::get()?.unix_timestamp;
+        if pool.curve_kind != CurveKind::StableSwap {
+            return err!(SwapError::InvalidCurveParams);
+        }
+        if now < pool.amp_ramp_end_ts || ramp_end_ts < now.saturating_add(MIN_AMP_RAMP_DURATION_SECS) {
+            return err!(SwapError::InvalidAmpRamp);
+        }
+
+        // Bound both the target and how far a single ramp may move amp
+        let amp_initial = pool.current_amp(now);
+        if !(MIN_AMP..=MAX_AMP).contains(&target_amp)
+            || target_amp > amp_initial.saturating_mul(MAX_AMP_CHANGE)
+            || amp_initial > target_amp.saturating_mul(MAX_AMP_CHANGE)
+        {
+            return err!(SwapError::InvalidAmpRamp);
+        }
+
+        pool.amp_initial = amp_initial;
+        pool.amp_target = target_amp;
+        pool.amp_ramp_start_ts = now;
+        pool.amp_ramp_end_ts = ramp_end_ts;
+
+        emit!(AmpRampStartedEvent {
+            pool: pool.key(),
+            amp_initial,
+            amp_target: target_amp,
+            ramp_start_ts: now,
+            ramp_end_ts,
+        });
+
+        Ok(())
+    }
+
+    /// Freezes a StableSwap pool's `amp` at its current value. Only the pool admin can call this.
+    pub fn stop_ramp_amp(ctx: Context<ManagePool>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let now = Clock::get()?.unix_timestamp;
+        if pool.curve_kind != CurveKind::StableSwap {
+            return err!(SwapError::InvalidCurveParams);
+        }
+
+        let amp = pool.current_amp(now);
+        pool.amp_initial = amp;
+        pool.amp_target = amp;
+        pool.amp_ramp_start_ts = now;
+        pool.amp_ramp_end_ts = now;
+
+        emit!(AmpRampStoppedEvent {
+            pool: pool.key(),
+            amp,
+        });
+
+        Ok(())
+    }
+
+    /// Ends a liquidity bootstrapping pool's sale early, freezing its weights at their current
+    /// values and opening deposits to everyone. Only the pool admin can call this.
+    pub fn end_sale(ctx: Context<ManagePool>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let now = Clock::get()?.unix_timestamp;
+        if pool.curve_kind != CurveKind::LiquidityBootstrapping {
+            return err!(SwapError::InvalidCurveParams);
+        }
+        if now >= pool.sale_end_ts {
+            return err!(SwapError::SaleEnded);
+        }
+
+        let weights = pool.current_weights(now);
+        pool.start_weights = weights;
+        pool.end_weights = weights;
+        pool.sale_start_ts = pool.sale_start_ts.min(now);
+        pool.sale_end_ts = now;
+
+        emit!(SaleEndedEvent {
+            pool: pool.key(),
+            weight_a_bps: weights[0],
+            weight_b_bps: weights[1],
+            ended_at: now,
+        });
+
+        Ok(())
+    }
+
+    /// Queues a pool parameter change that can be executed once `change_delay_secs` from the
+    /// program config has passed. Only the pool admin can call this, except for protocol fee
+    /// share changes, which only the protocol admin can queue.
+    pub fn queue_change(ctx: Context<QueueChange>, change: PoolParamChange) -> Result<()> {
+        if ctx.accounts.admin.key() != change.admin(&ctx.accounts.pool, &ctx.accounts.config) {
+            return err!(SwapError::Unauthorized);
+        }
+        change.validate(&ctx.accounts.config)?;
+
+        let eta = Clock::get()?
+            .unix_timestamp
+            .checked_add(ctx.accounts.config.change_delay_secs as i64)
+            .ok_or(SwapError::CalculationOverflow)?;
+
+        let pending_change = &mut ctx.accounts.pending_change;
+        pending_change.pool = ctx.accounts.pool.key();
+        pending_change.proposer = ctx.accounts.admin.key();
+        pending_change.change = change;
+        pending_change.eta = eta;
+        pending_change.bump = ctx.bumps.pending_change;
+        pending_change.pool_status = ctx.accounts.pool.status;
+
+        emit!(ChangeQueuedEvent {
+            pool: pending_change.pool,
+            change,
+            eta,
+        });
+
+        Ok(())
+    }
+
+    /// Applies a queued pool parameter change once its `eta` has passed. Anyone can call this;
+    /// the pending change account is closed back to the admin who queued it.
+    pub fn execute_change(ctx: Context<ExecuteChange>) -> Result<()> {
+        let pending_change = &ctx.accounts.pending_change;
+        if Clock::get()?.unix_timestamp < pending_change.eta {
+            return err!(SwapError::TimelockNotElapsed);
+        }
+
+        let change = pending_change.change;
+        let pool = &mut ctx.accounts.pool;
+        match change {
+            PoolParamChange::FeeBps(fee_bps) => pool.fee_bps = fee_bps,
+            PoolParamChange::ProtocolFeeShareBps(share_bps) => pool.protocol_fee_share_bps = share_bps,
+            PoolParamChange::MaxPriceImpactBps(impact_bps) => pool.max_price_impact_bps = impact_bps,
+            PoolParamChange::Status(status) => {
+                // Only `set_pool_status` changes the status meanwhile, and it only adds flags
+                if pool.status != pending_change.pool_status {
+                    return err!(SwapError::StaleStatusChange);
+                }
+                pool.status = status;
+            }
+        }
+
+        emit!(ChangeExecutedEvent {
+            pool: pool.key(),
+            change,
+        });
+
+        Ok(())
+    }
+
+    /// Drops a queued pool parameter change. Only the admin who may queue the change can call this.
+    pub fn cancel_change(ctx: Context<CancelChange>) -> Result<()> {
+        let change = ctx.accounts.pending_change.change;
+        if ctx.accounts.admin.key() != change.admin(&ctx.accounts.pool, &ctx.accounts.config) {
+            return err!(SwapError::Unauthorized);
+        }
+
+        emit!(ChangeCancelledEvent {
+            pool: ctx.accounts.pool.key(),
+            change: ctx.accounts.pending_change.change,
+        });
+
+        Ok(())
+    }
+
+    /// Starts a handover of the pool admin role to `new_admin`. Only the pool admin can call this.
+    /// The role only moves once `new_admin` signs `accept_admin`.
+    pub fn propose_admin(ctx: Context<ManagePool>, new_admin: Pubkey) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let target = pool.key();
+        propose_admin_transfer(target, pool.admin, &mut pool.pending_admin, new_admin)
+    }
+
+    /// Completes a pool admin handover. Must be signed by the proposed admin.
+    pub fn accept_admin(ctx: Context<AcceptPoolAdmin>) -> Result<()> {
+        let target = ctx.accounts.pool.key();
+        let pool: &mut LiquidityPool = &mut ctx.accounts.pool;
+        accept_admin_transfer(target, &mut pool.admin, &mut pool.pending_admin)
+    }
+
+    /// Withdraws a pending pool admin handover. Only the pool admin can call this.
+    pub fn cancel_admin_transfer(ctx: Context<ManagePool>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let target = pool.key();
+        cancel_pending_admin(target, pool.admin, &mut pool.pending_admin)
+    }
+
+    /// Sends the protocol fees owed by a pool from its vaults to the treasury set in the program config.
+    /// Anyone can call this, since the destination accounts must belong to the configured treasury.
+    pub fn collect_protocol_fees(ctx: Context<CollectProtocolFees>) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        pool.require_unlocked()?;
+        let amount_a = pool.protocol_fees_owed_a;
+        let amount_b = pool.protocol_fees_owed_b;
+
+        if amount_a == 0 && amount_b == 0 {
+            return err!(SwapError::ZeroAmount);
+        }
+
+        let pool_bump_slice = &[pool.bump];
+        let pool_signer_seeds: &[&[u8]] = &[
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool_bump_slice
+        ];
+        let signer = &[pool_signer_seeds];
+
+        if amount_a > 0 {
+            let transfer_a_accounts = TransferChecked {
+                from: ctx.accounts.token_a_vault.to_account_info(),
+                mint: ctx.accounts.token_a_mint.to_account_info(),
+                to: ctx.accounts.treasury_token_a_account.to_account_info(),
+                authority: ctx.accounts.pool_authority.to_account_info(),
+            };
+            let transfer_a_cpi = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                transfer_a_accounts,
+                signer,
+            );
+            transfer_checked(transfer_a_cpi, amount_a, ctx.accounts.token_a_mint.decimals)?;
+        }
+
+        if amount_b > 0 {
+            let transfer_b_accounts = TransferChecked {
+                from: ctx.accounts.token_b_vault.to_account_info(),
+                mint: ctx.accounts.token_b_mint.to_account_info(),
+                to: ctx.accounts.treasury_token_b_account.to_account_info(),
+                authority: ctx.accounts.pool_authority.to_account_info(),
+            };
+            let transfer_b_cpi = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                transfer_b_accounts,
+                signer,
+            );
+            transfer_checked(transfer_b_cpi, amount_b, ctx.accounts.token_b_mint.decimals)?;
+        }
+
+        let pool = &mut ctx.accounts.pool;
+        pool.protocol_fees_owed_a = 0;
+        pool.protocol_fees_owed_b = 0;
+
+        emit!(ProtocolFeesCollectedEvent {
+            pool: pool.key(),
+            treasury: ctx.accounts.config.treasury,
+            amount_a,
+            amount_b,
+        });
+
+        Ok(())
+    }
+
+    /// Creates a concentrated-liquidity pool and its vaults, priced at `initial_sqrt_price_x64`
+    /// (Q64.64 square root of the Token B per Token A price). Liquidity is provided through
+    /// positions over tick ranges aligned to `tick_spacing`. `fee_bps` follows the same rules
+    /// as in `initialize_pool`; all swap fees go to the positions the swap trades through.
+    pub fn initialize_concentrated_pool(
+        ctx: Context<InitializeConcentratedPool>,
+        fee_bps: Option<u16>,
+        tick_spacing: u16,
+        initial_sqrt_price_x64: u128,
+    ) -> Result<()> {
+        let config = &ctx.accounts.config;
+        if !config.pool_creation_enabled {
+            return err!(SwapError::PoolCreationDisabled);
+        }
+        let fee_bps = fee_bps.unwrap_or(config.default_fee_bps);
+        if fee_bps > config.max_fee_bps {
+            return err!(SwapError::FeeTooHigh);
+        }
+        if tick_spacing == 0 || tick_spacing > MAX_TICK_SPACING {
+            return err!(SwapError::InvalidTickRange);
+        }
+        let tick_current = tick_at_sqrt_price(initial_sqrt_price_x64)?;
+
+        // Same layout as `initialize_pool`: the pool PDA owns both vaults and signs for them
+        let pool = &mut ctx.accounts.pool;
+        pool.token_a_mint = ctx.accounts.token_a_mint.key();
+        pool.token_b_mint = ctx.accounts.token_b_mint.key();
+        pool.token_a_vault = ctx.accounts.token_a_vault.key();
+        pool.token_b_vault = ctx.accounts.token_b_vault.key();
+        pool.bump = ctx.bumps.pool;
+        pool.fee_bps = fee_bps;
+        pool.tick_spacing = tick_spacing;
+        pool.sqrt_price_x64 = initial_sqrt_price_x64;
+        pool.tick_current = tick_current;
+        pool.liquidity = 0;
+        pool.fee_growth_global_a_x64 = 0;
+        pool.fee_growth_global_b_x64 = 0;
+
+        Ok(())
+    }
+
+    /// Creates the tick array of a concentrated-liquidity pool starting at `start_tick_index`,
+    /// which must be a multiple of `tick_spacing * TICK_ARRAY_SIZE`. Anyone can pay for it.
+    pub fn initialize_tick_array(ctx: Context<InitializeTickArray>, start_tick_index: i32) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let span = pool.tick_spacing as i32 * TICK_ARRAY_SIZE as i32;
+        if start_tick_index != TickArray::start_index_for(start_tick_index, pool.tick_spacing)
+            || start_tick_index > MAX_TICK
+            || start_tick_index + span <= MIN_TICK
+        {
+            return err!(SwapError::InvalidTickArray);
+        }
+
+        let tick_array = &mut ctx.accounts.tick_array;
+        tick_array.pool = pool.key();
+        tick_array.start_tick_index = start_tick_index;
+        tick_array.ticks = vec![Tick::default(); TICK_ARRAY_SIZE];
+
+        Ok(())
+    }
+
+    /// Opens an empty position over [tick_lower, tick_upper) for the signer.
+    /// Both ticks must be multiples of the pool's tick spacing.
+    pub fn open_position(ctx: Context<OpenPosition>, tick_lower: i32, tick_upper: i32) -> Result<()> {
+        check_tick_range(tick_lower, tick_upper, ctx.accounts.pool.tick_spacing)?;
+
+        let position = &mut ctx.accounts.position;
+        position.pool = ctx.accounts.pool.key();
+        position.owner = ctx.accounts.owner.key();
+        position.tick_lower = tick_lower;
+        position.tick_upper = tick_upper;
+        position.liquidity = 0;
+        position.fee_growth_inside_last_a_x64 = 0;
+        position.fee_growth_inside_last_b_x64 = 0;
+        position.tokens_owed_a = 0;
+        position.tokens_owed_b = 0;
+        position.bump = ctx.bumps.position;
+
+        Ok(())
+    }
+
+    /// Adds `liquidity_delta` to a position, pulling the Token A and Token B it needs at the current price.
+    /// `amount_a_max` and `amount_b_max` cap what the owner pays (slippage protection).
+    pub fn increase_liquidity(
+        ctx: Context<ModifyPosition>,
+        liquidity_delta: u128,
+        amount_a_max: u64,
+        amount_b_max: u64,
+    ) -> Result<()> {
+        if liquidity_delta == 0 {
+            return err!(SwapError::ZeroAmount);
+        }
+        let delta = i128::try_from(liquidity_delta).map_err(|_| error!(SwapError::CalculationOverflow))?;
+
+        // Deposits round up, so the position never gets liquidity it did not pay for
+        let pool = &ctx.accounts.pool;
+        let position = &ctx.accounts.position;
+        let (amount_a, amount_b) = position_amounts(
+            pool.sqrt_price_x64,
+            pool.tick_current,
+            position.tick_lower,
+            position.tick_upper,
+            liquidity_delta,
+            true,
+        )?;
+        if amount_a > amount_a_max || amount_b > amount_b_max {
+            return err!(SwapError::SlippageExceeded);
+        }
+
+        let same_array = ctx.accounts.tick_array_lower.key() == ctx.accounts.tick_array_upper.key();
+        update_position(
+            &mut ctx.accounts.pool,
+            &mut ctx.accounts.position,
+            &mut ctx.accounts.tick_array_lower,
+            &mut ctx.accounts.tick_array_upper,
+            same_array,
+            delta,
+        )?;
+
+        if amount_a > 0 {
+            let transfer_a_accounts = TransferChecked {
+                from: ctx.accounts.owner_token_a_account.to_account_info(),
+                mint: ctx.accounts.token_a_mint.to_account_info(),
+                to: ctx.accounts.token_a_vault.to_account_info(),
+                authority: ctx.accounts.owner.to_account_info(),
+            };
+            let transfer_a_cpi = CpiContext::new(ctx.accounts.token_program.to_account_info(), transfer_a_accounts);
+            transfer_checked(transfer_a_cpi, amount_a, ctx.accounts.token_a_mint.decimals)?;
+        }
+        if amount_b > 0 {
+            let transfer_b_accounts = TransferChecked {
+                from: ctx.accounts.owner_token_b_account.to_account_info(),
+                mint: ctx.accounts.token_b_mint.to_account_info(),
+                to: ctx.accounts.token_b_vault.to_account_info(),
+                authority: ctx.accounts.owner.to_account_info(),
+            };
+            let transfer_b_cpi = CpiContext::new(ctx.accounts.token_program.to_account_info(), transfer_b_accounts);
+            transfer_checked(transfer_b_cpi, amount_b, ctx.accounts.token_b_mint.decimals)?;
+        }
+
+        emit!(PositionLiquidityChangedEvent {
+            pool: ctx.accounts.pool.key(),
+            position: ctx.accounts.position.key(),
+            owner: ctx.accounts.owner.key(),
+            liquidity_delta: delta,
+            amount_a,
+            amount_b,
+        });
+
+        Ok(())
+    }
+
+    /// Removes `liquidity_delta` from a position and pays out the Token A and Token B behind it.
+    /// `amount_a_min` and `amount_b_min` bound what the owner receives (slippage protection).
+    /// Fees stay in the position until `collect_fees`.
+    pub fn decrease_liquidity(
+        ctx: Context<ModifyPosition>,
+        liquidity_delta: u128,
+        amount_a_min: u64,
+        amount_b_min: u64,
+    ) -> Result<()> {
+        if liquidity_delta == 0 {
+            return err!(SwapError::ZeroAmount);
+        }
+        if liquidity_delta > ctx.accounts.position.liquidity {
+            return err!(SwapError::InsufficientPositionLiquidity);
+        }
+        let delta = -i128::try_from(liquidity_delta).map_err(|_| error!(SwapError::CalculationOverflow))?;
+
+        // Withdrawals round down, so the pool never pays out more than the position holds
+        let pool = &ctx.accounts.pool;
+        let position = &ctx.accounts.position;
+        let (amount_a, amount_b) = position_amounts(
+            pool.sqrt_price_x64,
+            pool.tick_current,
+            position.tick_lower,
+            position.tick_upper,
+            liquidity_delta,
+            false,
+        )?;
+        if amount_a < amount_a_min || amount_b < amount_b_min {
+            return err!(SwapError::SlippageExceeded);
+        }
+
+        let same_array = ctx.accounts.tick_array_lower.key() == ctx.accounts.tick_array_upper.key();
+        update_position(
+            &mut ctx.accounts.pool,
+            &mut ctx.accounts.position,
+            &mut ctx.accounts.tick_array_lower,
+            &mut ctx.accounts.tick_array_upper,
+            same_array,
+            delta,
+        )?;
+
+        pay_out_of_position(&ctx, amount_a, amount_b)?;
+
+        emit!(PositionLiquidityChangedEvent {
+            pool: ctx.accounts.pool.key(),
+            position: ctx.accounts.position.key(),
+            owner: ctx.accounts.owner.key(),
+            liquidity_delta: delta,
+            amount_a,
+            amount_b,
+        });
+
+        Ok(())
+    }
+
+    /// Pays out the swap fees a position has earned since it last collected.
+    pub fn collect_fees(ctx: Context<ModifyPosition>) -> Result<()> {
+        // A zero liquidity change only brings the position's fees up to date
+        let same_array = ctx.accounts.tick_array_lower.key() == ctx.accounts.tick_array_upper.key();
+        update_position(
+            &mut ctx.accounts.pool,
+            &mut ctx.accounts.position,
+            &mut ctx.accounts.tick_array_lower,
+            &mut ctx.accounts.tick_array_upper,
+            same_array,
+            0,
+        )?;
+
+        let position = &mut ctx.accounts.position;
+        let (amount_a, amount_b) = (position.tokens_owed_a, position.tokens_owed_b);
+        position.tokens_owed_a = 0;
+        position.tokens_owed_b = 0;
+
+        pay_out_of_position(&ctx, amount_a, amount_b)?;
+
+        emit!(PositionFeesCollectedEvent {
+            pool: ctx.accounts.pool.key(),
+            position: ctx.accounts.position.key(),
+            owner: ctx.accounts.owner.key(),
+            amount_a,
+            amount_b,
+        });
+
+        Ok(())
+    }
+
+    /// Swaps an exact input on a concentrated-liquidity pool, crossing every initialized tick on the way.
+    /// The tick arrays the swap may walk through are passed as `remaining_accounts`, starting with the
+    /// array holding the current tick and continuing in the swap direction.
+    /// Concentrated pools have no admin and no status flags, so `set_pool_status` cannot pause them.
+    pub fn swap_concentrated<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SwapConcentrated<'info>>,
+        amount_in: u64,
+        min_amount_out: u64,
+        deadline_unix_ts: Option<i64>,
+    ) -> Result<()> {
+        check_deadline(deadline_unix_ts)?;
+        if amount_in == 0 {
+            return err!(SwapError::ZeroAmount);
+        }
+
+        let pool = &mut ctx.accounts.pool;
+        let pool_key = pool.key();
+        let a_to_b = ctx.accounts.source_mint.key() == pool.token_a_mint;
+        let spacing = pool.tick_spacing;
+        let span = spacing as i32 * TICK_ARRAY_SIZE as i32;
+
+        // --- Tick Array Validation ---
+        // The arrays must be this pool's, start at the current tick and follow each other
+        // in the swap direction, which also rules out passing an array twice
+        let mut tick_arrays = ctx
+            .remaining_accounts
+            .iter()
+            .map(Account::<TickArray>::try_from)
+            .collect::<Result<Vec<_>>>()?;
+        let mut expected_start = TickArray::start_index_for(pool.tick_current, spacing);
+        for tick_array in &tick_arrays {
+            if tick_array.pool != pool_key || tick_array.start_tick_index != expected_start {
+                return err!(SwapError::InvalidTickArray);
+            }
+            expected_start += if a_to_b { -span } else { span };
+        }
+        if tick_arrays.is_empty() {
+            return err!(SwapError::InvalidTickArray);
+        }
+
+        // --- Swap Loop ---
+        let sqrt_price_limit_x64 = sqrt_price_at_tick(if a_to_b { MIN_TICK } else { MAX_TICK })?;
+        let mut amount_remaining = amount_in;
+        let mut amount_out: u64 = 0;
+        let mut fee_amount: u64 = 0;
+        let mut array_index = 0;
+
+        while amount_remaining > 0 && pool.sqrt_price_x64 != sqrt_price_limit_x64 {
+            let tick_array = tick_arrays.get(array_index).ok_or(SwapError::InvalidTickArray)?;
+
+            // Walk to the next initialized tick in this array, or to the array's edge
+            let next_tick = tick_array
+                .next_initialized_tick(pool.tick_current, spacing, a_to_b)
+                .unwrap_or(if a_to_b { tick_array.start_tick_index } else { tick_array.start_tick_index + span })
+                .clamp(MIN_TICK, MAX_TICK);
+            let sqrt_price_target_x64 = sqrt_price_at_tick(next_tick)?;
+
+            let step = compute_swap_step(
+                pool.sqrt_price_x64,
+                sqrt_price_target_x64,
+                pool.liquidity,
+                amount_remaining,
+                pool.fee_bps,
+            )?;
+            amount_remaining = step.amount_in
+                .checked_add(step.fee_amount)
+                .and_then(|step_in| amount_remaining.checked_sub(step_in))
+                .ok_or(SwapError::CalculationOverflow)?;
+            amount_out = amount_out.checked_add(step.amount_out).ok_or(SwapError::CalculationOverflow)?;
+            fee_amount = fee_amount.checked_add(step.fee_amount).ok_or(SwapError::CalculationOverflow)?;
+
+            // Fees accrue per unit of in-range liquidity, in the input token
+            if let Some(fee_growth) = ((step.fee_amount as u128) << 64).checked_div(pool.liquidity) {
+                if a_to_b {
+                    pool.fee_growth_global_a_x64 = pool.fee_growth_global_a_x64.wrapping_add(fee_growth);
+                } else {
+                    pool.fee_growth_global_b_x64 = pool.fee_growth_global_b_x64.wrapping_add(fee_growth);
+                }
+            }
+
+            if step.sqrt_price_next_x64 == sqrt_price_target_x64 {
+                // Crossing a tick moves the liquidity of every position bounded by it in or out of range.
+                // The array edge crossed upwards is the first tick of the next array.
+                let holder = tick_arrays
+                    .iter_mut()
+                    .find(|tick_array| (tick_array.start_tick_index..tick_array.start_tick_index + span).contains(&next_tick))
+                    .ok_or(SwapError::InvalidTickArray)?;
+                let tick = holder.tick_mut(next_tick, spacing)?;
+                if tick.initialized {
+                    let liquidity_net = tick.cross(pool.fee_growth_global_a_x64, pool.fee_growth_global_b_x64);
+                    let delta = if a_to_b { -liquidity_net } else { liquidity_net };
+                    pool.liquidity = add_liquidity_delta(pool.liquidity, delta)?;
+                }
+                pool.tick_current = if a_to_b { next_tick - 1 } else { next_tick };
+            } else if step.sqrt_price_next_x64 != pool.sqrt_price_x64 {
+                pool.tick_current = tick_at_sqrt_price(step.sqrt_price_next_x64)?;
+            }
+            pool.sqrt_price_x64 = step.sqrt_price_next_x64;
+
+            // Move on once the current tick has left this array
+            let start = tick_arrays[array_index].start_tick_index;
+            if pool.tick_current < start || pool.tick_current >= start + span {
+                array_index += 1;
+            }
+        }
+
+        // --- Slippage Check ---
+        if amount_out < min_amount_out {
+            return err!(SwapError::SlippageExceeded);
+        }
+
+        for tick_array in &tick_arrays {
+            tick_array.exit(ctx.program_id)?;
+        }
+
+        // Input left over at the price bound stays with the user
+        let amount_in_used = amount_in - amount_remaining;
+        let (vault_in, vault_out) = if a_to_b {
+            (&ctx.accounts.token_a_vault, &ctx.accounts.token_b_vault)
+        } else {
+            (&ctx.accounts.token_b_vault, &ctx.accounts.token_a_vault)
+        };
+
+        // 1. Transfer IN: User -> Pool Vault
+        let transfer_in_accounts = TransferChecked {
+            from: ctx.accounts.user_source_token_account.to_account_info(),
+            mint: ctx.accounts.source_mint.to_account_info(),
+            to: vault_in.to_account_info(),
+            authority: ctx.accounts.user_authority.to_account_info(),
+        };
+        let transfer_in_cpi = CpiContext::new(ctx.accounts.token_program.to_account_info(), transfer_in_accounts);
+        transfer_checked(transfer_in_cpi, amount_in_used, ctx.accounts.source_mint.decimals)?;
+
+        // 2. Transfer OUT: Pool Vault -> User, signed by the pool authority PDA
+        let pool = &ctx.accounts.pool;
+        let pool_bump_slice = &[pool.bump];
+        let pool_signer_seeds: &[&[u8]] = &[
+            b"cl_pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool_bump_slice
+        ];
+        let signer = &[pool_signer_seeds];
+        let transfer_out_accounts = TransferChecked {
+            from: vault_out.to_account_info(),
+            mint: ctx.accounts.destination_mint.to_account_info(),
+            to: ctx.accounts.user_destination_token_account.to_account_info(),
+            authority: ctx.accounts.pool_authority.to_account_info(),
+        };
+        let transfer_out_cpi = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_out_accounts,
+            signer,
+        );
+        transfer_checked(transfer_out_cpi, amount_out, ctx.accounts.destination_mint.decimals)?;
+
+        emit!(SwapEvent {
+            pool: pool_key,
+            user: ctx.accounts.user_authority.key(),
+            amount_in: amount_in_used,
+            amount_out,
+            fee_amount,
+            source_mint: ctx.accounts.source_mint.key(),
+            destination_mint: ctx.accounts.destination_mint.key()
+        });
+
+        Ok(())
+    }
+
+    /// Creates the liquidity mining farm of a pool with its first reward stream, emitting
+    /// `reward_per_second` of the reward token to the pool's LP shares staked between `start_ts`
+    /// and `end_ts`. Only the pool admin can call this. Further streams are added with
+    /// `add_reward_stream`, and every stream's vault is filled with `fund_farm`.
+    pub fn initialize_farm(ctx: Context<InitializeFarm>, reward_per_second: u64, start_ts: i64, end_ts: i64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let farm = &mut ctx.accounts.farm;
+        farm.pool = ctx.accounts.pool.key();
+        farm.lp_mint = ctx.accounts.lp_mint.key();
+        farm.staked_lp_vault = ctx.accounts.staked_lp_vault.key();
+        farm.bump = ctx.bumps.farm;
+        farm.total_staked = 0;
+        farm.reward_stream_count = 0;
+        farm.add_reward_stream(
+            ctx.accounts.reward_mint.key(),
+            ctx.accounts.reward_vault.key(),
+            reward_per_second,
+            start_ts,
+            end_ts,
+            now,
+        )?;
+
+        emit!(RewardStreamAddedEvent {
+            farm: farm.key(),
+            reward_mint: ctx.accounts.reward_mint.key(),
+            reward_per_second,
+            start_ts,
+            end_ts,
+        });
+
+        Ok(())
+    }
+
+    /// Adds another reward stream to a farm, for up to `MAX_REWARD_STREAMS` reward tokens emitted at
+    /// once. The existing streams are accrued up to now first and keep emitting unchanged.
+    /// Only the pool admin can call this.
+    pub fn add_reward_stream(ctx: Context<AddRewardStream>, reward_per_second: u64, start_ts: i64, end_ts: i64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let farm = &mut ctx.accounts.farm;
+        farm.update(now);
+        farm.add_reward_stream(
+            ctx.accounts.reward_mint.key(),
+            ctx.accounts.reward_vault.key(),
+            reward_per_second,
+            start_ts,
+            end_ts,
+            now,
+        )?;
+
+        emit!(RewardStreamAddedEvent {
+            farm: farm.key(),
+            reward_mint: ctx.accounts.reward_mint.key(),
+            reward_per_second,
+            start_ts,
+            end_ts,
+        });
+
+        Ok(())
+    }
+
+    /// Moves `amount` reward tokens of stream `reward_index` from the pool admin into the stream's vault.
+    pub fn fund_farm(ctx: Context<FundFarm>, reward_index: u8, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return err!(SwapError::ZeroAmount);
+        }
+        let stream = ctx.accounts.farm.reward_stream(reward_index)?;
+        if ctx.accounts.reward_mint.key() != stream.reward_mint {
+            return err!(SwapError::InvalidMint);
+        }
+        if ctx.accounts.reward_vault.key() != stream.reward_vault {
+            return err!(SwapError::InvalidVault);
+        }
+
+        let transfer_in_accounts = TransferChecked {
+            from: ctx.accounts.admin_reward_token_account.to_account_info(),
+            mint: ctx.accounts.reward_mint.to_account_info(),
+            to: ctx.accounts.reward_vault.to_account_info(),
+            authority: ctx.accounts.admin.to_account_info(),
+        };
+        let transfer_in_cpi = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_in_accounts,
+        );
+        transfer_checked(transfer_in_cpi, amount, ctx.accounts.reward_mint.decimals)?;
+
+        emit!(FarmFundedEvent {
+            farm: ctx.accounts.farm.key(),
+            reward_mint: ctx.accounts.reward_mint.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// Changes the emission rate of stream `reward_index` from now on. Rewards emitted at the old
+    /// rate stay earned, and the other streams are not affected. Only the pool admin can call this.
+    pub fn set_emission_rate(ctx: Context<ManageFarm>, reward_index: u8, reward_per_second: u64) -> Result<()> {
+        let farm = &mut ctx.accounts.farm;
+        farm.update(Clock::get()?.unix_timestamp);
+        let stream = farm.reward_stream_mut(reward_index)?;
+        stream.reward_per_second = reward_per_second;
+        let reward_mint = stream.reward_mint;
+
+        emit!(EmissionRateUpdatedEvent {
+            farm: farm.key(),
+            reward_mint,
+            reward_per_second,
+        });
+
+        Ok(())
+    }
+
+    /// Creates the caller's (empty) stake position in a farm.
+    pub fn open_stake_position(ctx: Context<OpenStakePosition>) -> Result<()> {
+        let stake_position = &mut ctx.accounts.stake_position;
+        stake_position.farm = ctx.accounts.farm.key();
+        stake_position.owner = ctx.accounts.owner.key();
+        stake_position.amount = 0;
+        stake_position.reward_per_share_last = ctx.accounts.farm.reward_streams.map(|stream| stream.acc_reward_per_share);
+        stake_position.rewards_owed = [0; MAX_REWARD_STREAMS];
+        stake_position.bump = ctx.bumps.stake_position;
+
+        Ok(())
+    }
+
+    /// Stakes `amount` LP shares into the farm. Rewards earned so far are kept for `harvest`.
+    pub fn stake(ctx: Context<UpdateStake>, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return err!(SwapError::ZeroAmount);
+        }
+
+        // --- Accrue Rewards ---
+        let farm = &mut ctx.accounts.farm;
+        let stake_position = &mut ctx.accounts.stake_position;
+        farm.update(Clock::get()?.unix_timestamp);
+        stake_position.settle(farm)?;
+
+        // --- Transfer IN: Owner -> Staked LP Vault ---
+        let transfer_in_accounts = TransferChecked {
+            from: ctx.accounts.owner_lp_token_account.to_account_info(),
+            mint: ctx.accounts.lp_mint.to_account_info(),
+            to: ctx.accounts.staked_lp_vault.to_account_info(),
+            authority: ctx.accounts.owner.to_account_info(),
+        };
+        let transfer_in_cpi = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_in_accounts,
+        );
+        transfer_checked(transfer_in_cpi, amount, ctx.accounts.lp_mint.decimals)?;
+
+        stake_position.amount = stake_position.amount.checked_add(amount).ok_or(SwapError::CalculationOverflow)?;
+        farm.total_staked = farm.total_staked.checked_add(amount).ok_or(SwapError::CalculationOverflow)?;
+
+        emit!(StakedEvent {
+            farm: farm.key(),
+            owner: ctx.accounts.owner.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// Withdraws `amount` staked LP shares from the farm. Rewards earned so far are kept for `harvest`.
+    pub fn unstake(ctx: Context<UpdateStake>, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return err!(SwapError::ZeroAmount);
+        }
+        if amount > ctx.accounts.stake_position.amount {
+            return err!(SwapError::InsufficientStake);
+        }
+
+        // --- Accrue Rewards ---
+        let farm = &mut ctx.accounts.farm;
+        let stake_position = &mut ctx.accounts.stake_position;
+        farm.update(Clock::get()?.unix_timestamp);
+        stake_position.settle(farm)?;
+        stake_position.amount -= amount;
+        farm.total_staked = farm.total_staked.checked_sub(amount).ok_or(SwapError::CalculationOverflow)?;
+
+        // --- Transfer OUT: Staked LP Vault -> Owner ---
+        let farm = &ctx.accounts.farm;
+        let farm_bump_slice = &[farm.bump];
+        let farm_signer_seeds: &[&[u8]] = &[
+            b"farm",
+            farm.pool.as_ref(),
+            farm_bump_slice
+        ];
+        let signer = &[farm_signer_seeds];
+        let transfer_out_accounts = TransferChecked {
+            from: ctx.accounts.staked_lp_vault.to_account_info(),
+            mint: ctx.accounts.lp_mint.to_account_info(),
+            to: ctx.accounts.owner_lp_token_account.to_account_info(),
+            authority: farm.to_account_info(),
+        };
+        let transfer_out_cpi = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_out_accounts,
+            signer,
+        );
+        transfer_checked(transfer_out_cpi, amount, ctx.accounts.lp_mint.decimals)?;
+
+        emit!(UnstakedEvent {
+            farm: farm.key(),
+            owner: ctx.accounts.owner.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// Pays out the rewards of every stream earned by the caller's stake position. For each of the
+    /// farm's streams, in order, the remaining accounts are [reward_mint, reward_vault,
+    /// owner_reward_token_account]. If a reward vault runs short, what it holds is paid and the
+    /// rest stays owed until the stream is funded again.
+    pub fn harvest<'info>(ctx: Context<'_, '_, 'info, 'info, Harvest<'info>>) -> Result<()> {
+        // --- Accrue Rewards ---
+        let farm = &mut ctx.accounts.farm;
+        let stake_position = &mut ctx.accounts.stake_position;
+        farm.update(Clock::get()?.unix_timestamp);
+        stake_position.settle(farm)?;
+
+        if ctx.remaining_accounts.len() != farm.reward_stream_count as usize * REWARD_ACCOUNTS_PER_STREAM {
+            return err!(SwapError::InvalidRewardAccounts);
+        }
+
+        let farm = &ctx.accounts.farm;
+        let farm_bump_slice = &[farm.bump];
+        let farm_signer_seeds: &[&[u8]] = &[
+            b"farm",
+            farm.pool.as_ref(),
+            farm_bump_slice
+        ];
+        let signer = &[farm_signer_seeds];
+
+        let mut harvested_any = false;
+        for (reward_index, reward_accounts) in ctx.remaining_accounts.chunks(REWARD_ACCOUNTS_PER_STREAM).enumerate() {
+            let stream = &farm.reward_streams[reward_index];
+            let reward_mint = InterfaceAccount::<Mint>::try_from(&reward_accounts[0])?;
+            let reward_vault = InterfaceAccount::<TokenAccount>::try_from(&reward_accounts[1])?;
+            let destination = InterfaceAccount::<TokenAccount>::try_from(&reward_accounts[2])?;
+            if reward_mint.key() != stream.reward_mint || destination.mint != stream.reward_mint {
+                return err!(SwapError::InvalidMint);
+            }
+            if reward_vault.key() != stream.reward_vault {
+                return err!(SwapError::InvalidVault);
+            }
+            if destination.owner != ctx.accounts.owner.key() {
+                return err!(SwapError::InvalidOwner);
+            }
+
+            // Whatever the vault cannot cover stays in `rewards_owed` for a later harvest
+            let amount = stake_position.rewards_owed[reward_index].min(reward_vault.amount);
+            if amount == 0 {
+                continue;
+            }
+            stake_position.rewards_owed[reward_index] -= amount;
+
+            // --- Transfer OUT: Reward Vault -> Owner ---
+            let transfer_out_accounts = TransferChecked {
+                from: reward_vault.to_account_info(),
+                mint: reward_mint.to_account_info(),
+                to: destination.to_account_info(),
+                authority: farm.to_account_info(),
+            };
+            let transfer_out_cpi = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                transfer_out_accounts,
+                signer,
+            );
+            transfer_checked(transfer_out_cpi, amount, reward_mint.decimals)?;
+            harvested_any = true;
+
+            emit!(RewardsHarvestedEvent {
+                farm: farm.key(),
+                owner: ctx.accounts.owner.key(),
+                reward_mint: stream.reward_mint,
+                amount,
+            });
+        }
+
+        if !harvested_any {
+            return err!(SwapError::ZeroAmount);
+        }
+        Ok(())
+    }
+
+    /// Creates the vote-escrow for the governance token, a singleton holding every locked token in
+    /// its vault. Only the protocol admin can call this.
+    pub fn initialize_ve_escrow(ctx: Context<InitializeVeEscrow>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.governance_mint = ctx.accounts.governance_mint.key();
+        escrow.vault = ctx.accounts.vault.key();
+        escrow.bump = ctx.bumps.escrow;
+        escrow.total_locked = 0;
+
+        Ok(())
+    }
+
+    /// Escrows `amount` governance tokens until `unlock_ts` and creates the caller's `VeLock`.
+    /// Voting power starts at `amount * (unlock_ts - now) / MAX_LOCK_DURATION_SECS` and decays
+    /// linearly to zero at `unlock_ts`.
+    pub fn lock(ctx: Context<CreateVeLock>, amount: u64, unlock_ts: i64) -> Result<()> {
+        if amount == 0 {
+            return err!(SwapError::ZeroAmount);
+        }
+        check_unlock_ts(unlock_ts, Clock::get()?.unix_timestamp)?;
+
+        // --- Transfer IN: Owner -> Escrow Vault ---
+        let transfer_in_accounts = TransferChecked {
+            from: ctx.accounts.owner_token_account.to_account_info(),
+            mint: ctx.accounts.governance_mint.to_account_info(),
+            to: ctx.accounts.vault.to_account_info(),
+            authority: ctx.accounts.owner.to_account_info(),
+        };
+        let transfer_in_cpi = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_in_accounts,
+        );
+        transfer_checked(transfer_in_cpi, amount, ctx.accounts.governance_mint.decimals)?;
+
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.total_locked = escrow.total_locked.checked_add(amount).ok_or(SwapError::CalculationOverflow)?;
+
+        let ve_lock = &mut ctx.accounts.ve_lock;
+        ve_lock.owner = ctx.accounts.owner.key();
+        ve_lock.amount = amount;
+        ve_lock.unlock_ts = unlock_ts;
+        ve_lock.bump = ctx.bumps.ve_lock;
+
+        emit!(VeLockChangedEvent {
+            owner: ve_lock.owner,
+            amount,
+            unlock_ts,
+        });
+
+        Ok(())
+    }
+
+    /// Adds `amount` governance tokens to the caller's unexpired lock, keeping its unlock time.
+    pub fn increase_amount(ctx: Context<ModifyVeLock>, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return err!(SwapError::ZeroAmount);
+        }
+        if Clock::get()?.unix_timestamp >= ctx.accounts.ve_lock.unlock_ts {
+            return err!(SwapError::LockExpired);
+        }
+
+        // --- Transfer IN: Owner -> Escrow Vault ---
+        let transfer_in_accounts = TransferChecked {
+            from: ctx.accounts.owner_token_account.to_account_info(),
+            mint: ctx.accounts.governance_mint.to_account_info(),
+            to: ctx.accounts.vault.to_account_info(),
+            authority: ctx.accounts.owner.to_account_info(),
+        };
+        let transfer_in_cpi = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_in_accounts,
+        );
+        transfer_checked(transfer_in_cpi, amount, ctx.accounts.governance_mint.decimals)?;
+
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.total_locked = escrow.total_locked.checked_add(amount).ok_or(SwapError::CalculationOverflow)?;
+        let ve_lock = &mut ctx.accounts.ve_lock;
+        ve_lock.amount = ve_lock.amount.checked_add(amount).ok_or(SwapError::CalculationOverflow)?;
+
+        emit!(VeLockChangedEvent {
+            owner: ve_lock.owner,
+            amount: ve_lock.amount,
+            unlock_ts: ve_lock.unlock_ts,
+        });
+
+        Ok(())
+    }
+
+    /// Moves the unlock time of the caller's unexpired lock to the later `unlock_ts`, at most
+    /// `MAX_LOCK_DURATION_SECS` from now.
+    pub fn extend_lock(ctx: Context<ExtendVeLock>, unlock_ts: i64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let ve_lock = &mut ctx.accounts.ve_lock;
+        if now >= ve_lock.unlock_ts {
+            return err!(SwapError::LockExpired);
+        }
+        if unlock_ts <= ve_lock.unlock_ts {
+            return err!(SwapError::InvalidLockDuration);
+        }
+        check_unlock_ts(unlock_ts, now)?;
+        ve_lock.unlock_ts = unlock_ts;
+
+        emit!(VeLockChangedEvent {
+            owner: ve_lock.owner,
+            amount: ve_lock.amount,
+            unlock_ts,
+        });
+
+        Ok(())
+    }
+
+    /// Returns every governance token of the caller's expired lock and closes the lock.
+    pub fn withdraw_after_expiry(ctx: Context<WithdrawVeLock>) -> Result<()> {
+        let ve_lock = &ctx.accounts.ve_lock;
+        if Clock::get()?.unix_timestamp < ve_lock.unlock_ts {
+            return err!(SwapError::LockNotExpired);
+        }
+        let amount = ve_lock.amount;
+
+        // --- Transfer OUT: Escrow Vault -> Owner ---
+        let escrow = &ctx.accounts.escrow;
+        let escrow_bump_slice = &[escrow.bump];
+        let escrow_signer_seeds: &[&[u8]] = &[
+            b"ve_escrow",
+            escrow_bump_slice
+        ];
+        let signer = &[escrow_signer_seeds];
+        let transfer_out_accounts = TransferChecked {
+            from: ctx.accounts.vault.to_account_info(),
+            mint: ctx.accounts.governance_mint.to_account_info(),
+            to: ctx.accounts.owner_token_account.to_account_info(),
+            authority: escrow.to_account_info(),
+        };
+        let transfer_out_cpi = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_out_accounts,
+            signer,
+        );
+        transfer_checked(transfer_out_cpi, amount, ctx.accounts.governance_mint.decimals)?;
+
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.total_locked = escrow.total_locked.checked_sub(amount).ok_or(SwapError::CalculationOverflow)?;
+
+        emit!(VeLockWithdrawnEvent {
+            owner: ctx.accounts.owner.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// Read-only view of a lock's current voting power, returned as return data (use `simulateTransaction`).
+    /// Instructions that weigh users by voting power read it from the `VeLock` account directly.
+    pub fn get_voting_power(ctx: Context<GetVotingPower>) -> Result<u64> {
+        Ok(ctx.accounts.ve_lock.voting_power(Clock::get()?.unix_timestamp))
+    }
+}
+
+
+// --- Helpers ---
+
+/// Rejects the instruction if the optional deadline has passed according to the `Clock` sysvar.
+fn check_deadline(deadline_unix_ts: Option<i64>) -> Result<()> {
+    if let Some(deadline) = deadline_unix_ts {
+        if Clock::get()?.unix_timestamp > deadline {
+            return err!(SwapError::Expired);
+        }
+    }
+    Ok(())
+}
+
+/// Rejects a flash loan instruction made through CPI, which the instructions sysvar cannot see.
+fn require_top_level() -> Result<()> {
+    if get_stack_height() != TRANSACTION_LEVEL_STACK_HEIGHT {
+        return err!(SwapError::FlashLoanNotTopLevel);
+    }
+    Ok(())
+}
+
+/// Rejects a flash loan unless the next `flash_repay` for `pool` later in the transaction pays
+/// back into `vault`. The borrow itself must be a top-level instruction, see `require_top_level`.
+fn require_flash_repay(instructions: &AccountInfo, pool: Pubkey, vault: Pubkey) -> Result<()> {
+    let current_index = load_current_index_checked(instructions)? as usize;
+
+    // The pool stays locked until a repay, so the next repay naming the pool is the one that
+    // settles this loan. Repaying in the pool's other token would turn the loan into a swap.
+    let mut index = current_index + 1;
+    while let Ok(instruction) = load_instruction_at_checked(index, instructions) {
+        if instruction.program_id == crate::ID && instruction.data.starts_with(instruction::FlashRepay::DISCRIMINATOR) {
+            let account = |position: usize| instruction.accounts.get(position).map(|meta| meta.pubkey);
+            if account(FLASH_LOAN_POOL_INDEX) == Some(pool) {
+                return if account(FLASH_LOAN_VAULT_INDEX) == Some(vault) {
+                    Ok(())
+                } else {
+                    err!(SwapError::FlashLoanNotRepaid)
+                };
+            }
+        }
+        index += 1;
+    }
+    err!(SwapError::FlashLoanNotRepaid)
+}
+
+/// Rejects an unlock time that is not in the future or more than `MAX_LOCK_DURATION_SECS` away.
+fn check_unlock_ts(unlock_ts: i64, now: i64) -> Result<()> {
+    if unlock_ts <= now || unlock_ts - now > MAX_LOCK_DURATION_SECS {
+        return err!(SwapError::InvalidLockDuration);
+    }
+    Ok(())
+}
+
+/// Records `new_admin` as the pending admin of `target` (a pool or the program config).
+fn propose_admin_transfer(target: Pubkey, admin: Pubkey, pending_admin: &mut Pubkey, new_admin: Pubkey) -> Result<()> {
+    if new_admin == Pubkey::default() || new_admin == admin {
+        return err!(SwapError::InvalidPendingAdmin);
+    }
+    *pending_admin = new_admin;
+
+    emit!(AdminTransferProposedEvent { target, admin, pending_admin: new_admin });
+    Ok(())
+}
+
+/// Moves the admin role of `target` to its pending admin. The caller checks the pending admin signed.
+fn accept_admin_transfer(target: Pubkey, admin: &mut Pubkey, pending_admin: &mut Pubkey) -> Result<()> {
+    if *pending_admin == Pubkey::default() {
+        return err!(SwapError::NoPendingAdmin);
+    }
+    let old_admin = *admin;
+    *admin = *pending_admin;
+    *pending_admin = Pubkey::default();
+
+    emit!(AdminTransferAcceptedEvent { target, old_admin, new_admin: *admin });
+    Ok(())
+}
+
+/// Clears the pending admin of `target`.
+fn cancel_pending_admin(target: Pubkey, admin: Pubkey, pending_admin: &mut Pubkey) -> Result<()> {
+    if *pending_admin == Pubkey::default() {
+        return err!(SwapError::NoPendingAdmin);
+    }
+    let cancelled_admin = *pending_admin;
+    *pending_admin = Pubkey::default();
+
+    emit!(AdminTransferCancelledEvent { target, admin, cancelled_admin });
+    Ok(())
+}
+
+/// Rejects swaps that take more than `max_price_impact_bps` of the output reserve.
+/// Returns the price impact in basis points.
+fn check_price_impact(amount_out: u64, reserve_out: u64, max_price_impact_bps: u16) -> Result<u64> {
+    let price_impact_bps = (amount_out as u128)
+        .checked_mul(BPS_DENOMINATOR)
+        .ok_or(SwapError::CalculationOverflow)?
+        .checked_div(reserve_out as u128)
+        .ok_or(SwapError::CalculationOverflow)?;
+
+    if price_impact_bps > max_price_impact_bps as u128 {
+        return err!(SwapError::ExcessivePriceImpact);
+    }
+    Ok(price_impact_bps as u64)
+}
+
+/// Prices an exact-input swap against the given reserves. Shared by `swap`, `route_swap` and `quote_swap`.
+fn quote_exact_in(amount_in: u64, reserves: &SwapReserves, pool: &LiquidityPool) -> Result<SwapQuote> {
+    if amount_in == 0 {
+        return err!(SwapError::ZeroAmount); // Input amount must be positive
+    }
+
+    let now = Clock::get()?.unix_timestamp;
+    pool.require_sale_started(now)?;
+    let curve = pool.curve(now);
+    let CurveSwap { amount_out, fee_amount, .. } = curve.swap_exact_in(amount_in, reserves, pool.fee_bps)?;
+    // The input would be kept without paying anything out, e.g. when rounding floors it away
+    if amount_out == 0 {
+        return err!(SwapError::SwapTooSmall);
+    }
+    let price_impact_bps = check_price_impact(amount_out, reserves.reserve_out, pool.max_price_impact_bps)?;
+
+    Ok(SwapQuote { amount_in, amount_out, fee_amount, price_impact_bps })
+}
+
+/// Prices an exact-output swap against the given reserves. Shared by `swap_exact_out` and `quote_swap_exact_out`.
+fn quote_exact_out(amount_out: u64, reserves: &SwapReserves, pool: &LiquidityPool) -> Result<SwapQuote> {
+    if amount_out == 0 {
+        return err!(SwapError::ZeroAmount); // Output amount must be positive
+    }
+
+    let now = Clock::get()?.unix_timestamp;
+    pool.require_sale_started(now)?;
+    let curve = pool.curve(now);
+    let CurveSwap { amount_in, fee_amount, .. } = curve.swap_exact_out(amount_out, reserves, pool.fee_bps)?;
+    let price_impact_bps = check_price_impact(amount_out, reserves.reserve_out, pool.max_price_impact_bps)?;
+
+    Ok(SwapQuote { amount_in, amount_out, fee_amount, price_impact_bps })
+}
+
+/// Calculates the protocol's share of a swap fee.
+fn calculate_protocol_fee(fee_amount: u64, protocol_fee_share_bps: u16) -> Result<u64> {
+    let protocol_fee = (fee_amount as u128)
+        .checked_mul(protocol_fee_share_bps as u128)
+        .ok_or(SwapError::CalculationOverflow)?
+        .checked_div(BPS_DENOMINATOR)
+        .ok_or(SwapError::CalculationOverflow)?;
+    Ok(protocol_fee as u64)
+}
+
+/// Returns `numerator / denominator` as a Q64.64 fixed-point price.
+fn q64_price(numerator: u64, denominator: u64) -> u128 {
+    ((numerator as u128) << 64) / denominator as u128
+}
+
+/// Computes the average prices between two observations of the same pool.
+fn compute_twap(start: &PriceObservation, end: &PriceObservation) -> Result<TwapPrices> {
+    let elapsed = end.timestamp.checked_sub(start.timestamp).ok_or(SwapError::CalculationOverflow)?;
+    if elapsed <= 0 {
+        return err!(SwapError::InvalidObservation);
+    }
+
+    // Accumulators wrap, so the difference is taken modulo 2^128
+    let price_a = end.price_a_cumulative.wrapping_sub(start.price_a_cumulative) / elapsed as u128;
+    let price_b = end.price_b_cumulative.wrapping_sub(start.price_b_cumulative) / elapsed as u128;
+    Ok(TwapPrices { price_a, price_b })
+}
+
+/// Linearly interpolates the accumulators between two observations at `target`.
+fn interpolate_observation(before: &Observation, after: &Observation, target: i64) -> Observation {
+    let span = after.timestamp - before.timestamp;
+    let offset = target - before.timestamp;
+    if span <= 0 || offset <= 0 {
+        return *before;
+    }
+
+    // Accumulators wrap, so deltas are taken modulo 2^128. The delta is scaled by the offset
+    // before dividing by the span, otherwise short spans would truncate it to zero.
+    let step = |from: u128, to: u128| {
+        let delta = U256::from(to.wrapping_sub(from)) * U256::from(offset) / U256::from(span);
+        from.wrapping_add(delta.as_u128())
+    };
+    Observation {
+        timestamp: target,
+        price_a_cumulative: step(before.price_a_cumulative, after.price_a_cumulative),
+        price_b_cumulative: step(before.price_b_cumulative, after.price_b_cumulative),
+        liquidity_cumulative: step(before.liquidity_cumulative, after.liquidity_cumulative),
+    }
+}
+
+/// Computes the average prices and liquidity between two observations of the same pool.
+fn compute_observed_twap(start: &Observation, end: &Observation) -> Result<ObservedTwap> {
+    let elapsed = end.timestamp.checked_sub(start.timestamp).ok_or(SwapError::CalculationOverflow)?;
+    if elapsed <= 0 {
+        return err!(SwapError::InvalidObservation);
+    }
+
+    let elapsed = elapsed as u128;
+    Ok(ObservedTwap {
+        price_a: end.price_a_cumulative.wrapping_sub(start.price_a_cumulative) / elapsed,
+        price_b: end.price_b_cumulative.wrapping_sub(start.price_b_cumulative) / elapsed,
+        liquidity: end.liquidity_cumulative.wrapping_sub(start.liquidity_cumulative) / elapsed,
+    })
+}
+
+/// Rejects position ranges that are empty, out of bounds or not aligned to the tick spacing.
+fn check_tick_range(tick_lower: i32, tick_upper: i32, tick_spacing: u16) -> Result<()> {
+    let spacing = tick_spacing as i32;
+    if tick_lower >= tick_upper
+        || tick_lower < MIN_TICK
+        || tick_upper > MAX_TICK
+        || tick_lower % spacing != 0
+        || tick_upper % spacing != 0
+    {
+        return err!(SwapError::InvalidTickRange);
+    }
+    Ok(())
+}
+
+/// Applies a liquidity change to a concentrated-liquidity position: updates its boundary ticks,
+/// accrues the fees earned since its last update, and moves the pool's active liquidity when the
+/// range holds the current price. `same_array` marks both ticks living in the same tick array.
+fn update_position(
+    pool: &mut ConcentratedPool,
+    position: &mut Position,
+    tick_array_lower: &mut TickArray,
+    tick_array_upper: &mut TickArray,
+    same_array: bool,
+    liquidity_delta: i128,
+) -> Result<()> {
+    let (tick_lower, tick_upper) = (position.tick_lower, position.tick_upper);
+    let spacing = pool.tick_spacing;
+    let (global_a, global_b) = (pool.fee_growth_global_a_x64, pool.fee_growth_global_b_x64);
+
+    if liquidity_delta != 0 {
+        tick_array_lower
+            .tick_mut(tick_lower, spacing)?
+            .update(tick_lower, pool.tick_current, liquidity_delta, global_a, global_b, false)?;
+        let upper_array = if same_array { &mut *tick_array_lower } else { &mut *tick_array_upper };
+        upper_array
+            .tick_mut(tick_upper, spacing)?
+            .update(tick_upper, pool.tick_current, liquidity_delta, global_a, global_b, true)?;
+    }
+
+    // --- Accrue Fees ---
+    let lower = *tick_array_lower.tick(tick_lower, spacing)?;
+    let upper = *if same_array { &*tick_array_lower } else { &*tick_array_upper }.tick(tick_upper, spacing)?;
+    let (inside_a, inside_b) = fee_growth_inside(&lower, &upper, tick_lower, tick_upper, pool.tick_current, global_a, global_b);
+    position.tokens_owed_a = position.tokens_owed_a.saturating_add(growth_earned(
+        position.liquidity,
+        inside_a,
+        position.fee_growth_inside_last_a_x64,
+    ));
+    position.tokens_owed_b = position.tokens_owed_b.saturating_add(growth_earned(
+        position.liquidity,
+        inside_b,
+        position.fee_growth_inside_last_b_x64,
+    ));
+    position.fee_growth_inside_last_a_x64 = inside_a;
+    position.fee_growth_inside_last_b_x64 = inside_b;
+
+    // --- Update Liquidity ---
+    position.liquidity = add_liquidity_delta(position.liquidity, liquidity_delta)?;
+    if (tick_lower..tick_upper).contains(&pool.tick_current) {
+        pool.liquidity = add_liquidity_delta(pool.liquidity, liquidity_delta)?;
+    }
+
+    // Ticks no position references any more are reset
+    if liquidity_delta < 0 {
+        tick_array_lower.tick_mut(tick_lower, spacing)?.clear_if_unused();
+        let upper_array = if same_array { &mut *tick_array_lower } else { &mut *tick_array_upper };
+        upper_array.tick_mut(tick_upper, spacing)?.clear_if_unused();
+    }
+
+    // Both accounts are written back, so a shared array must hold the same ticks in both
+    if same_array {
+        tick_array_upper.ticks = tick_array_lower.ticks.clone();
+    }
+    Ok(())
+}
+
+/// Returns the fee growth per unit of liquidity inside [tick_lower, tick_upper), for Token A and Token B.
+/// The growth values wrap, only differences between them are meaningful.
+fn fee_growth_inside(
+    lower: &Tick,
+    upper: &Tick,
+    tick_lower: i32,
+    tick_upper: i32,
+    tick_current: i32,
+    global_a: u128,
+    global_b: u128,
+) -> (u128, u128) {
+    let inside = |global: u128, lower_outside: u128, upper_outside: u128| {
+        let below = if tick_current >= tick_lower { lower_outside } else { global.wrapping_sub(lower_outside) };
+        let above = if tick_current < tick_upper { upper_outside } else { global.wrapping_sub(upper_outside) };
+        global.wrapping_sub(below).wrapping_sub(above)
+    };
+    (
+        inside(global_a, lower.fee_growth_outside_a_x64, upper.fee_growth_outside_a_x64),
+        inside(global_b, lower.fee_growth_outside_b_x64, upper.fee_growth_outside_b_x64),
+    )
+}
+
+/// Returns what `amount` earned while a Q64.64 per-unit growth went from `last` to `current`,
+/// e.g. a position's fees from the fee growth inside its range.
+fn growth_earned(amount: u128, current: u128, last: u128) -> u64 {
+    let earned = (U256::from(amount) * U256::from(current.wrapping_sub(last))) >> 64;
+    u64::try_from(earned).unwrap_or(u64::MAX)
+}
+
+/// Pays Token A and Token B out of a concentrated-liquidity pool's vaults to the position owner.
+fn pay_out_of_position(ctx: &Context<ModifyPosition>, amount_a: u64, amount_b: u64) -> Result<()> {
+    let pool = &ctx.accounts.pool;
+    let pool_bump_slice = &[pool.bump];
+    let pool_signer_seeds: &[&[u8]] = &[
+        b"cl_pool",
+        pool.token_a_mint.as_ref(),
+        pool.token_b_mint.as_ref(),
+        pool_bump_slice
+    ];
+    let signer = &[pool_signer_seeds];
+
+    if amount_a > 0 {
+        let transfer_a_accounts = TransferChecked {
+            from: ctx.accounts.token_a_vault.to_account_info(),
+            mint: ctx.accounts.token_a_mint.to_account_info(),
+            to: ctx.accounts.owner_token_a_account.to_account_info(),
+            authority: ctx.accounts.pool_authority.to_account_info(),
+        };
+        let transfer_a_cpi = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_a_accounts,
+            signer,
+        );
+        transfer_checked(transfer_a_cpi, amount_a, ctx.accounts.token_a_mint.decimals)?;
+    }
+    if amount_b > 0 {
+        let transfer_b_accounts = TransferChecked {
+            from: ctx.accounts.token_b_vault.to_account_info(),
+            mint: ctx.accounts.token_b_mint.to_account_info(),
+            to: ctx.accounts.owner_token_b_account.to_account_info(),
+            authority: ctx.accounts.pool_authority.to_account_info(),
+        };
+        let transfer_b_cpi = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            transfer_b_accounts,
+            signer,
+        );
+        transfer_checked(transfer_b_cpi, amount_b, ctx.accounts.token_b_mint.decimals)?;
+    }
+    Ok(())
+}
+
+// --- Account Data Structures ---
+
+/// Stores the state of a single liquidity pool.
+#[account]
+#[derive(Default)] // Allows initializing with default values (zeros, null pubkeys)
+pub struct LiquidityPool {
+    /// The mint address of the first token (Token A).
+    pub token_a_mint: Pubkey,
+    /// The mint address of the second token (Token B).
+    pub token_b_mint: Pubkey,
+    /// The address of the pool's vault (ATA) for Token A.
+    pub token_a_vault: Pubkey,
+    /// The address of the pool's vault (ATA) for Token B.
+    pub token_b_vault: Pubkey,
+    /// The bump seed used for the pool's PDA.
+    pub bump: u8,
+    /// The mint of the pool's LP share token. Mint authority is the pool authority PDA.
+    pub lp_mint: Pubkey,
+    /// The swap fee in basis points, charged on the input amount.
+    pub fee_bps: u16,
+    /// The share of every swap fee owed to the protocol treasury, in basis points of the fee.
+    pub protocol_fee_share_bps: u16,
+    /// Protocol fees accrued in Token A, held in vault A until collected.
+    pub protocol_fees_owed_a: u64,
+    /// Protocol fees accrued in Token B, held in vault B until collected.
+    pub protocol_fees_owed_b: u64,
+    /// Time-weighted sum of the price of Token A in Token B (Q64.64), wrapping on overflow.
+    pub price_a_cumulative: u128,
+    /// Time-weighted sum of the price of Token B in Token A (Q64.64), wrapping on overflow.
+    pub price_b_cumulative: u128,
+    /// The unix timestamp of the last accumulator update.
+    pub last_update_ts: i64,
+    /// The account allowed to change the pool's status.
+    pub admin: Pubkey,
+    /// Bitflags of paused operations, see the `POOL_STATUS_*` constants.
+    pub status: u8,
+    /// Maximum share of the output reserve a single swap may take, in basis points.
+    pub max_price_impact_bps: u16,
+    /// Allowed deviation of a deposit from the pool ratio, in basis points.
+    pub deposit_ratio_tolerance_bps: u16,
+    /// The proposed next admin, or the default key when no handover is pending.
+    pub pending_admin: Pubkey,
+    /// The pricing curve used by swaps, quotes and LP minting.
+    pub curve_kind: CurveKind,
+    /// StableSwap amplification coefficient at the start of the current ramp.
+    pub amp_initial: u64,
+    /// StableSwap amplification coefficient at the end of the current ramp.
+    pub amp_target: u64,
+    /// The unix timestamp the current amp ramp started at.
+    pub amp_ramp_start_ts: i64,
+    /// The unix timestamp the current amp ramp ends at.
+    pub amp_ramp_end_ts: i64,
+    /// Constant-price pools: the amount of Token A one unit of Token B is worth.
+    pub token_b_price: u64,
+    /// Weighted pools: the weight of Token A, in basis points.
+    pub weight_a_bps: u16,
+    /// Weighted pools: the weight of Token B, in basis points. Sums to `BPS_DENOMINATOR` with `weight_a_bps`.
+    pub weight_b_bps: u16,
+    /// Liquidity bootstrapping pools: the Token A and Token B weights at `sale_start_ts`, in basis points.
+    pub start_weights: [u16; 2],
+    /// Liquidity bootstrapping pools: the Token A and Token B weights at `sale_end_ts`, in basis points.
+    pub end_weights: [u16; 2],
+    /// Liquidity bootstrapping pools: the unix timestamp swaps open and the weights start shifting.
+    pub sale_start_ts: i64,
+    /// Liquidity bootstrapping pools: the unix timestamp the weights reach `end_weights` and the sale ends.
+    pub sale_end_ts: i64,
+    /// Set while a flash swap's callback runs or a flash loan is outstanding, so the borrower cannot re-enter the pool.
+    pub locked: bool,
+    /// Principal plus fee of the outstanding flash loan, zero when there is none.
+    pub flash_loan_owed: u64,
+    /// The vault the outstanding flash loan came from, which `flash_repay` must pay back into.
+    pub flash_loan_vault: Pubkey,
+    /// Total amount of Token A ever lent out by `flash_borrow`.
+    pub flash_loan_volume_a: u128,
+    /// Total amount of Token B ever lent out by `flash_borrow`.
+    pub flash_loan_volume_b: u128,
+    /// LP shares locked forever by the first deposit. They count towards the LP supply but are
+    /// never minted, so the supply cannot be drained back to a handful of shares.
+    pub locked_lp_amount: u64,
+    /// The account that created the pool. Unlike `admin` it never changes, and it is the only
+    /// one allowed to deposit into a liquidity bootstrapping pool during its sale.
+    pub creator: Pubkey,
+}
+
+impl LiquidityPool {
+    /// Returns the pool's pricing curve at `now`.
+    pub fn curve(&self, now: i64) -> Box<dyn SwapCurve> {
+        match self.curve_kind {
+            CurveKind::ConstantProduct => Box::new(ConstantProductCurve),
+            CurveKind::StableSwap => Box::new(StableSwapCurve { amp: self.current_amp(now) }),
+            CurveKind::ConstantPrice => Box::new(ConstantPriceCurve { token_b_price: self.token_b_price }),
+            CurveKind::Weighted => Box::new(WeightedCurve {
+                weight_a_bps: self.weight_a_bps,
+                weight_b_bps: self.weight_b_bps,
+            }),
+            CurveKind::LiquidityBootstrapping => {
+                let [weight_a_bps, weight_b_bps] = self.current_weights(now);
+                Box::new(WeightedCurve { weight_a_bps, weight_b_bps })
+            }
+        }
+    }
+
+    /// Returns a liquidity bootstrapping pool's Token A and Token B weights at `now`,
+    /// linearly interpolated between `start_weights` and `end_weights` over the sale.
+    pub fn current_weights(&self, now: i64) -> [u16; 2] {
+        if now <= self.sale_start_ts {
+            return self.start_weights;
+        }
+        if now >= self.sale_end_ts {
+            return self.end_weights;
+        }
+        let elapsed = (now - self.sale_start_ts) as i128;
+        let duration = (self.sale_end_ts - self.sale_start_ts) as i128;
+        let (start, end) = (self.start_weights[0] as i128, self.end_weights[0] as i128);
+        let weight_a_bps = (start + (end - start) * elapsed / duration) as u16;
+        [weight_a_bps, BPS_DENOMINATOR as u16 - weight_a_bps]
+    }
+
+    /// Rejects swaps on a liquidity bootstrapping pool whose sale has not started yet.
+    pub fn require_sale_started(&self, now: i64) -> Result<()> {
+        if self.curve_kind == CurveKind::LiquidityBootstrapping && now < self.sale_start_ts {
+            return err!(SwapError::SaleNotStarted);
+        }
+        Ok(())
+    }
+
+    /// Returns the StableSwap amplification coefficient at `now`, linearly interpolated along the ramp.
+    pub fn current_amp(&self, now: i64) -> u64 {
+        if now >= self.amp_ramp_end_ts || self.amp_ramp_end_ts <= self.amp_ramp_start_ts {
+            return self.amp_target;
+        }
+        let elapsed = (now - self.amp_ramp_start_ts).max(0) as i128;
+        let duration = (self.amp_ramp_end_ts - self.amp_ramp_start_ts) as i128;
+        let (initial, target) = (self.amp_initial as i128, self.amp_target as i128);
+        (initial + (target - initial) * elapsed / duration) as u64
+    }
+
+    /// Rejects the instruction if any of the given `POOL_STATUS_*` flags is set.
+    pub fn require_not_paused(&self, flag: u8) -> Result<()> {
+        if self.status & flag != 0 {
+            return err!(SwapError::PoolPaused);
+        }
+        Ok(())
+    }
+
+    /// Rejects the instruction while a flash swap on the pool is in progress.
+    pub fn require_unlocked(&self) -> Result<()> {
+        if self.locked {
+            return err!(SwapError::PoolLocked);
+        }
+        Ok(())
+    }
+
+    /// Returns the LP supply backing the reserves: the minted shares plus the locked ones.
+    pub fn lp_supply(&self, minted: u64) -> Result<u64> {
+        minted.checked_add(self.locked_lp_amount).ok_or(error!(SwapError::CalculationOverflow))
+    }
+
+    /// Returns the vault balances minus the protocol fees owed, i.e. the reserves
+    /// that back LP shares and are used for pricing.
+    pub fn available_reserves(&self, vault_a_amount: u64, vault_b_amount: u64) -> Result<(u64, u64)> {
+        let reserve_a = vault_a_amount
+            .checked_sub(self.protocol_fees_owed_a)
+            .ok_or(SwapError::CalculationOverflow)?;
+        let reserve_b = vault_b_amount
+            .checked_sub(self.protocol_fees_owed_b)
+            .ok_or(SwapError::CalculationOverflow)?;
+        Ok((reserve_a, reserve_b))
+    }
+
+    /// Returns the available reserves oriented for a swap in the given direction.
+    pub fn swap_reserves(&self, vault_a_amount: u64, vault_b_amount: u64, is_a_to_b: bool) -> Result<SwapReserves> {
+        let (reserve_a, reserve_b) = self.available_reserves(vault_a_amount, vault_b_amount)?;
+        let (reserve_in, reserve_out) = if is_a_to_b {
+            (reserve_a, reserve_b)
+        } else {
+            (reserve_b, reserve_a)
+        };
+
+        if reserve_in == 0 || reserve_out == 0 {
+            return err!(SwapError::PoolIsEmpty); // Cannot swap if a pool is empty
+        }
+
+        Ok(SwapReserves { is_a_to_b, reserve_in, reserve_out })
+    }
+
+    /// Returns the price accumulators as they would read at `now`, given the current reserves.
+    pub fn observe(&self, reserve_a: u64, reserve_b: u64, now: i64) -> PriceObservation {
+        let mut observation = PriceObservation {
+            timestamp: now,
+            price_a_cumulative: self.price_a_cumulative,
+            price_b_cumulative: self.price_b_cumulative,
+        };
+
+        let elapsed = now.saturating_sub(self.last_update_ts);
+        if elapsed > 0 && reserve_a > 0 && reserve_b > 0 {
+            // Overflow of the accumulators is intended, consumers only use differences
+            observation.price_a_cumulative = observation.price_a_cumulative
+                .wrapping_add(q64_price(reserve_b, reserve_a).wrapping_mul(elapsed as u128));
+            observation.price_b_cumulative = observation.price_b_cumulative
+                .wrapping_add(q64_price(reserve_a, reserve_b).wrapping_mul(elapsed as u128));
+        }
+        observation
+    }
+
+    /// Accumulates the current price for the time elapsed since the last update.
+    /// Must be called with the reserves *before* they change.
+    pub fn update_price_accumulators(&mut self, reserve_a: u64, reserve_b: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let observation = self.observe(reserve_a, reserve_b, now);
+        self.price_a_cumulative = observation.price_a_cumulative;
+        self.price_b_cumulative = observation.price_b_cumulative;
+        self.last_update_ts = now;
+        Ok(())
+    }
+}
+
+/// Define the space required for the LiquidityPool account.
+/// Pools created with the original 201-byte layout are grown to this size by `migrate_pool`.
+const POOL_ACCOUNT_SIZE: usize = 8 + ( 32 * 4 ) + 1 + 32 + 2 + 2 + ( 8 * 2 ) + ( 16 * 2 ) + 8 + 32 + 1 + 2 + 2 + 32 + 1 + ( 8 * 4 ) + 8 + ( 2 * 2 ) + ( 2 * 4 ) + ( 8 * 2 ) + 1 + 8 + 32 + ( 16 * 2 ) + 8 + 32 + 24; // = 504 bytes
+
+/// Pool status flag: `swap`, `swap_exact_out` and `route_swap` are rejected.
+pub const POOL_STATUS_SWAPS_PAUSED: u8 = 1 << 0;
+/// Pool status flag: `add_liquidity` is rejected.
+pub const POOL_STATUS_DEPOSITS_PAUSED: u8 = 1 << 1;
+/// Pool status flag: `remove_liquidity` is rejected.
+pub const POOL_STATUS_WITHDRAWALS_PAUSED: u8 = 1 << 2;
+/// All pool status flags combined.
+pub const POOL_STATUS_ALL_PAUSED: u8 = POOL_STATUS_SWAPS_PAUSED | POOL_STATUS_DEPOSITS_PAUSED | POOL_STATUS_WITHDRAWALS_PAUSED;
+
+/// A single entry in a pool's observation ring buffer.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default)]
+pub struct Observation {
+    /// The unix timestamp of the observation. Zero marks an unused slot.
+    pub timestamp: i64,
+    /// Cumulative price of Token A in Token B (Q64.64).
+    pub price_a_cumulative: u128,
+    /// Cumulative price of Token B in Token A (Q64.64).
+    pub price_b_cumulative: u128,
+    /// Time-weighted sum of the pool liquidity, sqrt(reserve_a * reserve_b).
+    pub liquidity_cumulative: u128,
+}
+
+/// Historical price observations of a pool, stored as a ring buffer in a PDA seeded from the pool.
+#[account]
+#[derive(Default)]
+pub struct PoolObservations {
+    /// The pool these observations belong to.
+    pub pool: Pubkey,
+    /// Index of the most recent observation.
+    pub index: u16,
+    /// Number of slots in use by the ring. Grows up to the allocated length as the ring wraps.
+    pub cardinality: u16,
+    /// The slot of the last write, so the buffer is written at most once per slot.
+    pub last_write_slot: u64,
+    /// The bump seed used for the observations' PDA.
+    pub bump: u8,
+    /// The ring buffer. Its length is the allocated cardinality.
+    pub observations: Vec<Observation>,
+}
+
+impl PoolObservations {
+    /// Space required for an observations account holding `cardinality` entries.
+    pub fn space(cardinality: usize) -> usize {
+        8 + 32 + 2 + 2 + 8 + 1 + 4 + OBSERVATION_SIZE * cardinality
+    }
+
+    /// Returns the observation the buffer would record at `now`, given the current reserves.
+    pub fn current(&self, pool: &LiquidityPool, reserve_a: u64, reserve_b: u64, now: i64) -> Result<Observation> {
+        let last = self.observations[self.index as usize];
+        let prices = pool.observe(reserve_a, reserve_b, now);
+
+        let elapsed = now.saturating_sub(last.timestamp).max(0) as u128;
+        let liquidity = integer_sqrt(
+            (reserve_a as u128)
+                .checked_mul(reserve_b as u128)
+                .ok_or(SwapError::CalculationOverflow)?,
+        );
+
+        Ok(Observation {
+            timestamp: now,
+            price_a_cumulative: prices.price_a_cumulative,
+            price_b_cumulative: prices.price_b_cumulative,
+            liquidity_cumulative: last.liquidity_cumulative.wrapping_add(liquidity.wrapping_mul(elapsed)),
+        })
+    }
+
+    /// Records an observation for the current slot. Must be called after the pool's
+    /// accumulators were updated and with the reserves *before* they change.
+    pub fn write(&mut self, pool: &LiquidityPool, reserve_a: u64, reserve_b: u64) -> Result<()> {
+        let clock = Clock::get()?;
+        let last = self.observations[self.index as usize];
+
+        // At most one observation per slot (and never two with the same timestamp)
+        if clock.slot == self.last_write_slot || clock.unix_timestamp <= last.timestamp {
+            return Ok(());
+        }
+
+        let observation = self.current(pool, reserve_a, reserve_b, clock.unix_timestamp)?;
+
+        // Slots added by increase_observation_cardinality join the ring once it reaches the end
+        if self.index + 1 == self.cardinality && (self.cardinality as usize) < self.observations.len() {
+            self.cardinality += 1;
+        }
+        self.index = (self.index + 1) % self.cardinality;
+        self.observations[self.index as usize] = observation;
+        self.last_write_slot = clock.slot;
+        Ok(())
+    }
+
+    /// Returns the (interpolated) observation at `target`, given the observation `current` at now.
+    pub fn observation_at(&self, current: &Observation, target: i64) -> Result<Observation> {
+        let newest = self.observations[self.index as usize];
+        if target >= newest.timestamp {
+            return Ok(interpolate_observation(&newest, current, target));
+        }
+
+        // The oldest entry sits right after the newest one, unless that slot was never written
+        let cardinality = self.cardinality as usize;
+        let mut oldest_index = (self.index as usize + 1) % cardinality;
+        if self.observations[oldest_index].timestamp == 0 {
+            oldest_index = 0;
+        }
+        let oldest = self.observations[oldest_index];
+        if target < oldest.timestamp {
+            return err!(SwapError::ObservationTooOld);
+        }
+
+        // Binary search over the ring, in chronological order, for the last entry at or before target
+        let entries = (self.index as usize + cardinality - oldest_index) % cardinality + 1;
+        let at = |position: usize| self.observations[(oldest_index + position) % cardinality];
+        let (mut low, mut high) = (0, entries - 1);
+        while low < high {
+            let mid = (low + high).div_ceil(2);
+            if at(mid).timestamp <= target {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+        }
+
+        Ok(interpolate_observation(&at(low), &at(low + 1), target))
+    }
+}
+
+/// Size of a single serialized `Observation`.
+const OBSERVATION_SIZE: usize = 8 + ( 16 * 3 ); // = 56 bytes
+
+/// Upper bound for the number of entries in a pool's observation buffer. Every swap and liquidity
+/// change deserializes the whole buffer onto the 32 KB heap (twice over while it is being grown),
+/// so it has to stay well below that budget.
+pub const MAX_OBSERVATION_CARDINALITY: u16 = 128;
+
+/// Global protocol settings, stored in a singleton PDA.
+#[account]
+#[derive(Default)]
+pub struct ProgramConfig {
+    /// The protocol admin.
+    pub admin: Pubkey,
+    /// The wallet whose token accounts receive collected protocol fees.
+    pub treasury: Pubkey,
+    /// The share of every swap fee owed to the treasury, copied into each new pool.
+    pub protocol_fee_share_bps: u16,
+    /// The bump seed used for the config's PDA.
+    pub bump: u8,
+    /// Swap fee used by `initialize_pool` when none is given.
+    pub default_fee_bps: u16,
+    /// Upper bound for the swap fee of new pools.
+    pub max_fee_bps: u16,
+    /// Price impact limit copied into each new pool.
+    pub default_max_price_impact_bps: u16,
+    /// Deposit-ratio tolerance copied into each new pool.
+    pub deposit_ratio_tolerance_bps: u16,
+    /// Whether `initialize_pool` accepts new pools.
+    pub pool_creation_enabled: bool,
+    /// The proposed next protocol admin, or the default key when no handover is pending.
+    pub pending_admin: Pubkey,
+    /// Delay between queueing a pool parameter change and being able to execute it.
+    pub change_delay_secs: u32,
+}
+
+impl ProgramConfig {
+    /// Copies the protocol parameters into the config.
+    pub fn apply(&mut self, params: &ConfigParams) {
+        self.protocol_fee_share_bps = params.protocol_fee_share_bps;
+        self.default_fee_bps = params.default_fee_bps;
+        self.max_fee_bps = params.max_fee_bps;
+        self.default_max_price_impact_bps = params.default_max_price_impact_bps;
+        self.deposit_ratio_tolerance_bps = params.deposit_ratio_tolerance_bps;
+        self.pool_creation_enabled = params.pool_creation_enabled;
+        self.change_delay_secs = params.change_delay_secs;
+    }
+}
+
+/// Define the space required for the ProgramConfig account.
+const CONFIG_ACCOUNT_SIZE: usize = 8 + ( 32 * 2 ) + 2 + 1 + ( 2 * 4 ) + 1 + 32 + 4 + 19; // = 139 bytes
+
+/// Protocol parameters passed to `initialize_config` and `update_config`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct ConfigParams {
+    pub protocol_fee_share_bps: u16,
+    pub default_fee_bps: u16,
+    pub max_fee_bps: u16,
+    pub default_max_price_impact_bps: u16,
+    pub deposit_ratio_tolerance_bps: u16,
+    pub pool_creation_enabled: bool,
+    pub change_delay_secs: u32,
+}
+
+impl ConfigParams {
+    /// Rejects parameters outside the bounds compiled into the program.
+    pub fn validate(&self) -> Result<()> {
+        if self.protocol_fee_share_bps as u128 > BPS_DENOMINATOR {
+            return err!(SwapError::InvalidProtocolFeeShare);
+        }
+        if self.max_fee_bps > MAX_FEE_BPS || self.default_fee_bps > self.max_fee_bps {
+            return err!(SwapError::FeeTooHigh);
+        }
+        if self.default_max_price_impact_bps == 0 || self.default_max_price_impact_bps as u128 > BPS_DENOMINATOR {
+            return err!(SwapError::InvalidConfigParams);
+        }
+        if self.deposit_ratio_tolerance_bps as u128 > BPS_DENOMINATOR {
+            return err!(SwapError::InvalidConfigParams);
+        }
+        if self.change_delay_secs > MAX_CHANGE_DELAY_SECS {
+            return err!(SwapError::InvalidConfigParams);
+        }
+        Ok(())
+    }
+}
+
+/// The weight schedule of a liquidity bootstrapping pool, passed to `initialize_pool`.
+/// Weights are `[token_a, token_b]` in basis points.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default)]
+pub struct WeightSchedule {
+    pub start_weights: [u16; 2],
+    pub end_weights: [u16; 2],
+    pub start_ts: i64,
+    pub end_ts: i64,
+}
+
+impl WeightSchedule {
+    /// Rejects weights that are not normalized or below `MIN_WEIGHT_BPS`, and sale windows that are empty or already over.
+    pub fn validate(&self, now: i64) -> Result<()> {
+        let normalized = |weights: [u16; 2]| {
+            weights.iter().all(|&weight| weight as u64 >= MIN_WEIGHT_BPS)
+                && weights[0] as u128 + weights[1] as u128 == BPS_DENOMINATOR
+        };
+        if !normalized(self.start_weights) || !normalized(self.end_weights) {
+            return err!(SwapError::InvalidCurveParams);
+        }
+        if self.start_ts >= self.end_ts || self.end_ts <= now {
+            return err!(SwapError::InvalidCurveParams);
+        }
+        Ok(())
+    }
+}
+
+/// A pool parameter change that has to wait out the timelock.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PoolParamChange {
+    FeeBps(u16),
+    ProtocolFeeShareBps(u16),
+    MaxPriceImpactBps(u16),
+    Status(u8),
+}
+
+impl Default for PoolParamChange {
+    fn default() -> Self {
+        PoolParamChange::Status(0)
+    }
+}
+
+impl PoolParamChange {
+    /// Distinguishes the kinds of change in the `PendingChange` seeds, so one change per kind can be queued.
+    pub fn kind(&self) -> u8 {
+        match self {
+            PoolParamChange::FeeBps(_) => 0,
+            PoolParamChange::ProtocolFeeShareBps(_) => 1,
+            PoolParamChange::MaxPriceImpactBps(_) => 2,
+            PoolParamChange::Status(_) => 3,
+        }
+    }
+
+    /// Returns who may queue and cancel the change: the protocol admin for the protocol's fee
+    /// share, the pool admin for everything else.
+    pub fn admin(&self, pool: &LiquidityPool, config: &ProgramConfig) -> Pubkey {
+        match self {
+            PoolParamChange::ProtocolFeeShareBps(_) => config.admin,
+            _ => pool.admin,
+        }
+    }
+
+    /// Rejects values that could not be set at pool creation either.
+    pub fn validate(&self, config: &ProgramConfig) -> Result<()> {
+        match *self {
+            PoolParamChange::FeeBps(fee_bps) if fee_bps > config.max_fee_bps => err!(SwapError::FeeTooHigh),
+            PoolParamChange::ProtocolFeeShareBps(share_bps) if share_bps as u128 > BPS_DENOMINATOR => {
+                err!(SwapError::InvalidProtocolFeeShare)
+            }
+            PoolParamChange::MaxPriceImpactBps(impact_bps) if impact_bps == 0 || impact_bps as u128 > BPS_DENOMINATOR => {
+                err!(SwapError::InvalidConfigParams)
+            }
+            PoolParamChange::Status(status) if status & !POOL_STATUS_ALL_PAUSED != 0 => err!(SwapError::InvalidPoolStatus),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A queued pool parameter change, stored in a PDA seeded from the pool and the kind of change.
+#[account]
+#[derive(Default)]
+pub struct PendingChange {
+    /// The pool the change applies to.
+    pub pool: Pubkey,
+    /// The admin who queued the change; receives the account's rent back.
+    pub proposer: Pubkey,
+    /// The change to apply.
+    pub change: PoolParamChange,
+    /// The unix timestamp from which the change can be executed.
+    pub eta: i64,
+    /// The bump seed used for the pending change's PDA.
+    pub bump: u8,
+    /// The pool's status when the change was queued. A status change is rejected if an emergency
+    /// pause has set more flags since.
+    pub pool_status: u8,
+}
+
+/// Define the space required for the PendingChange account.
+const PENDING_CHANGE_ACCOUNT_SIZE: usize = 8 + ( 32 * 2 ) + ( 1 + 2 ) + 8 + 1 + 1; // = 85 bytes
+
+/// State of a concentrated-liquidity pool. Liquidity sits in positions over tick ranges instead of
+/// across the whole price curve. The pool PDA doubles as the authority of its vaults, as for `LiquidityPool`.
+/// Its parameters are fixed at creation: there is no pool admin, so it cannot be paused or retuned
+/// and is exempt from the emergency pause.
+#[account]
+#[derive(Default)]
+pub struct ConcentratedPool {
+    /// The mint address of Token A.
+    pub token_a_mint: Pubkey,
+    /// The mint address of Token B.
+    pub token_b_mint: Pubkey,
+    /// The pool's vault for Token A, owned by the pool PDA.
+    pub token_a_vault: Pubkey,
+    /// The pool's vault for Token B, owned by the pool PDA.
+    pub token_b_vault: Pubkey,
+    /// The bump seed used for the pool's PDA.
+    pub bump: u8,
+    /// Swap fee charged on the input amount, in basis points.
+    pub fee_bps: u16,
+    /// Position ticks must be multiples of this spacing.
+    pub tick_spacing: u16,
+    /// Square root of the current Token B per Token A price (Q64.64).
+    pub sqrt_price_x64: u128,
+    /// The tick of the current price.
+    pub tick_current: i32,
+    /// Liquidity of the positions whose range holds the current price.
+    pub liquidity: u128,
+    /// Token A fees earned per unit of liquidity over the pool's lifetime (Q64.64, wrapping).
+    pub fee_growth_global_a_x64: u128,
+    /// Token B fees earned per unit of liquidity over the pool's lifetime (Q64.64, wrapping).
+    pub fee_growth_global_b_x64: u128,
+}
+
+/// Define the space required for the ConcentratedPool account.
+const CONCENTRATED_POOL_ACCOUNT_SIZE: usize = 8 + ( 32 * 4 ) + 1 + 2 + 2 + 16 + 4 + 16 + ( 16 * 2 ) + 24; // = 233 bytes
+
+/// Upper bound for a concentrated-liquidity pool's tick spacing.
+pub const MAX_TICK_SPACING: u16 = 16_384;
+
+/// Number of ticks held by a single tick array.
+pub const TICK_ARRAY_SIZE: usize = 32;
+
+/// A tick that positions can use as a range boundary.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default)]
+pub struct Tick {
+    /// Liquidity added to the pool when the price crosses this tick upwards (removed when crossing downwards).
+    pub liquidity_net: i128,
+    /// Total liquidity of the positions bounded by this tick.
+    pub liquidity_gross: u128,
+    /// Token A fee growth on the other side of this tick from the current price (Q64.64, wrapping).
+    pub fee_growth_outside_a_x64: u128,
+    /// Token B fee growth on the other side of this tick from the current price (Q64.64, wrapping).
+    pub fee_growth_outside_b_x64: u128,
+    /// Whether any position uses this tick.
+    pub initialized: bool,
+}
+
+/// Size of a serialized `Tick`.
+const TICK_SIZE: usize = 16 * 4 + 1;
+
+impl Tick {
+    /// Applies a position's liquidity change at this tick. `upper` marks the tick as the position's upper bound.
+    pub fn update(
+        &mut self,
+        tick_index: i32,
+        tick_current: i32,
+        liquidity_delta: i128,
+        fee_growth_global_a_x64: u128,
+        fee_growth_global_b_x64: u128,
+        upper: bool,
+    ) -> Result<()> {
+        if self.liquidity_gross == 0 {
+            // By convention, all fees before a tick was initialized were earned below it
+            if tick_index <= tick_current {
+                self.fee_growth_outside_a_x64 = fee_growth_global_a_x64;
+                self.fee_growth_outside_b_x64 = fee_growth_global_b_x64;
+            }
+            self.initialized = true;
+        }
+        self.liquidity_gross = add_liquidity_delta(self.liquidity_gross, liquidity_delta)?;
+        self.liquidity_net = if upper {
+            self.liquidity_net.checked_sub(liquidity_delta)
+        } else {
+            self.liquidity_net.checked_add(liquidity_delta)
+        }
+        .ok_or(SwapError::CalculationOverflow)?;
+        Ok(())
+    }
+
+    /// Flips the fee growth outside this tick as the price crosses it and returns its `liquidity_net`.
+    pub fn cross(&mut self, fee_growth_global_a_x64: u128, fee_growth_global_b_x64: u128) -> i128 {
+        self.fee_growth_outside_a_x64 = fee_growth_global_a_x64.wrapping_sub(self.fee_growth_outside_a_x64);
+        self.fee_growth_outside_b_x64 = fee_growth_global_b_x64.wrapping_sub(self.fee_growth_outside_b_x64);
+        self.liquidity_net
+    }
+
+    /// Resets the tick once no position uses it.
+    pub fn clear_if_unused(&mut self) {
+        if self.liquidity_gross == 0 {
+            *self = Tick::default();
+        }
+    }
+}
+
+/// A fixed run of `TICK_ARRAY_SIZE` ticks of a concentrated-liquidity pool, stored in a PDA seeded
+/// from the pool and the index of its first tick.
+#[account]
+#[derive(Default)]
+pub struct TickArray {
+    /// The pool these ticks belong to.
+    pub pool: Pubkey,
+    /// Index of the first tick. A multiple of `tick_spacing * TICK_ARRAY_SIZE`.
+    pub start_tick_index: i32,
+    /// The ticks, `tick_spacing` apart. Always `TICK_ARRAY_SIZE` long.
+    pub ticks: Vec<Tick>,
+}
+
+/// Define the space required for the TickArray account.
+const TICK_ARRAY_ACCOUNT_SIZE: usize = 8 + 32 + 4 + 4 + TICK_SIZE * TICK_ARRAY_SIZE; // = 2128 bytes
+
+impl TickArray {
+    /// Returns the start index of the tick array holding `tick_index`.
+    pub fn start_index_for(tick_index: i32, tick_spacing: u16) -> i32 {
+        let span = tick_spacing as i32 * TICK_ARRAY_SIZE as i32;
+        tick_index.div_euclid(span) * span
+    }
+
+    /// Returns the position of `tick_index` in this array.
+    fn offset(&self, tick_index: i32, tick_spacing: u16) -> Result<usize> {
+        let spacing = tick_spacing as i32;
+        if tick_index % spacing != 0 {
+            return err!(SwapError::InvalidTickRange);
+        }
+        let offset = (tick_index - self.start_tick_index).div_euclid(spacing);
+        if !(0..TICK_ARRAY_SIZE as i32).contains(&offset) {
+            return err!(SwapError::InvalidTickArray);
+        }
+        Ok(offset as usize)
+    }
+
+    pub fn tick(&self, tick_index: i32, tick_spacing: u16) -> Result<&Tick> {
+        let offset = self.offset(tick_index, tick_spacing)?;
+        Ok(&self.ticks[offset])
+    }
+
+    pub fn tick_mut(&mut self, tick_index: i32, tick_spacing: u16) -> Result<&mut Tick> {
+        let offset = self.offset(tick_index, tick_spacing)?;
+        Ok(&mut self.ticks[offset])
+    }
+
+    /// Returns the next initialized tick in this array at or below `tick_index` when the price
+    /// moves down (`a_to_b`), or above it when the price moves up.
+    pub fn next_initialized_tick(&self, tick_index: i32, tick_spacing: u16, a_to_b: bool) -> Option<i32> {
+        let spacing = tick_spacing as i32;
+        let size = TICK_ARRAY_SIZE as i32;
+        let offset = (tick_index - self.start_tick_index).div_euclid(spacing);
+        let initialized = |offset: &i32| self.ticks[*offset as usize].initialized;
+
+        let found = if a_to_b {
+            (0..=offset.min(size - 1)).rev().find(initialized)
+        } else {
+            ((offset + 1).max(0)..size).find(initialized)
+        };
+        found.map(|offset| self.start_tick_index + offset * spacing)
+    }
+}
+
+/// A concentrated-liquidity position over [tick_lower, tick_upper), stored in a PDA seeded from the
+/// pool, the owner and the range.
+#[account]
+#[derive(Default)]
+pub struct Position {
+    /// The pool the position provides liquidity to.
+    pub pool: Pubkey,
+    /// The owner of the position.
+    pub owner: Pubkey,
+    /// Lower bound of the range (inclusive).
+    pub tick_lower: i32,
+    /// Upper bound of the range (exclusive).
+    pub tick_upper: i32,
+    /// The position's liquidity.
+    pub liquidity: u128,
+    /// Token A fee growth inside the range at the last update (Q64.64, wrapping).
+    pub fee_growth_inside_last_a_x64: u128,
+    /// Token B fee growth inside the range at the last update (Q64.64, wrapping).
+    pub fee_growth_inside_last_b_x64: u128,
+    /// Token A fees earned and not yet collected.
+    pub tokens_owed_a: u64,
+    /// Token B fees earned and not yet collected.
+    pub tokens_owed_b: u64,
+    /// The bump seed used for the position's PDA.
+    pub bump: u8,
+}
+
+/// Define the space required for the Position account.
+const POSITION_ACCOUNT_SIZE: usize = 8 + ( 32 * 2 ) + ( 4 * 2 ) + 16 + ( 16 * 2 ) + ( 8 * 2 ) + 1; // = 145 bytes
+
+/// Maximum number of reward streams a farm can emit at once.
+pub const MAX_REWARD_STREAMS: usize = 3;
+
+/// Number of remaining accounts passed per reward stream to `harvest`.
+pub const REWARD_ACCOUNTS_PER_STREAM: usize = 3;
+
+/// A liquidity mining farm, streaming up to `MAX_REWARD_STREAMS` reward tokens to the LP shares
+/// of a pool staked in it. The farm PDA owns the staked LP vault and every reward vault.
+#[account]
+#[derive(Default)]
+pub struct Farm {
+    /// The pool whose LP shares are staked.
+    pub pool: Pubkey,
+    /// The pool's LP share mint.
+    pub lp_mint: Pubkey,
+    /// The farm's vault (ATA) holding the staked LP shares.
+    pub staked_lp_vault: Pubkey,
+    /// The bump seed used for the farm's PDA.
+    pub bump: u8,
+    /// LP shares currently staked in the farm.
+    pub total_staked: u64,
+    /// Number of reward streams in use, filled from the front of `reward_streams`.
+    pub reward_stream_count: u8,
+    /// The farm's reward streams. Slots from `reward_stream_count` on are unused.
+    pub reward_streams: [RewardStream; MAX_REWARD_STREAMS],
+}
+
+impl Farm {
+    /// Accrues the emissions of every reward stream since its last update.
+    pub fn update(&mut self, now: i64) {
+        let count = self.reward_stream_count as usize;
+        for stream in &mut self.reward_streams[..count] {
+            stream.update(now, self.total_staked);
+        }
+    }
+
+    /// Returns the reward stream at `reward_index`, if it is in use.
+    pub fn reward_stream(&self, reward_index: u8) -> Result<&RewardStream> {
+        self.reward_streams[..self.reward_stream_count as usize]
+            .get(reward_index as usize)
+            .ok_or_else(|| error!(SwapError::InvalidRewardStream))
+    }
+
+    /// Returns the reward stream at `reward_index` for updating, if it is in use.
+    pub fn reward_stream_mut(&mut self, reward_index: u8) -> Result<&mut RewardStream> {
+        self.reward_streams[..self.reward_stream_count as usize]
+            .get_mut(reward_index as usize)
+            .ok_or_else(|| error!(SwapError::InvalidRewardStream))
+    }
+
+    /// Starts a new reward stream in the next free slot. Stake positions hold a zero snapshot for
+    /// unused slots, which matches the new stream's zero accumulator.
+    pub fn add_reward_stream(
+        &mut self,
+        reward_mint: Pubkey,
+        reward_vault: Pubkey,
+        reward_per_second: u64,
+        start_ts: i64,
+        end_ts: i64,
+        now: i64,
+    ) -> Result<()> {
+        if start_ts >= end_ts || end_ts <= now {
+            return err!(SwapError::InvalidFarmSchedule);
+        }
+        let slot = self.reward_streams
+            .get_mut(self.reward_stream_count as usize)
+            .ok_or(SwapError::TooManyRewardStreams)?;
+        *slot = RewardStream {
+            reward_mint,
+            reward_vault,
+            reward_per_second,
+            start_ts,
+            end_ts,
+            acc_reward_per_share: 0,
+            last_update_ts: now,
+        };
+        self.reward_stream_count += 1;
+        Ok(())
+    }
+}
+
+/// Define the space required for the Farm account.
+const FARM_ACCOUNT_SIZE: usize = 8 + ( 32 * 3 ) + 1 + 8 + 1 + REWARD_STREAM_SIZE * MAX_REWARD_STREAMS + 24; // = 474 bytes
+
+/// A reward token emitted by a farm at a fixed rate over its schedule.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default)]
+pub struct RewardStream {
+    /// The mint of the reward token.
+    pub reward_mint: Pubkey,
+    /// The farm's vault (ATA) holding the rewards not yet harvested.
+    pub reward_vault: Pubkey,
+    /// Reward tokens emitted per second, shared by all staked LP shares.
+    pub reward_per_second: u64,
+    /// The unix timestamp emissions start at.
+    pub start_ts: i64,
+    /// The unix timestamp emissions end at.
+    pub end_ts: i64,
+    /// Rewards emitted per staked LP share (Q64.64, wrapping).
+    pub acc_reward_per_share: u128,
+    /// The unix timestamp of the last accumulator update.
+    pub last_update_ts: i64,
+}
+
+/// Define the space required for a RewardStream.
+const REWARD_STREAM_SIZE: usize = ( 32 * 2 ) + 8 + ( 8 * 2 ) + 16 + 8; // = 112 bytes
+
+impl RewardStream {
+    /// Accrues the emissions since the last update into `acc_reward_per_share`. While nothing is
+    /// staked the stream emits nothing and its end moves back by the idle time instead, so the
+    /// rewards funded for that time are emitted later rather than left in the reward vault.
+    pub fn update(&mut self, now: i64, total_staked: u64) {
+        let from = self.last_update_ts.max(self.start_ts);
+        let to = now.min(self.end_ts);
+        if to > from {
+            if total_staked > 0 {
+                let reward = self.reward_per_second as u128 * (to - from) as u128;
+                let growth = (U256::from(reward) << 64) / U256::from(total_staked);
+                // Overflow of the accumulator is intended, stake positions only use differences
+                self.acc_reward_per_share = self.acc_reward_per_share.wrapping_add(growth.low_u128());
+            } else {
+                self.end_ts = self.end_ts.saturating_add(to - from);
+            }
+        }
+        self.last_update_ts = self.last_update_ts.max(now);
+    }
+}
+
+/// LP shares a user has staked in a farm, and the rewards they earned from each stream.
+#[account]
+#[derive(Default)]
+pub struct StakePosition {
+    /// The farm the shares are staked in.
+    pub farm: Pubkey,
+    /// The owner of the staked shares.
+    pub owner: Pubkey,
+    /// LP shares staked.
+    pub amount: u64,
+    /// Each stream's `acc_reward_per_share` at the last update (Q64.64, wrapping).
+    pub reward_per_share_last: [u128; MAX_REWARD_STREAMS],
+    /// Rewards earned from each stream and not yet harvested.
+    pub rewards_owed: [u64; MAX_REWARD_STREAMS],
+    /// The bump seed used for the stake position's PDA.
+    pub bump: u8,
+}
+
+impl StakePosition {
+    /// Moves the rewards earned from every stream since the last update into `rewards_owed`.
+    /// The farm must have been updated first.
+    pub fn settle(&mut self, farm: &Farm) -> Result<()> {
+        for (reward_index, stream) in farm.reward_streams.iter().enumerate() {
+            let last = &mut self.reward_per_share_last[reward_index];
+            let earned = growth_earned(self.amount as u128, stream.acc_reward_per_share, *last);
+            self.rewards_owed[reward_index] = self.rewards_owed[reward_index]
+                .checked_add(earned)
+                .ok_or(SwapError::CalculationOverflow)?;
+            *last = stream.acc_reward_per_share;
+        }
+        Ok(())
+    }
+}
+
+/// Define the space required for the StakePosition account.
+const STAKE_POSITION_ACCOUNT_SIZE: usize = 8 + ( 32 * 2 ) + 8 + ( 16 * MAX_REWARD_STREAMS ) + ( 8 * MAX_REWARD_STREAMS ) + 1; // = 153 bytes
+
+/// Longest allowed lock (4 years). A lock this long starts with voting power equal to its amount.
+pub const MAX_LOCK_DURATION_SECS: i64 = 4 * 365 * 24 * 60 * 60;
+
+/// The vote-escrow singleton, holding every locked governance token in its vault.
+#[account]
+#[derive(Default)]
+pub struct VeEscrow {
+    /// The mint of the governance token.
+    pub governance_mint: Pubkey,
+    /// The escrow's vault (ATA) holding the locked tokens.
+    pub vault: Pubkey,
+    /// The bump seed used for the escrow's PDA.
+    pub bump: u8,
+    /// Governance tokens currently locked.
+    pub total_locked: u64,
+}
+
+/// Define the space required for the VeEscrow account.
+const VE_ESCROW_ACCOUNT_SIZE: usize = 8 + ( 32 * 2 ) + 1 + 8 + 24; // = 105 bytes
+
+/// Governance tokens a user locked in the vote-escrow, and until when.
+#[account]
+#[derive(Default)]
+pub struct VeLock {
+    /// The owner of the locked tokens.
+    pub owner: Pubkey,
+    /// Governance tokens locked.
+    pub amount: u64,
+    /// The unix timestamp the tokens can be withdrawn from.
+    pub unlock_ts: i64,
+    /// The bump seed used for the lock's PDA.
+    pub bump: u8,
+}
+
+impl VeLock {
+    /// Returns the lock's voting power at `now`, decaying linearly from
+    /// `amount * remaining / MAX_LOCK_DURATION_SECS` to zero at `unlock_ts`.
+    pub fn voting_power(&self, now: i64) -> u64 {
+        if now >= self.unlock_ts {
+            return 0;
+        }
+        let remaining = (self.unlock_ts - now) as u128;
+        (self.amount as u128 * remaining / MAX_LOCK_DURATION_SECS as u128) as u64
+    }
+}
+
+/// Define the space required for the VeLock account.
+const VE_LOCK_ACCOUNT_SIZE: usize = 8 + 32 + 8 + 8 + 1; // = 57 bytes
+
+/// Upper bound for the config's `change_delay_secs` (30 days).
+pub const MAX_CHANGE_DELAY_SECS: u32 = 30 * 24 * 60 * 60;
+
+/// Basis point denominator (100% = 10_000 bps).
+pub(crate) const BPS_DENOMINATOR: u128 = 10_000;
+
+/// Maximum number of pools a single `route_swap` may walk through.
+pub const MAX_ROUTE_HOPS: u8 = 4;
+
+/// Number of remaining accounts passed per hop to `route_swap`.
+pub const ROUTE_ACCOUNTS_PER_HOP: usize = 7;
+
+/// Instruction discriminator of a receiver's `flash_swap_callback`, as Anchor derives it:
+/// the first 8 bytes of sha256("global:flash_swap_callback").
+pub const FLASH_SWAP_CALLBACK_DISCRIMINATOR: [u8; 8] = [225, 54, 80, 1, 45, 208, 202, 124];
+
+/// Positions of the pool and the vault in the `FlashLoan` accounts, read from a `flash_repay`
+/// instruction by `flash_borrow`.
+const FLASH_LOAN_POOL_INDEX: usize = 0;
+const FLASH_LOAN_VAULT_INDEX: usize = 3;
+
+/// Bounds for a StableSwap pool's amplification coefficient.
+pub const MIN_AMP: u64 = 1;
+pub const MAX_AMP: u64 = 1_000_000;
+
+/// Minimum weight of either token in a weighted pool (1%).
+pub const MIN_WEIGHT_BPS: u64 = 100;
+
+/// Maximum factor a single `ramp_amp` may raise or lower amp by.
+pub const MAX_AMP_CHANGE: u64 = 10;
+
+/// Minimum duration of an amp ramp (1 day).
+pub const MIN_AMP_RAMP_DURATION_SECS: i64 = 24 * 60 * 60;
+
+/// Iteration cap for the StableSwap Newton solvers.
+pub(crate) const STABLE_MAX_ITERATIONS: usize = 255;
+
+/// Upper bound for the config's `max_fee_bps` (10%).
+pub const MAX_FEE_BPS: u16 = 1_000;
+
+/// LP shares locked by a pool's first deposit, which keeps the share price from being inflated
+/// by donations while the supply is tiny.
+pub const MINIMUM_LIQUIDITY: u64 = 1_000;
+
+/// Decimals used for every pool's LP share mint.
+const LP_MINT_DECIMALS: u8 = 6;
+
+/// Defines the accounts required for the `initialize_config` instruction.
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    /// The ProgramConfig singleton to be created.
+    #[account(
+        init,
+        payer = admin,
+        seeds = [b"config"],
+        bump,
+        space = CONFIG_ACCOUNT_SIZE,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// The protocol admin (signer and payer).
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Defines the accounts required for the `update_config` instruction.
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ SwapError::Unauthorized,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// The protocol admin (signer).
+    pub admin: Signer<'info>,
+}
+
+/// Defines the accounts required for the `accept_config_admin` instruction.
+#[derive(Accounts)]
+pub struct AcceptConfigAdmin<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = config.pending_admin == new_admin.key() @ SwapError::Unauthorized,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// The proposed protocol admin (signer).
+    pub new_admin: Signer<'info>,
+}
+
+/// Defines the accounts required for the `initialize_pool` instruction.
+#[derive(Accounts)]
+pub struct InitializePool<'info> {
+    /// The global program config, providing the default parameters for the new pool.
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// The mint account for Token A. Must be passed by the client.
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+    /// The mint account for Token B. Must be passed by the client.
+    /// Must sort after Token A, so there is exactly one pool per pair.
+    #[account(
+        constraint = token_a_mint.key() < token_b_mint.key() @ SwapError::NonCanonicalMintOrder,
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    /// The LiquidityPool account to be created.
+    #[account(
+        init,
+        payer = initializer,
+        seeds = [
+            b"pool",
+            token_a_mint.key().as_ref(),
+            token_b_mint.key().as_ref(),
+        ],
+        bump,
+        space = POOL_ACCOUNT_SIZE,
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+
+    /// CHECK: The authority PDA for the pool.
+    #[account(
+        seeds = [
+            b"pool",
+            token_a_mint.key().as_ref(),
+            token_b_mint.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    /// The associated token account (vault) for Token A, owned by the pool_authority PDA.
+    #[account(
+        init,
+        payer = initializer,
+        associated_token::mint = token_a_mint,
+        associated_token::authority = pool_authority, // Anchor ensures this authority matches the pool_authority account provided
+    )]
+    pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The associated token account (vault) for Token B, owned by the pool_authority PDA.
+    #[account(
+        init,
+        payer = initializer,
+        associated_token::mint = token_b_mint,
+        associated_token::authority = pool_authority, // Anchor ensures this authority matches the pool_authority account provided
+    )]
+    pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The LP share mint for this pool, with the pool_authority PDA as mint authority.
+    #[account(
+        init,
+        payer = initializer,
+        seeds = [
+            b"lp_mint",
+            pool.key().as_ref(),
+        ],
+        bump,
+        mint::decimals = LP_MINT_DECIMALS,
+        mint::authority = pool_authority,
+        mint::token_program = token_program,
+    )]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+
+    /// The pool's observation ring buffer, created with a single slot.
+    #[account(
+        init,
+        payer = initializer,
+        seeds = [
+            b"observations",
+            pool.key().as_ref(),
+        ],
+        bump,
+        space = PoolObservations::space(1),
+    )]
+    pub observations: Account<'info, PoolObservations>,
+
+    /// The user initializing the pool (signer and payer).
+    #[account(mut)]
+    pub initializer: Signer<'info>,
+
+    // System Accounts
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Defines the accounts required for the `migrate_pool` instruction.
+#[derive(Accounts)]
+pub struct MigratePool<'info> {
+    /// The global program config, providing the defaults for the migrated pool.
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ SwapError::Unauthorized,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    /// CHECK: A pool with the original layout, too short to load as a `LiquidityPool` until the
+    /// handler grows it. Its address must match the pool seeds.
+    #[account(
+        mut,
+        owner = crate::ID,
+        seeds = [
+            b"pool",
+            token_a_mint.key().as_ref(),
+            token_b_mint.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub pool: UncheckedAccount<'info>,
+
+    /// The pool's Token A vault, checked against the pool state in the handler.
+    pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The pool's Token B vault, checked against the pool state in the handler.
+    pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The LP share mint for this pool, with the pool PDA as mint authority.
+    #[account(
+        init,
+        payer = admin,
+        seeds = [
+            b"lp_mint",
+            pool.key().as_ref(),
+        ],
+        bump,
+        mint::decimals = LP_MINT_DECIMALS,
+        mint::authority = pool,
+        mint::token_program = token_program,
+    )]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+
+    /// The pool's observation ring buffer, created with a single slot.
+    #[account(
+        init,
+        payer = admin,
+        seeds = [
+            b"observations",
+            pool.key().as_ref(),
+        ],
+        bump,
+        space = PoolObservations::space(1),
+    )]
+    pub observations: Account<'info, PoolObservations>,
+
+    /// The protocol admin (signer), who pays for the extra space and becomes the pool admin.
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Defines the accounts required for the `swap` instruction.
+#[derive(Accounts)]
+pub struct Swap<'info> {
+    /// The mint account for the token being sent *in*.
+    pub source_mint: InterfaceAccount<'info, Mint>,
+    /// The mint account for the token being sent *out*.
+    pub destination_mint: InterfaceAccount<'info, Mint>,
+
+    /// The LiquidityPool account containing the state for this swap.
+    /// Mutable so protocol fees can accrue.
+    #[account(
+        mut,
+        // REMOVED seeds and bump validation from here.
+        // We validate the pool implicitly through the pool_authority check and vault constraints.
+        // --- Security Constraints ---
+        // These constraints ensure the provided vaults match the addresses stored in the pool state.
+        constraint = token_a_vault.key() == pool.token_a_vault @ SwapError::InvalidVault,
+        constraint = token_b_vault.key() == pool.token_b_vault @ SwapError::InvalidVault,
+        constraint = (pool.token_a_mint == source_mint.key() && pool.token_b_mint == destination_mint.key()) || 
+                    (pool.token_a_mint == destination_mint.key() && pool.token_b_mint == source_mint.key()) 
+                    @ SwapError::InvalidMint,
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+
+    /// CHECK: The authority PDA for the pool. Required for signing outgoing transfers.
+    #[account(
+        seeds = [
+            b"pool", 
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    /// The pool's observation ring buffer.
+    #[account(
+        mut,
+        seeds = [
+            b"observations",
+            pool.key().as_ref(),
+        ],
+        bump = observations.bump,
+    )]
+    pub observations: Account<'info, PoolObservations>,
+
+    /// The user's token account for the token they are sending *in*.
+    #[account(
+        mut,
+        constraint = user_source_token_account.owner == user_authority.key() @ SwapError::InvalidOwner,
+        constraint = user_source_token_account.mint == source_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub user_source_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The user's token account for the token they are receiving *out*.
+    #[account(
+        mut,
+        constraint = user_destination_token_account.owner == user_authority.key() @ SwapError::InvalidOwner,
+        constraint = user_destination_token_account.mint == destination_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub user_destination_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The pool's vault for Token A. Needs to be mutable for balance changes.
+    #[account(
+        mut,
+        // Constraint to ensure this vault belongs to the validated pool authority PDA.
+        constraint = token_a_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+        // Constraint ensuring the vault's mint matches the pool state's mint A.
+        constraint = token_a_vault.mint == pool.token_a_mint @ SwapError::InvalidMint,
+    )]
+    pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The pool's vault for Token B. Needs to be mutable for balance changes.
+    #[account(
+        mut,
+        // Constraint to ensure this vault belongs to the validated pool authority PDA.
+        constraint = token_b_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+        // Constraint ensuring the vault's mint matches the pool state's mint B.
+        constraint = token_b_vault.mint == pool.token_b_mint @ SwapError::InvalidMint,
+    )]
+    pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The user performing the swap (signer).
+    #[account(mut)]
+    pub user_authority: Signer<'info>,
+
+    // System Accounts
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+/// Direction of a quoted swap.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SwapDirection {
+    /// Send Token A, receive Token B.
+    AToB,
+    /// Send Token B, receive Token A.
+    BToA,
+}
+
+/// One of a pool's two tokens.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PoolToken {
+    /// Token A.
+    A,
+    /// Token B.
+    B,
+}
+
+/// Result of a swap quote, returned via return data by `quote_swap` and `quote_swap_exact_out`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct SwapQuote {
+    /// The amount of tokens sent into the pool.
+    pub amount_in: u64,
+    /// The amount of tokens sent out of the pool.
+    pub amount_out: u64,
+    /// The portion of `amount_in` kept by the pool as fee.
+    pub fee_amount: u64,
+    /// The share of the output reserve taken by the swap, in basis points.
+    pub price_impact_bps: u64,
+}
+
+/// A snapshot of a pool's price accumulators, used to compute a TWAP between two points in time.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default)]
+pub struct PriceObservation {
+    /// The unix timestamp the accumulators were read at.
+    pub timestamp: i64,
+    /// Cumulative price of Token A in Token B (Q64.64).
+    pub price_a_cumulative: u128,
+    /// Cumulative price of Token B in Token A (Q64.64).
+    pub price_b_cumulative: u128,
+}
+
+/// Time-weighted average prices returned by `get_twap`, both in Q64.64 fixed point.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct TwapPrices {
+    /// Average price of Token A in Token B.
+    pub price_a: u128,
+    /// Average price of Token B in Token A.
+    pub price_b: u128,
+}
+
+/// Time-weighted averages returned by `observe_twap`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct ObservedTwap {
+    /// Average price of Token A in Token B (Q64.64).
+    pub price_a: u128,
+    /// Average price of Token B in Token A (Q64.64).
+    pub price_b: u128,
+    /// Average pool liquidity, sqrt(reserve_a * reserve_b).
+    pub liquidity: u128,
+}
+
+/// Reserves of a pool seen from the direction of a swap.
+pub struct SwapReserves {
+    /// True when the user sends Token A and receives Token B.
+    pub is_a_to_b: bool,
+    /// Reserve of the token being sent *in*, excluding protocol fees owed.
+    pub reserve_in: u64,
+    /// Reserve of the token being sent *out*, excluding protocol fees owed.
+    pub reserve_out: u64,
+}
+
+impl SwapReserves {
+    /// Returns the reserves in pool order (Token A, Token B).
+    pub fn a_b(&self) -> (u64, u64) {
+        if self.is_a_to_b {
+            (self.reserve_in, self.reserve_out)
+        } else {
+            (self.reserve_out, self.reserve_in)
+        }
+    }
+}
+
+impl<'info> Swap<'info> {
+    /// Validates the swap direction and returns the current reserves for it.
+    fn load_reserves(&mut self) -> Result<SwapReserves> {
+        let pool = &self.pool;
+
+        // --- Input Validation ---
+        // Ensure the user's source token account mint matches one of the pool's tokens
+        // This check is partially redundant due to constraints but good for clarity
+        if self.user_source_token_account.mint != pool.token_a_mint && self.user_source_token_account.mint != pool.token_b_mint {
+            return err!(SwapError::InvalidMint);
+        }
+
+        // User is sending Token A (and receiving Token B) when the source mint matches mint A
+        let is_a_to_b = self.user_source_token_account.mint == pool.token_a_mint;
+
+        // --- Destination Mint Check ---
+        // Ensure the user's destination token account matches the mint of the pool's destination vault
+        // This check is partially redundant due to constraints but good for clarity
+        let dest_vault_account = if is_a_to_b { &self.token_b_vault } else { &self.token_a_vault };
+        if self.user_destination_token_account.mint != dest_vault_account.mint {
+            return err!(SwapError::InvalidDestinationMint);
+        }
+
+        // --- Get Reserves ---
+        // Reload vault accounts to get the latest balance data on-chain
+        // It's crucial to reload *before* calculations to prevent race conditions.
+        self.token_a_vault.reload()?;
+        self.token_b_vault.reload()?;
+        self.pool.swap_reserves(self.token_a_vault.amount, self.token_b_vault.amount, is_a_to_b)
+    }
+
+    /// Moves `amount_in` from the user into the pool and `amount_out` from the pool to the user,
+    /// accrues the protocol's share of `fee_amount` and emits a `SwapEvent`.
+    fn settle(&mut self, is_a_to_b: bool, amount_in: u64, amount_out: u64, fee_amount: u64) -> Result<()> {
+        let (source_vault_account, dest_vault_account) = if is_a_to_b {
+            (&self.token_a_vault, &self.token_b_vault)
+        } else {
+            (&self.token_b_vault, &self.token_a_vault)
+        };
+
+        // --- Perform Transfers via CPI ---
+
+        // 1. Transfer IN: User -> Pool Source Vault
+        let transfer_in_accounts = TransferChecked {
+            from: self.user_source_token_account.to_account_info(), // User's source ATA
+            mint: self.source_mint.to_account_info(), // Mint of the token being sent in
+            to: source_vault_account.to_account_info(), // Pool's vault for receiving the token
+            authority: self.user_authority.to_account_info(), // User signing the transaction
+        };
+        let transfer_in_cpi = CpiContext::new(
+            self.token_program.to_account_info(), // Target program (Token Program)
+            transfer_in_accounts, // Accounts required by transfer_checked
+        );
+        // Execute the CPI
+        transfer_checked(transfer_in_cpi, amount_in, self.source_mint.decimals)?;
+
+
+        // 2. Transfer OUT: Pool Destination Vault -> User
+        // Define the PDA signer seeds for the pool authority
+        // Use the bump stored in the pool account state
+        let pool = &self.pool;
+        let pool_bump_slice = &[pool.bump];
+        let pool_signer_seeds: &[&[u8]] = &[
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+            pool_bump_slice
+        ];
+        // Add another layer of &[&[u8]] for the signer seeds argument
+        let signer = &[pool_signer_seeds];
+
+
+        let transfer_out_accounts = TransferChecked {
+            from: dest_vault_account.to_account_info(), // Pool's vault sending the token
+            mint: self.destination_mint.to_account_info(), // Mint of the token being sent out
+            to: self.user_destination_token_account.to_account_info(), // User's destination ATA
+            authority: self.pool_authority.to_account_info(), // The pool's PDA authority
+        };
+        // Create CPI context *with signer* because the authority is a PDA
+        let transfer_out_cpi = CpiContext::new_with_signer(
+            self.token_program.to_account_info(), // Target program (Token Program)
+            transfer_out_accounts, // Accounts required by transfer_checked
+            signer, // Pass the &[&[&[u8]]] signer seeds
+        );
+        // Execute the CPI
+        transfer_checked(transfer_out_cpi, amount_out, self.destination_mint.decimals)?;
+
+        // --- Accrue Protocol Fee ---
+        // The protocol's share of the fee was paid in the input token
+        let protocol_fee = calculate_protocol_fee(fee_amount, self.pool.protocol_fee_share_bps)?;
+        let pool = &mut self.pool;
+        if is_a_to_b {
+            pool.protocol_fees_owed_a = pool.protocol_fees_owed_a.checked_add(protocol_fee).ok_or(SwapError::CalculationOverflow)?;
+        } else {
+            pool.protocol_fees_owed_b = pool.protocol_fees_owed_b.checked_add(protocol_fee).ok_or(SwapError::CalculationOverflow)?;
+        }
+
+        // --- Emit Event ---
+        // Log the details of the swap event
+        emit!(SwapEvent {
+            pool: self.pool.key(),
+            user: self.user_authority.key(),
+            amount_in,
+            amount_out,
+            fee_amount,
+            source_mint: self.source_mint.key(),
+            destination_mint: self.destination_mint.key()
+        });
+
+        Ok(()) // Indicate successful execution
+    }
+}
+
+
+/// Defines the accounts required for the read-only `quote_swap` and `quote_swap_exact_out` instructions.
+#[derive(Accounts)]
+pub struct QuoteSwap<'info> {
+    #[account(
+        constraint = token_a_vault.key() == pool.token_a_vault @ SwapError::InvalidVault,
+        constraint = token_b_vault.key() == pool.token_b_vault @ SwapError::InvalidVault,
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+
+    /// The pool's vault for Token A.
+    pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The pool's vault for Token B.
+    pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
+}
+
+/// Defines the accounts required for the read-only `get_twap` instruction.
+#[derive(Accounts)]
+pub struct GetTwap<'info> {
+    #[account(
+        constraint = token_a_vault.key() == pool.token_a_vault @ SwapError::InvalidVault,
+        constraint = token_b_vault.key() == pool.token_b_vault @ SwapError::InvalidVault,
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+
+    /// The pool's vault for Token A.
+    pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The pool's vault for Token B.
+    pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
+}
+
+/// Defines the accounts required for the read-only `observe_twap` instruction.
+#[derive(Accounts)]
+pub struct ObserveTwap<'info> {
+    #[account(
+        constraint = token_a_vault.key() == pool.token_a_vault @ SwapError::InvalidVault,
+        constraint = token_b_vault.key() == pool.token_b_vault @ SwapError::InvalidVault,
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+
+    /// The pool's observation ring buffer.
+    #[account(
+        seeds = [
+            b"observations",
+            pool.key().as_ref(),
+        ],
+        bump = observations.bump,
+    )]
+    pub observations: Account<'info, PoolObservations>,
+
+    /// The pool's vault for Token A.
+    pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The pool's vault for Token B.
+    pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
+}
+
+/// Defines the accounts required for the `increase_observation_cardinality` instruction.
+#[derive(Accounts)]
+#[instruction(new_cardinality: u16)]
+pub struct IncreaseObservationCardinality<'info> {
+    pub pool: Account<'info, LiquidityPool>,
+
+    /// The pool's observation ring buffer, reallocated to hold `new_cardinality` entries.
+    #[account(
+        mut,
+        seeds = [
+            b"observations",
+            pool.key().as_ref(),
+        ],
+        bump = observations.bump,
+        realloc = PoolObservations::space(new_cardinality as usize),
+        realloc::payer = payer,
+        realloc::zero = false,
+    )]
+    pub observations: Account<'info, PoolObservations>,
+
+    /// Whoever pays for the extra space.
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Defines the accounts required for the `route_swap` instruction.
+/// The pools, vaults, mints and observation buffers of every hop are passed as remaining accounts.
+#[derive(Accounts)]
+pub struct RouteSwap<'info> {
+    /// The user's token account for the token they are sending into the first pool.
+    #[account(
+        mut,
+        constraint = user_source_token_account.owner == user_authority.key() @ SwapError::InvalidOwner,
+    )]
+    pub user_source_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The user's token account for the token they receive from the last pool.
+    #[account(
+        mut,
+        constraint = user_destination_token_account.owner == user_authority.key() @ SwapError::InvalidOwner,
+    )]
+    pub user_destination_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The user performing the swap (signer).
+    #[account(mut)]
+    pub user_authority: Signer<'info>,
+
+    // System Accounts
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+/// Defines the accounts required for `flash_swap`. The accounts the receiver program needs are
+/// passed as remaining accounts and handed to its callback unchanged.
+#[derive(Accounts)]
+pub struct FlashSwap<'info> {
+    /// The LiquidityPool account to borrow from. Mutable for the lock, the oracle and protocol fees.
+    #[account(
+        mut,
+        constraint = token_a_vault.key() == pool.token_a_vault @ SwapError::InvalidVault,
+        constraint = token_b_vault.key() == pool.token_b_vault @ SwapError::InvalidVault,
+        constraint = token_a_mint.key() == pool.token_a_mint @ SwapError::InvalidMint,
+        constraint = token_b_mint.key() == pool.token_b_mint @ SwapError::InvalidMint,
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+
+    /// CHECK: The authority PDA for the pool. Required for signing outgoing transfers.
+    #[account(
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    /// The pool's observation ring buffer.
+    #[account(
+        mut,
+        seeds = [
+            b"observations",
+            pool.key().as_ref(),
+        ],
+        bump = observations.bump,
+    )]
+    pub observations: Account<'info, PoolObservations>,
+
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    /// The pool's vault for Token A.
+    #[account(
+        mut,
+        constraint = token_a_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+    )]
+    pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The pool's vault for Token B.
+    #[account(
+        mut,
+        constraint = token_b_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+    )]
+    pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Receives the borrowed Token A. May be owned by the receiver program.
+    #[account(
+        mut,
+        constraint = receiver_token_a_account.mint == pool.token_a_mint @ SwapError::InvalidMint,
+    )]
+    pub receiver_token_a_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Receives the borrowed Token B. May be owned by the receiver program.
+    #[account(
+        mut,
+        constraint = receiver_token_b_account.mint == pool.token_b_mint @ SwapError::InvalidMint,
+    )]
+    pub receiver_token_b_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: The program whose `flash_swap_callback` instruction is invoked. Calling back into
+    /// this program is rejected, the pool lock covers any deeper re-entry.
+    #[account(
+        executable,
+        constraint = receiver_program.key() != crate::ID @ SwapError::InvalidFlashSwapReceiver,
+    )]
+    pub receiver_program: UncheckedAccount<'info>,
+
+    /// The user initiating the flash swap (signer).
+    pub user_authority: Signer<'info>,
+
+    // System Accounts
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+/// Arguments of the `flash_swap_callback` instruction a flash swap receiver implements, serialized
+/// after `FLASH_SWAP_CALLBACK_DISCRIMINATOR`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct FlashSwapCallback {
+    /// The pool the tokens were borrowed from.
+    pub pool: Pubkey,
+    /// The signer of the `flash_swap` instruction.
+    pub initiator: Pubkey,
+    /// The amount of Token A sent to the receiver.
+    pub amount_a_out: u64,
+    /// The amount of Token B sent to the receiver.
+    pub amount_b_out: u64,
+    /// The pool's fee, charged on whatever is paid back beyond the borrowed amounts.
+    pub fee_bps: u16,
+    /// Opaque data passed through from the `flash_swap` caller.
+    pub data: Vec<u8>,
+}
+
+/// Defines the accounts required for `flash_borrow` and `flash_repay`. The order of `pool` and
+/// `vault` is fixed, see `FLASH_LOAN_POOL_INDEX` and `FLASH_LOAN_VAULT_INDEX`.
+#[derive(Accounts)]
+pub struct FlashLoan<'info> {
+    /// The LiquidityPool account lending from its vault.
+    #[account(mut)]
+    pub pool: Account<'info, LiquidityPool>,
+
+    /// CHECK: The authority PDA for the pool. Required for signing outgoing transfers.
+    #[account(
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    /// The mint of the borrowed token.
+    #[account(constraint = mint.key() == vault.mint @ SwapError::InvalidMint)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// The pool's vault for the borrowed token.
+    #[account(
+        mut,
+        constraint = vault.key() == pool.token_a_vault || vault.key() == pool.token_b_vault @ SwapError::InvalidVault,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The user's token account receiving the loan and paying it back.
+    #[account(
+        mut,
+        constraint = user_token_account.owner == user_authority.key() @ SwapError::InvalidOwner,
+        constraint = user_token_account.mint == mint.key() @ SwapError::InvalidMint,
+    )]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The borrower (signer).
+    pub user_authority: Signer<'info>,
+
+    /// CHECK: The instructions sysvar, used to find the `flash_repay` of a borrow.
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: AccountInfo<'info>,
+
+    // System Accounts
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct AddLiquidity<'info> {
+    /// Mutable so the price oracle can be updated.
+    #[account(
+        mut,
+        constraint = token_a_vault.key() == pool.token_a_vault @ SwapError::InvalidVault,
+
+        constraint = token_b_vault.key() == pool.token_b_vault @ SwapError::InvalidVault,
+
+        constraint = lp_mint.key() == pool.lp_mint @ SwapError::InvalidLpMint,
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+
+    /// CHECK: This is the pool authority PDA that's derived deterministically.
+    #[account(
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+        ],
+        bump,
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    /// The pool's observation ring buffer.
+    #[account(
+        mut,
+        seeds = [
+            b"observations",
+            pool.key().as_ref(),
+        ],
+        bump = observations.bump,
+    )]
+    pub observations: Account<'info, PoolObservations>,
+
+    #[account(
+        constraint = token_a_mint.key() == pool.token_a_mint @ SwapError::InvalidMint,
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = token_b_mint.key() == pool.token_b_mint @ SwapError::InvalidMint,
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = user_token_a_account.owner == user_authority.key() @ SwapError::InvalidOwner,
+        constraint = user_token_a_account.mint == token_a_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub user_token_a_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = user_token_b_account.owner == user_authority.key() @
+        SwapError::InvalidOwner,
+
+        constraint = user_token_b_account.mint == token_b_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub user_token_b_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_a_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+    )]
+    pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_b_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+    )]
+    pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The pool's LP share mint. Mutable because the supply grows on deposit.
+    #[account(mut)]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+
+    /// The user's token account receiving the minted LP shares.
+    #[account(
+        mut,
+        constraint = user_lp_token_account.mint == lp_mint.key() @ SwapError::InvalidLpMint,
+    )]
+    pub user_lp_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub user_authority: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+/// Defines the accounts required for the `remove_liquidity` instruction.
+#[derive(Accounts)]
+pub struct RemoveLiquidity<'info> {
+    /// Mutable so the price oracle can be updated.
+    #[account(
+        mut,
+        constraint = token_a_vault.key() == pool.token_a_vault @ SwapError::InvalidVault,
+        constraint = token_b_vault.key() == pool.token_b_vault @ SwapError::InvalidVault,
+        constraint = lp_mint.key() == pool.lp_mint @ SwapError::InvalidLpMint,
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+
+    /// CHECK: The authority PDA for the pool. Required for signing outgoing transfers.
+    #[account(
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    /// The pool's observation ring buffer.
+    #[account(
+        mut,
+        seeds = [
+            b"observations",
+            pool.key().as_ref(),
+        ],
+        bump = observations.bump,
+    )]
+    pub observations: Account<'info, PoolObservations>,
+
+    #[account(
+        constraint = token_a_mint.key() == pool.token_a_mint @ SwapError::InvalidMint,
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = token_b_mint.key() == pool.token_b_mint @ SwapError::InvalidMint,
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    /// The user's token account receiving Token A.
+    #[account(
+        mut,
+        constraint = user_token_a_account.owner == user_authority.key() @ SwapError::InvalidOwner,
+        constraint = user_token_a_account.mint == token_a_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub user_token_a_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The user's token account receiving Token B.
+    #[account(
+        mut,
+        constraint = user_token_b_account.owner == user_authority.key() @ SwapError::InvalidOwner,
+        constraint = user_token_b_account.mint == token_b_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub user_token_b_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_a_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+    )]
+    pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_b_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+    )]
+    pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The pool's LP share mint. Mutable because the supply shrinks on burn.
+    #[account(mut)]
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+
+    /// The user's token account holding the LP shares to burn.
+    #[account(
+        mut,
+        constraint = user_lp_token_account.owner == user_authority.key() @ SwapError::InvalidOwner,
+        constraint = user_lp_token_account.mint == lp_mint.key() @ SwapError::InvalidLpMint,
+    )]
+    pub user_lp_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub user_authority: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+/// Defines the accounts required for the `set_pool_status` instruction.
+#[derive(Accounts)]
+pub struct SetPoolStatus<'info> {
+    #[account(
+        mut,
+        has_one = admin @ SwapError::Unauthorized,
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+
+    /// The pool admin (signer).
+    pub admin: Signer<'info>,
+}
+
+/// Defines the accounts required for the pool admin instructions `propose_admin` and `cancel_admin_transfer`.
+#[derive(Accounts)]
+pub struct ManagePool<'info> {
+    #[account(
+        mut,
+        has_one = admin @ SwapError::Unauthorized,
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+
+    /// The pool admin (signer).
+    pub admin: Signer<'info>,
+}
+
+/// Defines the accounts required for the `accept_admin` instruction.
+#[derive(Accounts)]
+pub struct AcceptPoolAdmin<'info> {
+    #[account(
+        mut,
+        constraint = pool.pending_admin == new_admin.key() @ SwapError::Unauthorized,
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+
+    /// The proposed pool admin (signer).
+    pub new_admin: Signer<'info>,
+}
+
+/// Defines the accounts required for the `queue_change` instruction.
+#[derive(Accounts)]
+#[instruction(change: PoolParamChange)]
+pub struct QueueChange<'info> {
+    /// The global program config, providing the timelock delay and parameter bounds.
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// The pool the change applies to. `queue_change` checks `admin` against it or the config.
+    pub pool: Account<'info, LiquidityPool>,
+
+    /// The queued change. Fails to initialize if a change of the same kind is already pending.
+    #[account(
+        init,
+        payer = admin,
+        seeds = [b"pending_change", pool.key().as_ref(), &[change.kind()]],
+        bump,
+        space = PENDING_CHANGE_ACCOUNT_SIZE,
+    )]
+    pub pending_change: Account<'info, PendingChange>,
+
+    /// The pool admin, or the protocol admin for a protocol fee share change (signer and payer).
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Defines the accounts required for the `execute_change` instruction.
+#[derive(Accounts)]
+pub struct ExecuteChange<'info> {
+    #[account(mut)]
+    pub pool: Account<'info, LiquidityPool>,
+
+    #[account(
+        mut,
+        seeds = [b"pending_change", pool.key().as_ref(), &[pending_change.change.kind()]],
+        bump = pending_change.bump,
+        has_one = pool,
+        has_one = proposer,
+        close = proposer,
+    )]
+    pub pending_change: Account<'info, PendingChange>,
+
+    /// CHECK: The admin who queued the change, only receives the closed account's rent.
+    #[account(mut)]
+    pub proposer: UncheckedAccount<'info>,
+}
+
+/// Defines the accounts required for the `cancel_change` instruction.
+#[derive(Accounts)]
+pub struct CancelChange<'info> {
+    /// The global program config, naming the protocol admin.
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// The pool the change applies to. `cancel_change` checks `admin` against it or the config.
+    pub pool: Account<'info, LiquidityPool>,
+
+    #[account(
+        mut,
+        seeds = [b"pending_change", pool.key().as_ref(), &[pending_change.change.kind()]],
+        bump = pending_change.bump,
+        has_one = pool,
+        close = admin,
+    )]
+    pub pending_change: Account<'info, PendingChange>,
+
+    /// The pool admin, or the protocol admin for a protocol fee share change (signer).
+    /// Receives the closed account's rent.
+    #[account(mut)]
+    pub admin: Signer<'info>,
+}
+
+/// Defines the accounts required for the `collect_protocol_fees` instruction.
+#[derive(Accounts)]
+pub struct CollectProtocolFees<'info> {
+    /// The global program config holding the treasury address.
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        mut,
+        constraint = token_a_vault.key() == pool.token_a_vault @ SwapError::InvalidVault,
+        constraint = token_b_vault.key() == pool.token_b_vault @ SwapError::InvalidVault,
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+
+    /// CHECK: The authority PDA for the pool. Required for signing outgoing transfers.
+    #[account(
+        seeds = [
+            b"pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    #[account(
+        constraint = token_a_mint.key() == pool.token_a_mint @ SwapError::InvalidMint,
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = token_b_mint.key() == pool.token_b_mint @ SwapError::InvalidMint,
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = token_a_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+    )]
+    pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_b_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+    )]
+    pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The treasury's token account for Token A.
+    #[account(
+        mut,
+        constraint = treasury_token_a_account.owner == config.treasury @ SwapError::InvalidTreasury,
+        constraint = treasury_token_a_account.mint == token_a_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub treasury_token_a_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The treasury's token account for Token B.
+    #[account(
+        mut,
+        constraint = treasury_token_b_account.owner == config.treasury @ SwapError::InvalidTreasury,
+        constraint = treasury_token_b_account.mint == token_b_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub treasury_token_b_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+/// Defines the accounts required for the `process_transaction` instruction.
+#[derive(Accounts)]
+#[instruction(amount: u64)]
+pub struct ProcessTransaction<'info> {
+    /// The mint of the token being transferred.
+    pub sender_token_account_mint : InterfaceAccount<'info, Mint>,
+
+    /// The token account sending the tokens.
+    #[account(
+        mut,
+        constraint = sender_token_account.owner == authority.key() @ SwapError::InvalidOwner,
+        constraint = sender_token_account.mint == sender_token_account_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub sender_token_account : InterfaceAccount<'info, TokenAccount>,
+
+    /// The token account receiving the tokens.
+    #[account(
+        mut,
+        constraint = receiver_token_account.mint == sender_token_account_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub receiver_token_account : InterfaceAccount<'info, TokenAccount>,
+
+    /// SPL Token Program (or Token-2022 program).
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// The authority (signer) authorizing the transfer.
+    #[account(mut, signer)]
+    pub authority: Signer<'info>,
 }
 
-/// Define the space required for the LiquidityPool account.
-const POOL_ACCOUNT_SIZE: usize = 8 + ( 32 * 4 ) + 1 + 64; // = 201 bytes
-
-/// Defines the accounts required for the `initialize_pool` instruction.
+/// Defines the accounts required for the `initialize_concentrated_pool` instruction.
 #[derive(Accounts)]
-pub struct InitializePool<'info> {
-    /// The mint account for Token A. Must be passed by the client.
+pub struct InitializeConcentratedPool<'info> {
+    /// The global program config, providing the default parameters for the new pool.
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// The mint account for Token A.
     pub token_a_mint: InterfaceAccount<'info, Mint>,
-    /// The mint account for Token B. Must be passed by the client.
+    /// The mint account for Token B. Must sort after Token A, so there is exactly one pool per pair.
+    #[account(
+        constraint = token_a_mint.key() < token_b_mint.key() @ SwapError::NonCanonicalMintOrder,
+    )]
     pub token_b_mint: InterfaceAccount<'info, Mint>,
 
-    /// The LiquidityPool account to be created.
+    /// The ConcentratedPool account to be created.
     #[account(
         init,
         payer = initializer,
         seeds = [
-            b"pool",
+            b"cl_pool",
             token_a_mint.key().as_ref(),
             token_b_mint.key().as_ref(),
         ],
         bump,
-        space = POOL_ACCOUNT_SIZE,
+        space = CONCENTRATED_POOL_ACCOUNT_SIZE,
     )]
-    pub pool: Account<'info, LiquidityPool>,
+    pub pool: Account<'info, ConcentratedPool>,
 
     /// CHECK: The authority PDA for the pool.
     #[account(
         seeds = [
-            b"pool",
+            b"cl_pool",
             token_a_mint.key().as_ref(),
             token_b_mint.key().as_ref(),
         ],
@@ -413,7 +4827,7 @@ pub struct InitializePool<'info> {
         init,
         payer = initializer,
         associated_token::mint = token_a_mint,
-        associated_token::authority = pool_authority, // Anchor ensures this authority matches the pool_authority account provided
+        associated_token::authority = pool_authority,
     )]
     pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
 
@@ -422,7 +4836,7 @@ pub struct InitializePool<'info> {
         init,
         payer = initializer,
         associated_token::mint = token_b_mint,
-        associated_token::authority = pool_authority, // Anchor ensures this authority matches the pool_authority account provided
+        associated_token::authority = pool_authority,
     )]
     pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
 
@@ -436,32 +4850,173 @@ pub struct InitializePool<'info> {
     pub system_program: Program<'info, System>,
 }
 
-/// Defines the accounts required for the `swap` instruction.
+/// Defines the accounts required for the `initialize_tick_array` instruction.
 #[derive(Accounts)]
-pub struct Swap<'info> {
+#[instruction(start_tick_index: i32)]
+pub struct InitializeTickArray<'info> {
+    pub pool: Account<'info, ConcentratedPool>,
+
+    /// The tick array to be created.
+    #[account(
+        init,
+        payer = payer,
+        seeds = [
+            b"tick_array",
+            pool.key().as_ref(),
+            start_tick_index.to_le_bytes().as_ref(),
+        ],
+        bump,
+        space = TICK_ARRAY_ACCOUNT_SIZE,
+    )]
+    pub tick_array: Account<'info, TickArray>,
+
+    /// Pays for the tick array (signer).
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Defines the accounts required for the `open_position` instruction.
+#[derive(Accounts)]
+#[instruction(tick_lower: i32, tick_upper: i32)]
+pub struct OpenPosition<'info> {
+    pub pool: Account<'info, ConcentratedPool>,
+
+    /// The position to be created.
+    #[account(
+        init,
+        payer = owner,
+        seeds = [
+            b"position",
+            pool.key().as_ref(),
+            owner.key().as_ref(),
+            tick_lower.to_le_bytes().as_ref(),
+            tick_upper.to_le_bytes().as_ref(),
+        ],
+        bump,
+        space = POSITION_ACCOUNT_SIZE,
+    )]
+    pub position: Account<'info, Position>,
+
+    /// The owner of the new position (signer and payer).
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Defines the accounts required for `increase_liquidity`, `decrease_liquidity` and `collect_fees`.
+#[derive(Accounts)]
+pub struct ModifyPosition<'info> {
+    #[account(
+        mut,
+        constraint = token_a_vault.key() == pool.token_a_vault @ SwapError::InvalidVault,
+        constraint = token_b_vault.key() == pool.token_b_vault @ SwapError::InvalidVault,
+    )]
+    pub pool: Account<'info, ConcentratedPool>,
+
+    /// CHECK: The authority PDA for the pool. Required for signing outgoing transfers.
+    #[account(
+        seeds = [
+            b"cl_pool",
+            pool.token_a_mint.as_ref(),
+            pool.token_b_mint.as_ref(),
+        ],
+        bump = pool.bump,
+    )]
+    pub pool_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        has_one = pool @ SwapError::InvalidPosition,
+        has_one = owner @ SwapError::Unauthorized,
+    )]
+    pub position: Account<'info, Position>,
+
+    /// The tick array holding the position's lower tick.
+    #[account(
+        mut,
+        constraint = tick_array_lower.pool == pool.key() @ SwapError::InvalidTickArray,
+        constraint = tick_array_lower.start_tick_index
+            == TickArray::start_index_for(position.tick_lower, pool.tick_spacing) @ SwapError::InvalidTickArray,
+    )]
+    pub tick_array_lower: Account<'info, TickArray>,
+
+    /// The tick array holding the position's upper tick. May be the same account as `tick_array_lower`.
+    #[account(
+        mut,
+        constraint = tick_array_upper.pool == pool.key() @ SwapError::InvalidTickArray,
+        constraint = tick_array_upper.start_tick_index
+            == TickArray::start_index_for(position.tick_upper, pool.tick_spacing) @ SwapError::InvalidTickArray,
+    )]
+    pub tick_array_upper: Account<'info, TickArray>,
+
+    #[account(
+        constraint = token_a_mint.key() == pool.token_a_mint @ SwapError::InvalidMint,
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = token_b_mint.key() == pool.token_b_mint @ SwapError::InvalidMint,
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = owner_token_a_account.owner == owner.key() @ SwapError::InvalidOwner,
+        constraint = owner_token_a_account.mint == token_a_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub owner_token_a_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = owner_token_b_account.owner == owner.key() @ SwapError::InvalidOwner,
+        constraint = owner_token_b_account.mint == token_b_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub owner_token_b_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_a_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+    )]
+    pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_b_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+    )]
+    pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The position owner (signer).
+    pub owner: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+/// Defines the accounts required for the `swap_concentrated` instruction.
+/// The tick arrays to walk through are passed as `remaining_accounts`.
+#[derive(Accounts)]
+pub struct SwapConcentrated<'info> {
     /// The mint account for the token being sent *in*.
     pub source_mint: InterfaceAccount<'info, Mint>,
     /// The mint account for the token being sent *out*.
     pub destination_mint: InterfaceAccount<'info, Mint>,
 
-    /// The LiquidityPool account containing the state for this swap.
     #[account(
-        // REMOVED seeds and bump validation from here.
-        // We validate the pool implicitly through the pool_authority check and vault constraints.
-        // --- Security Constraints ---
-        // These constraints ensure the provided vaults match the addresses stored in the pool state.
+        mut,
         constraint = token_a_vault.key() == pool.token_a_vault @ SwapError::InvalidVault,
         constraint = token_b_vault.key() == pool.token_b_vault @ SwapError::InvalidVault,
-        constraint = (pool.token_a_mint == source_mint.key() && pool.token_b_mint == destination_mint.key()) || 
-                    (pool.token_a_mint == destination_mint.key() && pool.token_b_mint == source_mint.key()) 
+        constraint = (pool.token_a_mint == source_mint.key() && pool.token_b_mint == destination_mint.key()) ||
+                    (pool.token_a_mint == destination_mint.key() && pool.token_b_mint == source_mint.key())
                     @ SwapError::InvalidMint,
     )]
-    pub pool: Account<'info, LiquidityPool>,
+    pub pool: Account<'info, ConcentratedPool>,
 
     /// CHECK: The authority PDA for the pool. Required for signing outgoing transfers.
     #[account(
         seeds = [
-            b"pool", 
+            b"cl_pool",
             pool.token_a_mint.as_ref(),
             pool.token_b_mint.as_ref(),
         ],
@@ -469,144 +5024,450 @@ pub struct Swap<'info> {
     )]
     pub pool_authority: AccountInfo<'info>,
 
-    /// The user's token account for the token they are sending *in*.
+    /// The user's token account for the token they are sending *in*.
+    #[account(
+        mut,
+        constraint = user_source_token_account.owner == user_authority.key() @ SwapError::InvalidOwner,
+        constraint = user_source_token_account.mint == source_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub user_source_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The user's token account for the token they are receiving *out*.
+    #[account(
+        mut,
+        constraint = user_destination_token_account.owner == user_authority.key() @ SwapError::InvalidOwner,
+        constraint = user_destination_token_account.mint == destination_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub user_destination_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_a_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+    )]
+    pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = token_b_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+    )]
+    pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The user performing the swap (signer).
+    #[account(mut)]
+    pub user_authority: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+/// Defines the accounts required for the `initialize_farm` instruction.
+#[derive(Accounts)]
+pub struct InitializeFarm<'info> {
+    #[account(
+        has_one = admin @ SwapError::Unauthorized,
+        has_one = lp_mint @ SwapError::InvalidLpMint,
+    )]
+    pub pool: Account<'info, LiquidityPool>,
+
+    /// The farm to be created, one per pool.
+    #[account(
+        init,
+        payer = admin,
+        seeds = [
+            b"farm",
+            pool.key().as_ref(),
+        ],
+        bump,
+        space = FARM_ACCOUNT_SIZE,
+    )]
+    pub farm: Account<'info, Farm>,
+
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+
+    /// The associated token account holding staked LP shares, owned by the farm PDA.
+    #[account(
+        init,
+        payer = admin,
+        associated_token::mint = lp_mint,
+        associated_token::authority = farm,
+    )]
+    pub staked_lp_vault: InterfaceAccount<'info, TokenAccount>,
+
+    pub reward_mint: InterfaceAccount<'info, Mint>,
+
+    /// The associated token account holding the rewards, owned by the farm PDA.
+    #[account(
+        init,
+        payer = admin,
+        associated_token::mint = reward_mint,
+        associated_token::authority = farm,
+    )]
+    pub reward_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The pool admin (signer and payer).
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    // System Accounts
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Defines the accounts required for the `add_reward_stream` instruction.
+#[derive(Accounts)]
+pub struct AddRewardStream<'info> {
+    #[account(has_one = admin @ SwapError::Unauthorized)]
+    pub pool: Account<'info, LiquidityPool>,
+
+    #[account(
+        mut,
+        has_one = pool,
+    )]
+    pub farm: Account<'info, Farm>,
+
+    pub reward_mint: InterfaceAccount<'info, Mint>,
+
+    /// The associated token account holding the new stream's rewards, owned by the farm PDA.
+    #[account(
+        init,
+        payer = admin,
+        associated_token::mint = reward_mint,
+        associated_token::authority = farm,
+    )]
+    pub reward_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The pool admin (signer and payer).
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    // System Accounts
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Defines the accounts required for the `fund_farm` instruction.
+#[derive(Accounts)]
+pub struct FundFarm<'info> {
+    #[account(has_one = admin @ SwapError::Unauthorized)]
+    pub pool: Account<'info, LiquidityPool>,
+
+    #[account(has_one = pool)]
+    pub farm: Account<'info, Farm>,
+
+    pub reward_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub reward_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The admin's token account the rewards are paid from.
+    #[account(
+        mut,
+        constraint = admin_reward_token_account.owner == admin.key() @ SwapError::InvalidOwner,
+        constraint = admin_reward_token_account.mint == reward_mint.key() @ SwapError::InvalidMint,
+    )]
+    pub admin_reward_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The pool admin (signer).
+    pub admin: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+/// Defines the accounts required for the farm admin instruction `set_emission_rate`.
+#[derive(Accounts)]
+pub struct ManageFarm<'info> {
+    #[account(has_one = admin @ SwapError::Unauthorized)]
+    pub pool: Account<'info, LiquidityPool>,
+
+    #[account(
+        mut,
+        has_one = pool,
+    )]
+    pub farm: Account<'info, Farm>,
+
+    /// The pool admin (signer).
+    pub admin: Signer<'info>,
+}
+
+/// Defines the accounts required for the `open_stake_position` instruction.
+#[derive(Accounts)]
+pub struct OpenStakePosition<'info> {
+    pub farm: Account<'info, Farm>,
+
+    /// The stake position to be created, one per farm and owner.
+    #[account(
+        init,
+        payer = owner,
+        seeds = [
+            b"stake",
+            farm.key().as_ref(),
+            owner.key().as_ref(),
+        ],
+        bump,
+        space = STAKE_POSITION_ACCOUNT_SIZE,
+    )]
+    pub stake_position: Account<'info, StakePosition>,
+
+    /// The owner of the new stake position (signer and payer).
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Defines the accounts required for `stake` and `unstake`.
+#[derive(Accounts)]
+pub struct UpdateStake<'info> {
+    #[account(
+        mut,
+        has_one = lp_mint @ SwapError::InvalidLpMint,
+        has_one = staked_lp_vault @ SwapError::InvalidVault,
+    )]
+    pub farm: Account<'info, Farm>,
+
+    #[account(
+        mut,
+        has_one = farm @ SwapError::InvalidPosition,
+        has_one = owner @ SwapError::Unauthorized,
+    )]
+    pub stake_position: Account<'info, StakePosition>,
+
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub staked_lp_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The owner's LP token account the shares are staked from and returned to.
     #[account(
         mut,
-        constraint = user_source_token_account.owner == user_authority.key() @ SwapError::InvalidOwner,
-        constraint = user_source_token_account.mint == source_mint.key() @ SwapError::InvalidMint,
+        constraint = owner_lp_token_account.owner == owner.key() @ SwapError::InvalidOwner,
+        constraint = owner_lp_token_account.mint == lp_mint.key() @ SwapError::InvalidMint,
     )]
-    pub user_source_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub owner_lp_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The owner of the stake position (signer).
+    pub owner: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+/// Defines the accounts required for the `harvest` instruction. The mint, vault and destination
+/// of each reward stream are passed as remaining accounts, see `REWARD_ACCOUNTS_PER_STREAM`.
+#[derive(Accounts)]
+pub struct Harvest<'info> {
+    #[account(mut)]
+    pub farm: Account<'info, Farm>,
 
-    /// The user's token account for the token they are receiving *out*.
     #[account(
         mut,
-        constraint = user_destination_token_account.owner == user_authority.key() @ SwapError::InvalidOwner,
-        constraint = user_destination_token_account.mint == destination_mint.key() @ SwapError::InvalidMint,
+        has_one = farm @ SwapError::InvalidPosition,
+        has_one = owner @ SwapError::Unauthorized,
     )]
-    pub user_destination_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub stake_position: Account<'info, StakePosition>,
 
-    /// The pool's vault for Token A. Needs to be mutable for balance changes.
+    /// The owner of the stake position (signer).
+    pub owner: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+/// Defines the accounts required for the `initialize_ve_escrow` instruction.
+#[derive(Accounts)]
+pub struct InitializeVeEscrow<'info> {
     #[account(
-        mut,
-        // Constraint to ensure this vault belongs to the validated pool authority PDA.
-        constraint = token_a_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
-        // Constraint ensuring the vault's mint matches the pool state's mint A.
-        constraint = token_a_vault.mint == pool.token_a_mint @ SwapError::InvalidMint,
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ SwapError::Unauthorized,
     )]
-    pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
+    pub config: Account<'info, ProgramConfig>,
 
-    /// The pool's vault for Token B. Needs to be mutable for balance changes.
+    /// The vote-escrow to be created.
     #[account(
-        mut,
-        // Constraint to ensure this vault belongs to the validated pool authority PDA.
-        constraint = token_b_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
-        // Constraint ensuring the vault's mint matches the pool state's mint B.
-        constraint = token_b_vault.mint == pool.token_b_mint @ SwapError::InvalidMint,
+        init,
+        payer = admin,
+        seeds = [b"ve_escrow"],
+        bump,
+        space = VE_ESCROW_ACCOUNT_SIZE,
     )]
-    pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
+    pub escrow: Account<'info, VeEscrow>,
 
-    /// The user performing the swap (signer).
+    pub governance_mint: InterfaceAccount<'info, Mint>,
+
+    /// The associated token account holding locked tokens, owned by the escrow PDA.
+    #[account(
+        init,
+        payer = admin,
+        associated_token::mint = governance_mint,
+        associated_token::authority = escrow,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The protocol admin (signer and payer).
     #[account(mut)]
-    pub user_authority: Signer<'info>,
+    pub admin: Signer<'info>,
 
     // System Accounts
     pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
 }
 
-
+/// Defines the accounts required for the `lock` instruction.
 #[derive(Accounts)]
-pub struct AddLiquidity<'info> {
+pub struct CreateVeLock<'info> {
     #[account(
-        constraint = token_a_vault.key() == pool.token_a_vault @ SwapError::InvalidVault,
-
-        constraint = token_b_vault.key() == pool.token_b_vault @ SwapError::InvalidVault,
+        mut,
+        seeds = [b"ve_escrow"],
+        bump = escrow.bump,
+        has_one = governance_mint @ SwapError::InvalidMint,
+        has_one = vault @ SwapError::InvalidVault,
     )]
-    pub pool: Account<'info, LiquidityPool>,
+    pub escrow: Account<'info, VeEscrow>,
 
-    /// CHECK: This is the pool authority PDA that's derived deterministically.
+    /// The lock to be created, one per owner.
     #[account(
+        init,
+        payer = owner,
         seeds = [
-            b"pool",
-            pool.token_a_mint.as_ref(),
-            pool.token_b_mint.as_ref(),
+            b"ve_lock",
+            owner.key().as_ref(),
         ],
         bump,
+        space = VE_LOCK_ACCOUNT_SIZE,
     )]
-    pub pool_authority: AccountInfo<'info>,
+    pub ve_lock: Account<'info, VeLock>,
 
-    #[account(
-        constraint = token_a_mint.key() == pool.token_a_mint @ SwapError::InvalidMint,
-    )]
-    pub token_a_mint: InterfaceAccount<'info, Mint>,
+    pub governance_mint: InterfaceAccount<'info, Mint>,
 
-    #[account(
-        constraint = token_b_mint.key() == pool.token_b_mint @ SwapError::InvalidMint,
-    )]
-    pub token_b_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut)]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
 
+    /// The owner's governance token account the tokens are locked from.
     #[account(
         mut,
-        constraint = user_token_a_account.owner == user_authority.key() @ SwapError::InvalidOwner,
-        constraint = user_token_a_account.mint == token_a_mint.key() @ SwapError::InvalidMint,
+        constraint = owner_token_account.owner == owner.key() @ SwapError::InvalidOwner,
+        constraint = owner_token_account.mint == governance_mint.key() @ SwapError::InvalidMint,
     )]
-    pub user_token_a_account: InterfaceAccount<'info, TokenAccount>,
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
 
+    /// The owner of the new lock (signer and payer).
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Defines the accounts required for the `increase_amount` instruction.
+#[derive(Accounts)]
+pub struct ModifyVeLock<'info> {
     #[account(
         mut,
-        constraint = user_token_b_account.owner == user_authority.key() @
-        SwapError::InvalidOwner,
-
-        constraint = user_token_b_account.mint == token_b_mint.key() @ SwapError::InvalidMint,
+        seeds = [b"ve_escrow"],
+        bump = escrow.bump,
+        has_one = governance_mint @ SwapError::InvalidMint,
+        has_one = vault @ SwapError::InvalidVault,
     )]
-    pub user_token_b_account: InterfaceAccount<'info, TokenAccount>,
+    pub escrow: Account<'info, VeEscrow>,
 
     #[account(
         mut,
-        constraint = token_a_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+        seeds = [
+            b"ve_lock",
+            owner.key().as_ref(),
+        ],
+        bump = ve_lock.bump,
+        has_one = owner @ SwapError::Unauthorized,
     )]
-    pub token_a_vault: InterfaceAccount<'info, TokenAccount>,
+    pub ve_lock: Account<'info, VeLock>,
 
+    pub governance_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The owner's governance token account the tokens are locked from.
     #[account(
         mut,
-        constraint = token_b_vault.owner == pool_authority.key() @ SwapError::InvalidVault,
+        constraint = owner_token_account.owner == owner.key() @ SwapError::InvalidOwner,
+        constraint = owner_token_account.mint == governance_mint.key() @ SwapError::InvalidMint,
     )]
-    pub token_b_vault: InterfaceAccount<'info, TokenAccount>,
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
 
-    #[account(mut)]
-    pub user_authority: Signer<'info>,
+    /// The owner of the lock (signer).
+    pub owner: Signer<'info>,
 
     pub token_program: Interface<'info, TokenInterface>,
 }
 
+/// Defines the accounts required for the `extend_lock` instruction.
+#[derive(Accounts)]
+pub struct ExtendVeLock<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"ve_lock",
+            owner.key().as_ref(),
+        ],
+        bump = ve_lock.bump,
+        has_one = owner @ SwapError::Unauthorized,
+    )]
+    pub ve_lock: Account<'info, VeLock>,
+
+    /// The owner of the lock (signer).
+    pub owner: Signer<'info>,
+}
 
-/// Defines the accounts required for the `process_transaction` instruction.
+/// Defines the accounts required for the `withdraw_after_expiry` instruction.
 #[derive(Accounts)]
-#[instruction(amount: u64)]
-pub struct ProcessTransaction<'info> {
-    /// The mint of the token being transferred.
-    pub sender_token_account_mint : InterfaceAccount<'info, Mint>,
+pub struct WithdrawVeLock<'info> {
+    #[account(
+        mut,
+        seeds = [b"ve_escrow"],
+        bump = escrow.bump,
+        has_one = governance_mint @ SwapError::InvalidMint,
+        has_one = vault @ SwapError::InvalidVault,
+    )]
+    pub escrow: Account<'info, VeEscrow>,
 
-    /// The token account sending the tokens.
+    /// The expired lock, closed and its rent returned to the owner.
     #[account(
         mut,
-        constraint = sender_token_account.owner == authority.key() @ SwapError::InvalidOwner,
-        constraint = sender_token_account.mint == sender_token_account_mint.key() @ SwapError::InvalidMint,
+        seeds = [
+            b"ve_lock",
+            owner.key().as_ref(),
+        ],
+        bump = ve_lock.bump,
+        has_one = owner @ SwapError::Unauthorized,
+        close = owner,
     )]
-    pub sender_token_account : InterfaceAccount<'info, TokenAccount>,
+    pub ve_lock: Account<'info, VeLock>,
 
-    /// The token account receiving the tokens.
+    pub governance_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(mut)]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The owner's governance token account receiving the tokens.
     #[account(
         mut,
-        constraint = receiver_token_account.mint == sender_token_account_mint.key() @ SwapError::InvalidMint,
+        constraint = owner_token_account.owner == owner.key() @ SwapError::InvalidOwner,
+        constraint = owner_token_account.mint == governance_mint.key() @ SwapError::InvalidMint,
     )]
-    pub receiver_token_account : InterfaceAccount<'info, TokenAccount>,
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The owner of the lock (signer).
+    #[account(mut)]
+    pub owner: Signer<'info>,
 
-    /// SPL Token Program (or Token-2022 program).
     pub token_program: Interface<'info, TokenInterface>,
+}
 
-    /// The authority (signer) authorizing the transfer.
-    #[account(mut, signer)]
-    pub authority: Signer<'info>,
+/// Defines the accounts required for the read-only `get_voting_power` instruction.
+#[derive(Accounts)]
+pub struct GetVotingPower<'info> {
+    pub ve_lock: Account<'info, VeLock>,
 }
 
 // --- Events ---
@@ -630,18 +5491,250 @@ pub struct SwapEvent {
     pub amount_in: u64,
     /// The amount of tokens sent out of the pool.
     pub amount_out: u64,
+    /// The portion of `amount_in` kept by the pool as fee.
+    pub fee_amount: u64,
     /// The mint of the token sent into the pool.
     pub source_mint: Pubkey,
     /// The mint of the token sent out of the pool.
     pub destination_mint: Pubkey,
 }
 
+/// Event emitted when a flash loan is taken out with `flash_borrow`.
+#[event]
+pub struct FlashLoanEvent {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    /// The fee owed on top of `amount`, left in the vault for the LPs.
+    pub fee_amount: u64,
+}
+
+/// Event emitted when a flash swap is repaid.
+#[event]
+pub struct FlashSwapEvent {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub receiver_program: Pubkey,
+    pub amount_a_out: u64,
+    pub amount_b_out: u64,
+    pub amount_a_in: u64,
+    pub amount_b_in: u64,
+}
+
 #[event]
 pub struct LiquidityAddedEvent {
     pub pool: Pubkey,
     pub user: Pubkey,
     pub amount_a: u64,
     pub amount_b: u64,
+    /// The amount of LP shares minted to the depositor.
+    pub lp_amount: u64,
+}
+
+/// Event emitted when liquidity is withdrawn via `remove_liquidity`.
+#[event]
+pub struct LiquidityRemovedEvent {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub amount_a: u64,
+    pub amount_b: u64,
+    /// The amount of LP shares burned.
+    pub lp_amount: u64,
+}
+
+/// Event emitted when protocol fees are sent to the treasury via `collect_protocol_fees`.
+#[event]
+pub struct ProtocolFeesCollectedEvent {
+    pub pool: Pubkey,
+    pub treasury: Pubkey,
+    pub amount_a: u64,
+    pub amount_b: u64,
+}
+
+/// Event emitted when a pool's observation buffer grows via `increase_observation_cardinality`.
+#[event]
+pub struct ObservationCardinalityIncreasedEvent {
+    pub pool: Pubkey,
+    pub old_cardinality: u16,
+    pub new_cardinality: u16,
+}
+
+/// Event emitted when the protocol admin updates the program config.
+#[event]
+pub struct ConfigUpdatedEvent {
+    pub admin: Pubkey,
+    pub treasury: Pubkey,
+    pub params: ConfigParams,
+}
+
+/// Event emitted when a pool's pause flags change via `set_pool_status`.
+#[event]
+pub struct PoolStatusChangedEvent {
+    pub pool: Pubkey,
+    pub admin: Pubkey,
+    pub old_status: u8,
+    pub new_status: u8,
+}
+
+/// Event emitted when the admin starts ramping a StableSwap pool's amp.
+#[event]
+pub struct AmpRampStartedEvent {
+    pub pool: Pubkey,
+    pub amp_initial: u64,
+    pub amp_target: u64,
+    pub ramp_start_ts: i64,
+    pub ramp_end_ts: i64,
+}
+
+/// Event emitted when the admin freezes a StableSwap pool's amp.
+#[event]
+pub struct AmpRampStoppedEvent {
+    pub pool: Pubkey,
+    pub amp: u64,
+}
+
+/// Event emitted when the admin ends a liquidity bootstrapping pool's sale early.
+#[event]
+pub struct SaleEndedEvent {
+    pub pool: Pubkey,
+    pub weight_a_bps: u16,
+    pub weight_b_bps: u16,
+    pub ended_at: i64,
+}
+
+/// Event emitted when liquidity is added to or removed from a concentrated-liquidity position.
+#[event]
+pub struct PositionLiquidityChangedEvent {
+    pub pool: Pubkey,
+    pub position: Pubkey,
+    pub owner: Pubkey,
+    /// Positive for `increase_liquidity`, negative for `decrease_liquidity`.
+    pub liquidity_delta: i128,
+    pub amount_a: u64,
+    pub amount_b: u64,
+}
+
+/// Event emitted when a position owner collects the fees earned by their position.
+#[event]
+pub struct PositionFeesCollectedEvent {
+    pub pool: Pubkey,
+    pub position: Pubkey,
+    pub owner: Pubkey,
+    pub amount_a: u64,
+    pub amount_b: u64,
+}
+
+/// Event emitted when the pool admin adds rewards to a farm.
+#[event]
+pub struct FarmFundedEvent {
+    pub farm: Pubkey,
+    pub reward_mint: Pubkey,
+    pub amount: u64,
+}
+
+/// Event emitted when a reward stream is added to a farm.
+#[event]
+pub struct RewardStreamAddedEvent {
+    pub farm: Pubkey,
+    pub reward_mint: Pubkey,
+    pub reward_per_second: u64,
+    pub start_ts: i64,
+    pub end_ts: i64,
+}
+
+/// Event emitted when the pool admin changes the emission rate of a farm's reward stream.
+#[event]
+pub struct EmissionRateUpdatedEvent {
+    pub farm: Pubkey,
+    pub reward_mint: Pubkey,
+    pub reward_per_second: u64,
+}
+
+/// Event emitted when LP shares are staked in a farm.
+#[event]
+pub struct StakedEvent {
+    pub farm: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+}
+
+/// Event emitted when LP shares are withdrawn from a farm.
+#[event]
+pub struct UnstakedEvent {
+    pub farm: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+}
+
+/// Event emitted when a stake position's rewards of one stream are paid out.
+#[event]
+pub struct RewardsHarvestedEvent {
+    pub farm: Pubkey,
+    pub owner: Pubkey,
+    pub reward_mint: Pubkey,
+    pub amount: u64,
+}
+
+/// Event emitted when a vote-escrow lock is created, increased or extended.
+#[event]
+pub struct VeLockChangedEvent {
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub unlock_ts: i64,
+}
+
+/// Event emitted when an expired vote-escrow lock is withdrawn.
+#[event]
+pub struct VeLockWithdrawnEvent {
+    pub owner: Pubkey,
+    pub amount: u64,
+}
+
+/// Event emitted when a pool parameter change is queued, so indexers can warn the pool's users.
+#[event]
+pub struct ChangeQueuedEvent {
+    pub pool: Pubkey,
+    pub change: PoolParamChange,
+    pub eta: i64,
+}
+
+/// Event emitted when a queued pool parameter change is applied.
+#[event]
+pub struct ChangeExecutedEvent {
+    pub pool: Pubkey,
+    pub change: PoolParamChange,
+}
+
+/// Event emitted when the pool admin drops a queued change.
+#[event]
+pub struct ChangeCancelledEvent {
+    pub pool: Pubkey,
+    pub change: PoolParamChange,
+}
+
+/// Event emitted when an admin handover is proposed for a pool or the program config.
+#[event]
+pub struct AdminTransferProposedEvent {
+    pub target: Pubkey,
+    pub admin: Pubkey,
+    pub pending_admin: Pubkey,
+}
+
+/// Event emitted when the proposed admin accepts the role.
+#[event]
+pub struct AdminTransferAcceptedEvent {
+    pub target: Pubkey,
+    pub old_admin: Pubkey,
+    pub new_admin: Pubkey,
+}
+
+/// Event emitted when the current admin withdraws a pending handover.
+#[event]
+pub struct AdminTransferCancelledEvent {
+    pub target: Pubkey,
+    pub admin: Pubkey,
+    pub cancelled_admin: Pubkey,
 }
 
 // --- Errors ---
@@ -669,4 +5762,158 @@ pub enum SwapError {
     ExcessivePriceImpact,
     #[msg("Disproportionate liquidity provided")]
     DisproportionateLiquidity,
-}
\ No newline at end of file
+    #[msg("Invalid LP mint provided.")]
+    InvalidLpMint,
+    #[msg("Deposit too small to mint any LP shares.")]
+    InsufficientLiquidityMinted,
+    #[msg("Fee exceeds the maximum allowed.")]
+    FeeTooHigh,
+    #[msg("Protocol fee share must not exceed 10000 bps.")]
+    InvalidProtocolFeeShare,
+    #[msg("Token account does not belong to the treasury.")]
+    InvalidTreasury,
+    #[msg("Pool reserve is too small for the requested output.")]
+    InsufficientReserves,
+    #[msg("Invalid swap route.")]
+    InvalidRoute,
+    #[msg("Pool mints must be passed in canonical (sorted) order.")]
+    NonCanonicalMintOrder,
+    #[msg("Transaction deadline has passed.")]
+    Expired,
+    #[msg("Observation must be older than the current time.")]
+    InvalidObservation,
+    #[msg("Observation buffer does not reach back far enough.")]
+    ObservationTooOld,
+    #[msg("Invalid observation cardinality.")]
+    InvalidObservationCardinality,
+    #[msg("This operation is paused for the pool.")]
+    PoolPaused,
+    #[msg("Signer is not authorized for this action.")]
+    Unauthorized,
+    #[msg("Unknown pool status flags.")]
+    InvalidPoolStatus,
+    #[msg("Pool creation is currently disabled.")]
+    PoolCreationDisabled,
+    #[msg("Invalid protocol config parameters.")]
+    InvalidConfigParams,
+    #[msg("The proposed admin must be a new, non-default key.")]
+    InvalidPendingAdmin,
+    #[msg("No admin handover is pending.")]
+    NoPendingAdmin,
+    #[msg("This change has to be queued with queue_change.")]
+    TimelockRequired,
+    #[msg("The timelock for this change has not elapsed yet.")]
+    TimelockNotElapsed,
+    #[msg("Invalid curve parameters for this pool.")]
+    InvalidCurveParams,
+    #[msg("Invalid amp ramp.")]
+    InvalidAmpRamp,
+    #[msg("The sale has not started yet.")]
+    SaleNotStarted,
+    #[msg("The sale has already ended.")]
+    SaleEnded,
+    #[msg("Tick is out of bounds, not a multiple of the tick spacing, or the range is empty.")]
+    InvalidTickRange,
+    #[msg("Sqrt price is out of bounds.")]
+    InvalidSqrtPrice,
+    #[msg("Tick array is missing or does not cover the required ticks.")]
+    InvalidTickArray,
+    #[msg("Position does not belong to this pool.")]
+    InvalidPosition,
+    #[msg("Position does not hold that much liquidity.")]
+    InsufficientPositionLiquidity,
+    #[msg("This operation is not supported by the pool's curve.")]
+    UnsupportedCurve,
+    #[msg("Pool is locked by a flash swap or flash loan in progress.")]
+    PoolLocked,
+    #[msg("Flash swap receiver must be an executable program other than this one.")]
+    InvalidFlashSwapReceiver,
+    #[msg("Flash swap was not repaid with the pool's fee.")]
+    FlashSwapNotRepaid,
+    #[msg("No flash_repay for this pool and vault follows the borrow in the transaction.")]
+    FlashLoanNotRepaid,
+    #[msg("The pool has no outstanding flash loan.")]
+    NoFlashLoan,
+    #[msg("Farm must end after it starts and in the future.")]
+    InvalidFarmSchedule,
+    #[msg("Stake position does not hold that many LP shares.")]
+    InsufficientStake,
+    #[msg("Reward stream does not exist.")]
+    InvalidRewardStream,
+    #[msg("Farm already has the maximum number of reward streams.")]
+    TooManyRewardStreams,
+    #[msg("Harvest needs the mint, vault and destination account of every reward stream.")]
+    InvalidRewardAccounts,
+    #[msg("Unlock time must be later, in the future and within the maximum lock duration.")]
+    InvalidLockDuration,
+    #[msg("The lock has expired.")]
+    LockExpired,
+    #[msg("The lock has not expired yet.")]
+    LockNotExpired,
+    #[msg("The pool was paused after this status change was queued; queue it again.")]
+    StaleStatusChange,
+    #[msg("The pool has LP shares outstanding but an empty reserve.")]
+    EmptyReserves,
+    #[msg("Swap input too small to receive any output.")]
+    SwapTooSmall,
+    #[msg("Flash loans must be borrowed and repaid in top-level instructions, not through CPI.")]
+    FlashLoanNotTopLevel,
+    #[msg("The pool already has the current account layout.")]
+    PoolAlreadyMigrated,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn observation(timestamp: i64, cumulative: u128) -> Observation {
+        Observation {
+            timestamp,
+            price_a_cumulative: cumulative,
+            price_b_cumulative: cumulative,
+            liquidity_cumulative: cumulative,
+        }
+    }
+
+    #[test]
+    fn interpolation_scales_before_dividing() {
+        // A 7 second span with a delta of 10: 3 seconds in must land at 10 * 3 / 7 = 4
+        let before = observation(100, 1_000);
+        let after = observation(107, 1_010);
+        let interpolated = interpolate_observation(&before, &after, 103);
+        assert_eq!(interpolated.timestamp, 103);
+        assert_eq!(interpolated.price_a_cumulative, 1_004);
+        assert_eq!(interpolated.price_b_cumulative, 1_004);
+        assert_eq!(interpolated.liquidity_cumulative, 1_004);
+    }
+
+    #[test]
+    fn interpolation_handles_wrapped_accumulators() {
+        let before = observation(0, u128::MAX - 4);
+        let after = observation(3, 5);
+        // The accumulator advanced by 10 across the wrap
+        let interpolated = interpolate_observation(&before, &after, 2);
+        assert_eq!(interpolated.price_a_cumulative, 1);
+    }
+
+    #[test]
+    fn idle_reward_stream_defers_its_emissions() {
+        let mut stream = RewardStream {
+            reward_per_second: 10,
+            start_ts: 100,
+            end_ts: 200,
+            last_update_ts: 100,
+            ..RewardStream::default()
+        };
+
+        // Nothing staked for 50 seconds: nothing accrues and the stream ends 50 seconds later
+        stream.update(150, 0);
+        assert_eq!(stream.acc_reward_per_share, 0);
+        assert_eq!(stream.end_ts, 250);
+
+        // A single staked share then earns the whole schedule
+        stream.update(300, 1);
+        assert_eq!(stream.acc_reward_per_share, (10 * 100) << 64);
+        assert_eq!(stream.last_update_ts, 300);
+    }
+}
@@ -1,6 +1,7 @@
 import * as anchor from "@coral-xyz/anchor"
 import { Program, BN } from "@coral-xyz/anchor"
 import { Web3ForDummies } from "../target/types/web3_for_dummies"
+import { FlashSwapReceiver } from "../target/types/flash_swap_receiver"
 import {
     PublicKey,
     Keypair,
@@ -16,6 +17,7 @@ describe("web3-for-dummies", () => {
     anchor.setProvider(provider)
 
     const program = anchor.workspace.Web3ForDummies as Program<Web3ForDummies>
+    const flashSwapReceiver = anchor.workspace.FlashSwapReceiver as Program<FlashSwapReceiver>
 
 
     const payer = anchor.web3.Keypair.generate();
@@ -23,8 +25,22 @@ describe("web3-for-dummies", () => {
     const alice = anchor.web3.Keypair.generate();
     const bob = anchor.web3.Keypair.generate();
     const intializer = anchor.web3.Keypair.generate();
+    const treasury = anchor.web3.Keypair.generate();
 
     const decimals = 6;
+    const poolFeeBps = 30;
+    const protocolFeeShareBps = 2000;
+    // LP shares locked by the first deposit into every pool
+    const MINIMUM_LIQUIDITY = 1000;
+    const configParams = {
+        protocolFeeShareBps,
+        defaultFeeBps: poolFeeBps,
+        maxFeeBps: 1000,
+        defaultMaxPriceImpactBps: 1000,
+        depositRatioToleranceBps: 100,
+        poolCreationEnabled: true,
+        changeDelaySecs: 0,
+    };
     const initialMintAmount = 1_000_000 * (10 ** decimals);
 
     let simpleTransferMint: PublicKey;
@@ -38,9 +54,12 @@ describe("web3-for-dummies", () => {
     let poolAuthorityPda: PublicKey;
     let poolTokenAVault: PublicKey;
     let poolTokenBVault: PublicKey;
+    let lpMintPda: PublicKey;
+    let aliceLpTokenAccount: PublicKey;
     let aliceTokenAAccount: PublicKey;
     let aliceTokenBAccount: PublicKey;
     let poolBump: number;
+    let configPda: PublicKey;
 
     const getTokenBalance = async (tokenAccount: PublicKey): Promise<number> => {
         try {
@@ -50,6 +69,12 @@ describe("web3-for-dummies", () => {
             return 0;
         }
     };
+    function getPendingChangePda(pool: PublicKey, kind: number): PublicKey {
+        return PublicKey.findProgramAddressSync(
+            [Buffer.from("pending_change"), pool.toBuffer(), Buffer.from([kind])],
+            program.programId
+        )[0];
+    }
     function getSortedMints() {
         return [tokenAMint, tokenBMint].sort((a, b) => a.toBuffer().compare(b.toBuffer()));
     }
@@ -76,6 +101,17 @@ describe("web3-for-dummies", () => {
     }
 
 
+    const getObservationsPda = (pool: PublicKey): PublicKey =>
+        PublicKey.findProgramAddressSync([Buffer.from("observations"), pool.toBuffer()], program.programId)[0];
+
+    // The LP mint only exists once the pool is initialized, so the LP account is created lazily
+    const getAliceLpAccount = async (): Promise<PublicKey> => {
+        if (!aliceLpTokenAccount) {
+            aliceLpTokenAccount = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, lpMintPda, alice.publicKey)).address;
+        }
+        return aliceLpTokenAccount;
+    };
+
     const setupToken = async (authority: Keypair, recipient: PublicKey, amount: number): Promise<{ mint: PublicKey, ata: PublicKey }> => {
         const mint = await createMint(
             provider.connection,
@@ -161,6 +197,34 @@ describe("web3-for-dummies", () => {
         poolTokenAVault = await getAssociatedTokenAddress(mintAkey, poolAuthorityPda, true);
         poolTokenBVault = await getAssociatedTokenAddress(mintBKey, poolAuthorityPda, true);
 
+        [configPda] = PublicKey.findProgramAddressSync(
+            [Buffer.from("config")],
+            program.programId
+        );
+
+        // The config is a program-wide singleton, so it may already exist on a shared cluster
+        try {
+            await program.account.programConfig.fetch(configPda);
+        } catch (e) {
+            await program.methods
+                .initializeConfig(treasury.publicKey, configParams)
+                .accounts({
+                    admin: payer.publicKey,
+                    config: configPda,
+                    systemProgram: SystemProgram.programId,
+                } as any)
+                .signers([payer])
+                .rpc();
+        }
+
+        [lpMintPda] = PublicKey.findProgramAddressSync(
+            [
+                Buffer.from("lp_mint"),
+                poolPda.toBuffer(),
+            ],
+            program.programId
+        );
+
 
     });
 
@@ -257,6 +321,53 @@ describe("web3-for-dummies", () => {
     });
 
 
+    describe("update_config", () => {
+        it("Updates the protocol parameters as the admin", async () => {
+            const config = await program.account.programConfig.fetch(configPda);
+
+            await program.methods
+                .updateConfig(config.treasury, { ...configParams, defaultMaxPriceImpactBps: 1500 })
+                .accounts({ config: configPda, admin: config.admin } as any)
+                .signers([payer])
+                .rpc();
+            assert.equal((await program.account.programConfig.fetch(configPda)).defaultMaxPriceImpactBps, 1500, "Config not updated");
+
+            // Restore the defaults used by the rest of the suite
+            await program.methods
+                .updateConfig(config.treasury, configParams)
+                .accounts({ config: configPda, admin: config.admin } as any)
+                .signers([payer])
+                .rpc();
+        });
+
+        it("Fails to update the config when not the admin", async () => {
+            try {
+                await program.methods
+                    .updateConfig(treasury.publicKey, configParams)
+                    .accounts({ config: configPda, admin: bob.publicKey } as any)
+                    .signers([bob])
+                    .rpc();
+                assert.fail("Should have failed because bob is not the admin");
+            } catch (e) {
+                assert.include(e.toString(), "Unauthorized", "Expected Unauthorized error");
+            }
+        });
+
+        it("Fails to set a default fee above the maximum fee", async () => {
+            const config = await program.account.programConfig.fetch(configPda);
+            try {
+                await program.methods
+                    .updateConfig(config.treasury, { ...configParams, defaultFeeBps: configParams.maxFeeBps + 1 })
+                    .accounts({ config: configPda, admin: config.admin } as any)
+                    .signers([payer])
+                    .rpc();
+                assert.fail("Should have failed due to default fee above maximum");
+            } catch (e) {
+                assert.include(e.toString(), "FeeTooHigh", "Expected FeeTooHigh error");
+            }
+        });
+    });
+
     describe("intialize_pool", () => {
         it("Initializes the liquidity pool correctly", async () => {
 
@@ -273,15 +384,18 @@ describe("web3-for-dummies", () => {
             const [mintAkey, mintBKey] = getSortedMints();
 
             await program.methods
-                .initializePool()
+                .initializePool(poolFeeBps, { constantProduct: {} }, new BN(0), null)
                 .accounts({
                     initializer: intializer.publicKey,
+                    config: configPda,
                     tokenAMint: mintAkey,
                     tokenBMint: mintBKey,
                     pool: poolPda,
+                    observations: getObservationsPda(poolPda),
                     poolAuthority: poolAuthorityPda,
                     tokenAVault: poolTokenAVault,
                     tokenBVault: poolTokenBVault,
+                    lpMint: lpMintPda,
                     tokenProgram: TOKEN_PROGRAM_ID,
                     associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
                     systemProgram: SystemProgram.programId,
@@ -296,6 +410,9 @@ describe("web3-for-dummies", () => {
             assert.ok(poolAccount.tokenAVault.equals(poolTokenAVault), "Pool vault A mismatch");
             assert.ok(poolAccount.tokenBVault.equals(poolTokenBVault), "Pool vault B mismatch");
             assert.equal(poolAccount.bump, poolBump, "Pool bump mismatch");
+            assert.equal(poolAccount.feeBps, poolFeeBps, "Pool fee mismatch");
+            assert.equal(poolAccount.maxPriceImpactBps, configParams.defaultMaxPriceImpactBps, "Pool price impact limit mismatch");
+            assert.equal(poolAccount.depositRatioToleranceBps, configParams.depositRatioToleranceBps, "Pool deposit tolerance mismatch");
 
             const vaultAInfo = await getAccount(provider.connection, poolTokenAVault);
             const vaultBInfo = await getAccount(provider.connection, poolTokenBVault);
@@ -308,15 +425,18 @@ describe("web3-for-dummies", () => {
 
             try {
                 await program.methods
-                    .initializePool()
+                    .initializePool(poolFeeBps, { constantProduct: {} }, new BN(0), null)
                     .accounts({
                         initializer: intializer.publicKey,
+                        config: configPda,
                         tokenAMint: mintAKey,
                         tokenBMint: mintBKey,
                         pool: poolPda,
+                        observations: getObservationsPda(poolPda),
                         poolAuthority: poolAuthorityPda,
                         tokenAVault: poolTokenAVault,
                         tokenBVault: poolTokenBVault,
+                        lpMint: lpMintPda,
                         tokenProgram: TOKEN_PROGRAM_ID,
                         associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
                         systemProgram: SystemProgram.programId,
@@ -329,6 +449,111 @@ describe("web3-for-dummies", () => {
                 assert.include(e.toString(), "Simulation failed", "Expected initialization error");
             }
         });
+
+        it("Fails to initialize a pool with mints in non-canonical order", async () => {
+            const [mintAkey, mintBKey] = getSortedMints();
+
+            // Reversed seeds would derive a second pool for the same pair
+            const [reversedPool] = PublicKey.findProgramAddressSync(
+                [Buffer.from("pool"), mintBKey.toBuffer(), mintAkey.toBuffer()],
+                program.programId
+            );
+            const [reversedLpMint] = PublicKey.findProgramAddressSync(
+                [Buffer.from("lp_mint"), reversedPool.toBuffer()],
+                program.programId
+            );
+
+            try {
+                await program.methods
+                    .initializePool(poolFeeBps, { constantProduct: {} }, new BN(0), null)
+                    .accounts({
+                        config: configPda,
+                        initializer: intializer.publicKey,
+                        tokenAMint: mintBKey,
+                        tokenBMint: mintAkey,
+                        pool: reversedPool,
+                        observations: getObservationsPda(reversedPool),
+                        poolAuthority: reversedPool,
+                        tokenAVault: await getAssociatedTokenAddress(mintBKey, reversedPool, true),
+                        tokenBVault: await getAssociatedTokenAddress(mintAkey, reversedPool, true),
+                        lpMint: reversedLpMint,
+                        tokenProgram: TOKEN_PROGRAM_ID,
+                        associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+                        systemProgram: SystemProgram.programId,
+                    } as any)
+                    .signers([intializer])
+                    .rpc();
+                assert.fail("Should have failed due to non-canonical mint order");
+            } catch (e) {
+                assert.include(e.toString(), "NonCanonicalMintOrder", "Expected NonCanonicalMintOrder error");
+            }
+        });
+
+        it("Fails to initialize a pool with a fee above the maximum", async () => {
+            const mintX = await createMint(provider.connection, payer, mintAuthority.publicKey, null, decimals);
+            const mintY = await createMint(provider.connection, payer, mintAuthority.publicKey, null, decimals);
+            const [mintXKey, mintYKey] = [mintX, mintY].sort((a, b) => a.toBuffer().compare(b.toBuffer()));
+
+            const [otherPool] = PublicKey.findProgramAddressSync(
+                [Buffer.from("pool"), mintXKey.toBuffer(), mintYKey.toBuffer()],
+                program.programId
+            );
+            const [otherLpMint] = PublicKey.findProgramAddressSync(
+                [Buffer.from("lp_mint"), otherPool.toBuffer()],
+                program.programId
+            );
+
+            try {
+                await program.methods
+                    .initializePool(1001, { constantProduct: {} }, new BN(0), null)
+                    .accounts({
+                        initializer: intializer.publicKey,
+                        config: configPda,
+                        tokenAMint: mintXKey,
+                        tokenBMint: mintYKey,
+                        pool: otherPool,
+                        observations: getObservationsPda(otherPool),
+                        poolAuthority: otherPool,
+                        tokenAVault: await getAssociatedTokenAddress(mintXKey, otherPool, true),
+                        tokenBVault: await getAssociatedTokenAddress(mintYKey, otherPool, true),
+                        lpMint: otherLpMint,
+                        tokenProgram: TOKEN_PROGRAM_ID,
+                        associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+                        systemProgram: SystemProgram.programId,
+                    } as any)
+                    .signers([intializer])
+                    .rpc();
+                assert.fail("Should have failed due to fee above maximum");
+            } catch (e) {
+                assert.include(e.toString(), "FeeTooHigh", "Expected FeeTooHigh error");
+            }
+        });
+
+        it("Fails to migrate a pool that already has the current layout", async () => {
+            // The pool's LP mint and observation buffer already exist, so they cannot be created again
+            try {
+                await program.methods
+                    .migratePool()
+                    .accounts({
+                        config: configPda,
+                        tokenAMint: mintAkey,
+                        tokenBMint: mintBKey,
+                        pool: poolPda,
+                        tokenAVault: poolTokenAVault,
+                        tokenBVault: poolTokenBVault,
+                        lpMint: lpMintPda,
+                        observations: getObservationsPda(poolPda),
+                        admin: payer.publicKey,
+                        tokenProgram: TOKEN_PROGRAM_ID,
+                        systemProgram: SystemProgram.programId,
+                    } as any)
+                    .signers([payer])
+                    .rpc();
+                assert.fail("Should have failed because the pool is already migrated");
+            } catch (e) {
+                assert.notInclude(e.toString(), "Should have failed");
+            }
+        });
     });
 
     describe("add_liquidity", () => {
@@ -345,15 +570,18 @@ describe("web3-for-dummies", () => {
                 const [mintAkey, mintBKey] = getSortedMints();
                 try {
                     await program.methods
-                        .initializePool()
+                        .initializePool(poolFeeBps, { constantProduct: {} }, new BN(0), null)
                         .accounts({
                             initializer: intializer.publicKey,
+                            config: configPda,
                             tokenAMint: mintAkey,
                             tokenBMint: mintBKey,
                             pool: poolPda,
+                            observations: getObservationsPda(poolPda),
                             poolAuthority: poolAuthorityPda,
                             tokenAVault: poolTokenAVault,
                             tokenBVault: poolTokenBVault,
+                            lpMint: lpMintPda,
                             tokenProgram: TOKEN_PROGRAM_ID,
                             associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
                             systemProgram: SystemProgram.programId,
@@ -380,10 +608,11 @@ describe("web3-for-dummies", () => {
                 const poolAccount = await program.account.liquidityPool.fetch(poolPda);
 
                 await program.methods
-                    .addLiquidity(initialLiquidityA, initialLiquidityB)
+                    .addLiquidity(initialLiquidityA, initialLiquidityB, new BN(0), null)
                     .accounts({
                         userAuthority: alice.publicKey,
                         pool: poolPda,
+                        observations: getObservationsPda(poolPda),
                         poolAuthority: poolAuthorityPda,
                         // Use the mints from the pool account, not the original mint variables
                         tokenAMint: poolAccount.tokenAMint,
@@ -392,6 +621,8 @@ describe("web3-for-dummies", () => {
                         userTokenBAccount: aliceTokenBAccount,
                         tokenAVault: poolTokenAVault,
                         tokenBVault: poolTokenBVault,
+                        lpMint: lpMintPda,
+                        userLpTokenAccount: await getAliceLpAccount(),
                         tokenProgram: TOKEN_PROGRAM_ID,
                     } as any)
                     .signers([alice])
@@ -405,6 +636,11 @@ describe("web3-for-dummies", () => {
                 assert.equal(aliceB_before - aliceB_after, initialLiquidityB.toNumber(), "Alice B balance change mismatch");
                 assert.equal(vaultA_after - vaultA_before, initialLiquidityA.toNumber(), "Vault A balance change mismatch");
                 assert.equal(vaultB_after - vaultB_before, initialLiquidityB.toNumber(), "Vault B balance change mismatch");
+
+                // First deposit mints sqrt(amount_a * amount_b) LP shares, minus the locked minimum liquidity
+                const aliceLp = await getTokenBalance(aliceLpTokenAccount);
+                assert.equal(aliceLp, initialLiquidityA.toNumber() - MINIMUM_LIQUIDITY, "Alice LP share mismatch");
+                assert.equal((await program.account.liquidityPool.fetch(poolPda)).lockedLpAmount.toNumber(), MINIMUM_LIQUIDITY, "Locked LP mismatch");
             } catch (e) {
                 console.log("Skipping add_liquidity test - pool not initialized");
                 return;
@@ -429,10 +665,11 @@ describe("web3-for-dummies", () => {
             const vaultB_before = await getTokenBalance(poolTokenBVault)
 
             await program.methods
-                .addLiquidity(subsequentLiquidityA, subsequentLiquidityB)
+                .addLiquidity(subsequentLiquidityA, subsequentLiquidityB, new BN(0), null)
                 .accounts({
                     userAuthority: alice.publicKey,
                     pool: poolPda,
+                    observations: getObservationsPda(poolPda),
                     poolAuthority: poolAuthorityPda,
                     tokenAMint: poolAccounts.tokenAMint,
                     tokenBMint: poolAccounts.tokenBMint,
@@ -440,6 +677,8 @@ describe("web3-for-dummies", () => {
                     userTokenBAccount: poolAccounts.tokenBMint.equals(tokenBMint) ? aliceTokenBAccount : aliceTokenAAccount,
                     tokenAVault: poolAccounts.tokenAVault,
                     tokenBVault: poolAccounts.tokenBVault,
+                    lpMint: lpMintPda,
+                    userLpTokenAccount: await getAliceLpAccount(),
                     tokenProgram: TOKEN_PROGRAM_ID,
                 } as any)
                 .signers([alice])
@@ -457,13 +696,42 @@ describe("web3-for-dummies", () => {
 
         });
 
+        it("Fails to add liquidity below the minimum LP amount", async () => {
+            const amount = new BN(10 * (10 ** decimals));
+            try {
+                await program.methods
+                    .addLiquidity(amount, amount, amount.muln(2), null)
+                    .accounts({
+                        userAuthority: alice.publicKey,
+                        pool: poolPda,
+                        observations: getObservationsPda(poolPda),
+                        poolAuthority: poolAuthorityPda,
+                        tokenAMint: tokenAMint,
+                        tokenBMint: tokenBMint,
+                        userTokenAAccount: aliceTokenAAccount,
+                        userTokenBAccount: aliceTokenBAccount,
+                        tokenAVault: poolTokenAVault,
+                        tokenBVault: poolTokenBVault,
+                        lpMint: lpMintPda,
+                        userLpTokenAccount: await getAliceLpAccount(),
+                        tokenProgram: TOKEN_PROGRAM_ID,
+                    } as any)
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed because fewer LP shares would be minted");
+            } catch (e) {
+                assert.include(e.toString(), "SlippageExceeded", "Expected SlippageExceeded error");
+            }
+        });
+
         it("Fails to add liquidity with zero amount", async () => {
             try {
                 await program.methods
-                    .addLiquidity(new BN(0), new BN(10 * (10 ** decimals)))
+                    .addLiquidity(new BN(0), new BN(10 * (10 ** decimals)), new BN(0), null)
                     .accounts({
                         userAuthority: alice.publicKey,
                         pool: poolPda,
+                        observations: getObservationsPda(poolPda),
                         poolAuthority: poolAuthorityPda,
                         tokenAMint: tokenAMint,
                         tokenBMint: tokenBMint,
@@ -471,6 +739,8 @@ describe("web3-for-dummies", () => {
                         userTokenBAccount: aliceTokenBAccount,
                         tokenAVault: poolTokenAVault,
                         tokenBVault: poolTokenBVault,
+                        lpMint: lpMintPda,
+                        userLpTokenAccount: await getAliceLpAccount(),
                         tokenProgram: TOKEN_PROGRAM_ID,
                     } as any)
                     .signers([alice])
@@ -488,10 +758,11 @@ describe("web3-for-dummies", () => {
 
             try {
                 await program.methods
-                    .addLiquidity(disproportionateLiquidityA, disproportionateLiquidityB)
+                    .addLiquidity(disproportionateLiquidityA, disproportionateLiquidityB, new BN(0), null)
                     .accounts({
                         userAuthority: alice.publicKey,
                         pool: poolPda,
+                        observations: getObservationsPda(poolPda),
                         poolAuthority: poolAuthorityPda,
                         tokenAMint: tokenAMint,
                         tokenBMint: tokenBMint,
@@ -499,6 +770,8 @@ describe("web3-for-dummies", () => {
                         userTokenBAccount: aliceTokenBAccount,
                         tokenAVault: poolTokenAVault,
                         tokenBVault: poolTokenBVault,
+                        lpMint: lpMintPda,
+                        userLpTokenAccount: await getAliceLpAccount(),
                         tokenProgram: TOKEN_PROGRAM_ID
                     } as any)
                     .signers([alice])
@@ -536,15 +809,18 @@ describe("web3-for-dummies", () => {
 
                     try {
                         await program.methods
-                            .initializePool()
+                            .initializePool(poolFeeBps, { constantProduct: {} }, new BN(0), null)
                             .accounts({
                                 initializer: intializer.publicKey,
+                                config: configPda,
                                 tokenAMint: mintAkey,
                                 tokenBMint: mintBKey,
                                 pool: poolPda,
+                                observations: getObservationsPda(poolPda),
                                 poolAuthority: poolAuthorityPda,
                                 tokenAVault: poolTokenAVault,
                                 tokenBVault: poolTokenBVault,
+                                lpMint: lpMintPda,
                                 tokenProgram: TOKEN_PROGRAM_ID,
                                 associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
                                 systemProgram: SystemProgram.programId,
@@ -571,7 +847,7 @@ describe("web3-for-dummies", () => {
 
                 try {
                     await program.methods
-                        .addLiquidity(liquidityAmount, liquidityAmount)
+                        .addLiquidity(liquidityAmount, liquidityAmount, new BN(0), null)
                         .accounts({
                             userAuthority: alice.publicKey,
                             pool: poolPda,
@@ -582,6 +858,8 @@ describe("web3-for-dummies", () => {
                             userTokenBAccount: aliceTokenBAccount,
                             tokenAVault: poolAccounts.tokenAVault,
                             tokenBVault: poolAccounts.tokenBVault,
+                            lpMint: lpMintPda,
+                            userLpTokenAccount: await getAliceLpAccount(),
                             tokenProgram: TOKEN_PROGRAM_ID,
                         } as any)
                         .signers([alice])
@@ -604,9 +882,9 @@ describe("web3-for-dummies", () => {
                 return new BN(0);
             }
 
-            const feeNumerator = BigInt(3);
-            const feeDenominator = BigInt(1000);
-            const amountInAfterFee = (amountInU128 * (feeDenominator - feeNumerator)) / feeDenominator;
+            const feeBps = BigInt(poolFeeBps);
+            const bpsDenominator = BigInt(10000);
+            const amountInAfterFee = (amountInU128 * (bpsDenominator - feeBps)) / bpsDenominator;
 
             const constantProduct = reserveInU128 * reserveOutU128;
             const newReserveIn = reserveInU128 + amountInAfterFee;
@@ -639,10 +917,11 @@ describe("web3-for-dummies", () => {
             assert.ok(expectedBOut.gt(new BN(0)), "Expected output should be positive");
 
             await program.methods
-                .swap(swapAmountA, expectedBOut.muln(98).divn(100)) // Swap A, min B out (use expectedBOut)
+                .swap(swapAmountA, expectedBOut.muln(98).divn(100), null) // Swap A, min B out (use expectedBOut)
                 .accounts({
                     userAuthority: alice.publicKey,
                     pool: poolPda,
+                    observations: getObservationsPda(poolPda),
                     poolAuthority: poolAuthorityPda,
                     // Corrected mints for A -> B swap
                     sourceMint: poolAccounts.tokenAMint,      // Source is A
@@ -692,10 +971,11 @@ describe("web3-for-dummies", () => {
             assert.ok(expectedAOut.gt(new BN(0)), "Expected output should be positive");
 
             await program.methods
-                .swap(swapAmountB, expectedAOut.muln(98).divn(100)) // Swap B, min A out
+                .swap(swapAmountB, expectedAOut.muln(98).divn(100), null) // Swap B, min A out
                 .accounts({
                     userAuthority: alice.publicKey,
                     pool: poolPda,
+                    observations: getObservationsPda(poolPda),
                     poolAuthority: poolAuthorityPda,
                     // Corrected mints for B -> A swap
                     sourceMint: poolAccounts.tokenBMint,      // Source is B
@@ -730,10 +1010,11 @@ describe("web3-for-dummies", () => {
             const poolAccounts = await getPoolAccounts();
             try {
                 await program.methods
-                    .swap(new BN(0), mintAmountBOut)
+                    .swap(new BN(0), mintAmountBOut, null)
                     .accounts({
                         userAuthority: alice.publicKey,
                         pool: poolPda,
+                        observations: getObservationsPda(poolPda),
                         poolAuthority: poolAuthorityPda,
                         sourceMint: poolAccounts.tokenAMint,  // Use from pool accounts
                         destinationMint: poolAccounts.tokenBMint,  // Use from pool accounts
@@ -762,10 +1043,11 @@ describe("web3-for-dummies", () => {
 
             try {
                 await program.methods
-                    .swap(swapAmountA, tooHighMInAmountOut)
+                    .swap(swapAmountA, tooHighMInAmountOut, null)
                     .accounts({
                         userAuthority: alice.publicKey,
                         pool: poolPda,
+                        observations: getObservationsPda(poolPda),
                         poolAuthority: poolAuthorityPda,
                         sourceMint: poolAccounts.tokenAMint,  // Use from pool accounts
                         destinationMint: poolAccounts.tokenBMint,  // Use from pool accounts
@@ -783,15 +1065,44 @@ describe("web3-for-dummies", () => {
             }
         });
 
+        it("Fails swap after the deadline has passed", async () => {
+            const poolAccounts = await getPoolAccounts();
+            const expiredDeadline = new BN(Math.floor(Date.now() / 1000) - 60);
+
+            try {
+                await program.methods
+                    .swap(swapAmountA, mintAmountBOut, expiredDeadline)
+                    .accounts({
+                        userAuthority: alice.publicKey,
+                        pool: poolPda,
+                        observations: getObservationsPda(poolPda),
+                        poolAuthority: poolAuthorityPda,
+                        sourceMint: poolAccounts.tokenAMint,
+                        destinationMint: poolAccounts.tokenBMint,
+                        userSourceTokenAccount: aliceTokenAAccount,
+                        userDestinationTokenAccount: aliceTokenBAccount,
+                        tokenAVault: poolAccounts.tokenAVault,
+                        tokenBVault: poolAccounts.tokenBVault,
+                        tokenProgram: TOKEN_PROGRAM_ID
+                    } as any)
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed due to expired deadline");
+            } catch (error) {
+                assert.include(error.toString(), "Expired", "Expected Expired error");
+            }
+        });
+
         it("Fails swap with invalid owner for source account", async () => {
             const poolAccounts = await getPoolAccounts();
 
             try {
                 await program.methods
-                    .swap(swapAmountA, swapAmountB)
+                    .swap(swapAmountA, swapAmountB, null)
                     .accounts({
                         userAuthority: alice.publicKey,
                         pool: poolPda,
+                        observations: getObservationsPda(poolPda),
                         poolAuthority: poolAuthorityPda,
                         sourceMint: poolAccounts.tokenAMint,  // Use from pool accounts
                         destinationMint: poolAccounts.tokenBMint,  // Use from pool accounts
@@ -819,10 +1130,11 @@ describe("web3-for-dummies", () => {
 
             try {
                 await program.methods
-                    .swap(swapAmountA, swapAmountB)
+                    .swap(swapAmountA, swapAmountB, null)
                     .accounts({
                         userAuthority: alice.publicKey,
                         pool: poolPda,
+                        observations: getObservationsPda(poolPda),
                         poolAuthority: poolAuthorityPda,
                         sourceMint: poolAccounts.tokenAMint,  // Use from pool accounts
                         destinationMint: poolAccounts.tokenBMint,  // Use from pool accounts
@@ -850,10 +1162,11 @@ describe("web3-for-dummies", () => {
 
             try {
                 await program.methods
-                    .swap(swapAmountA, mintAmountBOut)
+                    .swap(swapAmountA, mintAmountBOut, null)
                     .accounts({
                         userAuthority: alice.publicKey,
                         pool: poolPda,
+                        observations: getObservationsPda(poolPda),
                         poolAuthority: poolAuthorityPda,
                         sourceMint: poolAccounts.tokenAMint,  // Use from pool accounts
                         destinationMint: poolAccounts.tokenBMint,  // Use from pool accounts
@@ -871,4 +1184,2128 @@ describe("web3-for-dummies", () => {
             }
         });
     });
-})
\ No newline at end of file
+
+    describe("quote_swap", () => {
+        it("Quotes the same output that swap pays", async () => {
+            const poolAccounts = await getPoolAccounts();
+            const amountIn = new BN(10 * (10 ** decimals));
+
+            const quote = await program.methods
+                .quoteSwap(amountIn, { aToB: {} })
+                .accounts({
+                    pool: poolPda,
+                    tokenAVault: poolAccounts.tokenAVault,
+                    tokenBVault: poolAccounts.tokenBVault,
+                } as any)
+                .view();
+
+            const aliceB_before = await getTokenBalance(aliceTokenBAccount);
+            await program.methods
+                .swap(amountIn, quote.amountOut, null)
+                .accounts({
+                    userAuthority: alice.publicKey,
+                    pool: poolPda,
+                    observations: getObservationsPda(poolPda),
+                    poolAuthority: poolAuthorityPda,
+                    sourceMint: poolAccounts.tokenAMint,
+                    destinationMint: poolAccounts.tokenBMint,
+                    userSourceTokenAccount: aliceTokenAAccount,
+                    userDestinationTokenAccount: aliceTokenBAccount,
+                    tokenAVault: poolAccounts.tokenAVault,
+                    tokenBVault: poolAccounts.tokenBVault,
+                    tokenProgram: TOKEN_PROGRAM_ID
+                } as any)
+                .signers([alice])
+                .rpc();
+
+            assert.equal(await getTokenBalance(aliceTokenBAccount) - aliceB_before, quote.amountOut.toNumber(), "Quoted amount out mismatch");
+            assert.equal(quote.feeAmount.toNumber(), amountIn.toNumber() * poolFeeBps / 10000, "Quoted fee mismatch");
+        });
+
+        it("Fails to quote a swap too small to pay anything out", async () => {
+            const poolAccounts = await getPoolAccounts();
+
+            // The fee leaves nothing of a single unit to price
+            try {
+                await program.methods
+                    .quoteSwap(new BN(1), { aToB: {} })
+                    .accounts({
+                        pool: poolPda,
+                        tokenAVault: poolAccounts.tokenAVault,
+                        tokenBVault: poolAccounts.tokenBVault,
+                    } as any)
+                    .view();
+                assert.fail("Should have failed because the swap pays nothing out");
+            } catch (e) {
+                assert.include(e.toString(), "SwapTooSmall", "Expected SwapTooSmall error");
+            }
+        });
+
+        it("Fails to quote a swap with excessive price impact", async () => {
+            const poolAccounts = await getPoolAccounts();
+            const reserveB = await getTokenBalance(poolAccounts.tokenBVault);
+
+            try {
+                await program.methods
+                    .quoteSwapExactOut(new BN(Math.floor(reserveB / 2)), { aToB: {} })
+                    .accounts({
+                        pool: poolPda,
+                        tokenAVault: poolAccounts.tokenAVault,
+                        tokenBVault: poolAccounts.tokenBVault,
+                    } as any)
+                    .view();
+                assert.fail("Should have failed due to price impact");
+            } catch (e) {
+                assert.include(e.toString(), "ExcessivePriceImpact", "Expected ExcessivePriceImpact error");
+            }
+        });
+    });
+
+    describe("get_twap", () => {
+        it("Returns the time-weighted price since a stored observation", async () => {
+            const poolAccount = await program.account.liquidityPool.fetch(poolPda);
+            const start = {
+                timestamp: poolAccount.lastUpdateTs,
+                priceACumulative: poolAccount.priceACumulative,
+                priceBCumulative: poolAccount.priceBCumulative,
+            };
+
+            // Let the cluster clock move past the observation
+            await new Promise((resolve) => setTimeout(resolve, 2000));
+
+            const twap = await program.methods
+                .getTwap(start)
+                .accounts({
+                    pool: poolPda,
+                    tokenAVault: poolAccount.tokenAVault,
+                    tokenBVault: poolAccount.tokenBVault,
+                } as any)
+                .view();
+
+            // Prices are Q64.64, so a balanced pool averages close to 2^64 in both directions
+            const one = new BN(1).shln(64);
+            assert.ok(twap.priceA.gt(one.divn(2)) && twap.priceA.lt(one.muln(2)), "TWAP price A out of range");
+            assert.ok(twap.priceB.gt(one.divn(2)) && twap.priceB.lt(one.muln(2)), "TWAP price B out of range");
+        });
+    });
+
+    describe("observations", () => {
+        it("Grows the observation buffer", async () => {
+            const observationsPda = getObservationsPda(poolPda);
+
+            await program.methods
+                .increaseObservationCardinality(8)
+                .accounts({
+                    pool: poolPda,
+                    observations: observationsPda,
+                    payer: payer.publicKey,
+                    systemProgram: SystemProgram.programId,
+                } as any)
+                .signers([payer])
+                .rpc();
+
+            const observations = await program.account.poolObservations.fetch(observationsPda);
+            assert.equal(observations.observations.length, 8, "Observation buffer length mismatch");
+            assert.ok(observations.pool.equals(poolPda), "Observation pool mismatch");
+        });
+
+        it("Fails to grow the observation buffer beyond the maximum", async () => {
+            try {
+                await program.methods
+                    .increaseObservationCardinality(129)
+                    .accounts({
+                        pool: poolPda,
+                        observations: getObservationsPda(poolPda),
+                        payer: payer.publicKey,
+                        systemProgram: SystemProgram.programId,
+                    } as any)
+                    .signers([payer])
+                    .rpc();
+                assert.fail("Should have failed because the buffer would exceed the maximum cardinality");
+            } catch (e) {
+                assert.include(e.toString(), "InvalidObservationCardinality", "Expected InvalidObservationCardinality error");
+            }
+        });
+
+        it("Fails to observe further back than the buffer reaches", async () => {
+            const poolAccount = await program.account.liquidityPool.fetch(poolPda);
+
+            try {
+                await program.methods
+                    .observeTwap(365 * 24 * 3600)
+                    .accounts({
+                        pool: poolPda,
+                        observations: getObservationsPda(poolPda),
+                        tokenAVault: poolAccount.tokenAVault,
+                        tokenBVault: poolAccount.tokenBVault,
+                    } as any)
+                    .view();
+                assert.fail("Should have failed due to missing history");
+            } catch (e) {
+                assert.include(e.toString(), "ObservationTooOld", "Expected ObservationTooOld error");
+            }
+        });
+    });
+
+    describe("swap_exact_out", () => {
+        const calculateExpectedIn = (amountOut: BN, reserveIn: BN, reserveOut: BN): BN => {
+            const amountOutU128 = BigInt(amountOut.toString());
+            const reserveInU128 = BigInt(reserveIn.toString());
+            const reserveOutU128 = BigInt(reserveOut.toString());
+            const bpsDenominator = BigInt(10000);
+            const ceilDiv = (a: bigint, b: bigint) => (a + b - BigInt(1)) / b;
+
+            const amountInAfterFee = ceilDiv(reserveInU128 * amountOutU128, reserveOutU128 - amountOutU128);
+            const amountIn = ceilDiv(amountInAfterFee * bpsDenominator, bpsDenominator - BigInt(poolFeeBps));
+            return new BN(amountIn.toString());
+        };
+
+        it("Swaps Token A for an exact amount of Token B", async () => {
+            const poolAccount = await program.account.liquidityPool.fetch(poolPda);
+            const reserveA = await getTokenBalance(poolTokenAVault) - poolAccount.protocolFeesOwedA.toNumber();
+            const reserveB = await getTokenBalance(poolTokenBVault) - poolAccount.protocolFeesOwedB.toNumber();
+            if (reserveA <= 0 || reserveB <= 0) {
+                console.log("Skipping test - pool has no liquidity");
+                return;
+            }
+
+            const exactOut = new BN(5 * (10 ** decimals));
+            const expectedIn = calculateExpectedIn(exactOut, new BN(reserveA), new BN(reserveB));
+
+            const aliceA_before = await getTokenBalance(aliceTokenAAccount);
+            const aliceB_before = await getTokenBalance(aliceTokenBAccount);
+
+            await program.methods
+                .swapExactOut(exactOut, expectedIn, null)
+                .accounts({
+                    userAuthority: alice.publicKey,
+                    pool: poolPda,
+                    observations: getObservationsPda(poolPda),
+                    poolAuthority: poolAuthorityPda,
+                    sourceMint: poolAccount.tokenAMint,
+                    destinationMint: poolAccount.tokenBMint,
+                    userSourceTokenAccount: aliceTokenAAccount,
+                    userDestinationTokenAccount: aliceTokenBAccount,
+                    tokenAVault: poolAccount.tokenAVault,
+                    tokenBVault: poolAccount.tokenBVault,
+                    tokenProgram: TOKEN_PROGRAM_ID
+                } as any)
+                .signers([alice])
+                .rpc();
+
+            assert.equal(await getTokenBalance(aliceTokenBAccount) - aliceB_before, exactOut.toNumber(), "Exact amount B out mismatch");
+            assert.equal(aliceA_before - await getTokenBalance(aliceTokenAAccount), expectedIn.toNumber(), "Amount A in mismatch");
+        });
+
+        it("Fails exact-out swap when max amount in is too low", async () => {
+            const poolAccount = await program.account.liquidityPool.fetch(poolPda);
+
+            try {
+                await program.methods
+                    .swapExactOut(new BN(5 * (10 ** decimals)), new BN(1), null)
+                    .accounts({
+                        userAuthority: alice.publicKey,
+                        pool: poolPda,
+                        observations: getObservationsPda(poolPda),
+                        poolAuthority: poolAuthorityPda,
+                        sourceMint: poolAccount.tokenAMint,
+                        destinationMint: poolAccount.tokenBMint,
+                        userSourceTokenAccount: aliceTokenAAccount,
+                        userDestinationTokenAccount: aliceTokenBAccount,
+                        tokenAVault: poolAccount.tokenAVault,
+                        tokenBVault: poolAccount.tokenBVault,
+                        tokenProgram: TOKEN_PROGRAM_ID
+                    } as any)
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed due to slippage");
+            } catch (e) {
+                assert.include(e.toString(), "SlippageExceeded", "Expected SlippageExceeded error");
+            }
+        });
+    });
+
+    describe("route_swap", () => {
+        let tokenCMint: PublicKey;
+        let aliceTokenCAccount: PublicKey;
+        let secondPool: PublicKey;
+        let secondPoolAccount: any;
+
+        before(async () => {
+            // Second pool between Token B and a new Token C, so A -> B -> C needs two hops
+            tokenCMint = await createMint(provider.connection, payer, mintAuthority.publicKey, null, decimals);
+            aliceTokenCAccount = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, tokenCMint, alice.publicKey)).address;
+            await mintTo(provider.connection, payer, tokenCMint, aliceTokenCAccount, mintAuthority, BigInt(initialMintAmount));
+
+            const [mintXKey, mintYKey] = [tokenBMint, tokenCMint].sort((a, b) => a.toBuffer().compare(b.toBuffer()));
+            [secondPool] = PublicKey.findProgramAddressSync(
+                [Buffer.from("pool"), mintXKey.toBuffer(), mintYKey.toBuffer()],
+                program.programId
+            );
+            const [secondLpMint] = PublicKey.findProgramAddressSync(
+                [Buffer.from("lp_mint"), secondPool.toBuffer()],
+                program.programId
+            );
+            const vaultX = await getAssociatedTokenAddress(mintXKey, secondPool, true);
+            const vaultY = await getAssociatedTokenAddress(mintYKey, secondPool, true);
+
+            await program.methods
+                .initializePool(poolFeeBps, { constantProduct: {} }, new BN(0), null)
+                .accounts({
+                    config: configPda,
+                    initializer: intializer.publicKey,
+                    tokenAMint: mintXKey,
+                    tokenBMint: mintYKey,
+                    pool: secondPool,
+                    observations: getObservationsPda(secondPool),
+                    poolAuthority: secondPool,
+                    tokenAVault: vaultX,
+                    tokenBVault: vaultY,
+                    lpMint: secondLpMint,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                } as any)
+                .signers([intializer])
+                .rpc();
+
+            const aliceSecondLp = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, secondLpMint, alice.publicKey)).address;
+            const liquidityAmount = new BN(1000 * (10 ** decimals));
+            await program.methods
+                .addLiquidity(liquidityAmount, liquidityAmount, new BN(0), null)
+                .accounts({
+                    userAuthority: alice.publicKey,
+                    pool: secondPool,
+                    poolAuthority: secondPool,
+                    tokenAMint: mintXKey,
+                    tokenBMint: mintYKey,
+                    userTokenAAccount: mintXKey.equals(tokenBMint) ? aliceTokenBAccount : aliceTokenCAccount,
+                    userTokenBAccount: mintYKey.equals(tokenBMint) ? aliceTokenBAccount : aliceTokenCAccount,
+                    tokenAVault: vaultX,
+                    tokenBVault: vaultY,
+                    lpMint: secondLpMint,
+                    userLpTokenAccount: aliceSecondLp,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                } as any)
+                .signers([alice])
+                .rpc();
+
+            secondPoolAccount = await program.account.liquidityPool.fetch(secondPool);
+        });
+
+        const vaultFor = (pool: any, mint: PublicKey): PublicKey =>
+            pool.tokenAMint.equals(mint) ? pool.tokenAVault : pool.tokenBVault;
+
+        it("Swaps Token A for Token C through two pools", async () => {
+            const firstPoolAccount = await program.account.liquidityPool.fetch(poolPda);
+            const amountIn = new BN(10 * (10 ** decimals));
+
+            const aliceA_before = await getTokenBalance(aliceTokenAAccount);
+            const aliceC_before = await getTokenBalance(aliceTokenCAccount);
+
+            const remainingAccounts = [
+                // Hop 1: A -> B
+                { pubkey: poolPda, isWritable: true, isSigner: false },
+                { pubkey: poolAuthorityPda, isWritable: false, isSigner: false },
+                { pubkey: vaultFor(firstPoolAccount, tokenAMint), isWritable: true, isSigner: false },
+                { pubkey: vaultFor(firstPoolAccount, tokenBMint), isWritable: true, isSigner: false },
+                { pubkey: tokenAMint, isWritable: false, isSigner: false },
+                { pubkey: tokenBMint, isWritable: false, isSigner: false },
+                { pubkey: getObservationsPda(poolPda), isWritable: true, isSigner: false },
+                // Hop 2: B -> C
+                { pubkey: secondPool, isWritable: true, isSigner: false },
+                { pubkey: secondPool, isWritable: false, isSigner: false },
+                { pubkey: vaultFor(secondPoolAccount, tokenBMint), isWritable: true, isSigner: false },
+                { pubkey: vaultFor(secondPoolAccount, tokenCMint), isWritable: true, isSigner: false },
+                { pubkey: tokenBMint, isWritable: false, isSigner: false },
+                { pubkey: tokenCMint, isWritable: false, isSigner: false },
+                { pubkey: getObservationsPda(secondPool), isWritable: true, isSigner: false },
+            ];
+
+            await program.methods
+                .routeSwap(amountIn, new BN(1), 2, null)
+                .accounts({
+                    userAuthority: alice.publicKey,
+                    userSourceTokenAccount: aliceTokenAAccount,
+                    userDestinationTokenAccount: aliceTokenCAccount,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                } as any)
+                .remainingAccounts(remainingAccounts)
+                .signers([alice])
+                .rpc();
+
+            assert.equal(aliceA_before - await getTokenBalance(aliceTokenAAccount), amountIn.toNumber(), "Alice A balance change mismatch (route)");
+            assert.ok(await getTokenBalance(aliceTokenCAccount) > aliceC_before, "Alice should receive Token C");
+        });
+
+        it("Fails route_swap when the hop count does not match the accounts", async () => {
+            try {
+                await program.methods
+                    .routeSwap(new BN(10 * (10 ** decimals)), new BN(1), 2, null)
+                    .accounts({
+                        userAuthority: alice.publicKey,
+                        userSourceTokenAccount: aliceTokenAAccount,
+                        userDestinationTokenAccount: aliceTokenCAccount,
+                        tokenProgram: TOKEN_PROGRAM_ID,
+                    } as any)
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed due to invalid route");
+            } catch (e) {
+                assert.include(e.toString(), "InvalidRoute", "Expected InvalidRoute error");
+            }
+        });
+    });
+
+    describe("remove_liquidity", () => {
+        it("Burns LP shares and returns both tokens", async () => {
+            const poolAccounts = await getPoolAccounts();
+            if (!poolAccounts) {
+                console.log("Skipping test - pool not initialized");
+                return;
+            }
+
+            const lpAccount = await getAliceLpAccount();
+            const lpBefore = await getTokenBalance(lpAccount);
+            // Protocol fees owed are not part of the reserves paid out to LPs
+            const poolAccount = await program.account.liquidityPool.fetch(poolPda);
+            const vaultA_before = await getTokenBalance(poolTokenAVault) - poolAccount.protocolFeesOwedA.toNumber();
+            const vaultB_before = await getTokenBalance(poolTokenBVault) - poolAccount.protocolFeesOwedB.toNumber();
+            const aliceA_before = await getTokenBalance(aliceTokenAAccount);
+            const aliceB_before = await getTokenBalance(aliceTokenBAccount);
+
+            const lpToBurn = new BN(Math.floor(lpBefore / 2));
+            // The locked minimum liquidity counts towards the supply without being minted
+            const lpSupply = Number((await provider.connection.getTokenSupply(lpMintPda)).value.amount) + poolAccount.lockedLpAmount.toNumber();
+            const expectedA = Math.floor(vaultA_before * lpToBurn.toNumber() / lpSupply);
+            const expectedB = Math.floor(vaultB_before * lpToBurn.toNumber() / lpSupply);
+
+            await program.methods
+                .removeLiquidity(lpToBurn, new BN(expectedA).muln(99).divn(100), new BN(expectedB).muln(99).divn(100), null)
+                .accounts({
+                    userAuthority: alice.publicKey,
+                    pool: poolPda,
+                    observations: getObservationsPda(poolPda),
+                    poolAuthority: poolAuthorityPda,
+                    tokenAMint: poolAccounts.tokenAMint,
+                    tokenBMint: poolAccounts.tokenBMint,
+                    userTokenAAccount: aliceTokenAAccount,
+                    userTokenBAccount: aliceTokenBAccount,
+                    tokenAVault: poolAccounts.tokenAVault,
+                    tokenBVault: poolAccounts.tokenBVault,
+                    lpMint: lpMintPda,
+                    userLpTokenAccount: lpAccount,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                } as any)
+                .signers([alice])
+                .rpc();
+
+            const lpAfter = await getTokenBalance(lpAccount);
+            const aliceA_after = await getTokenBalance(aliceTokenAAccount);
+            const aliceB_after = await getTokenBalance(aliceTokenBAccount);
+
+            assert.equal(lpBefore - lpAfter, lpToBurn.toNumber(), "LP burn mismatch");
+            assert.ok(Math.abs(aliceA_after - aliceA_before - expectedA) <= 1, "Alice A payout mismatch");
+            assert.ok(Math.abs(aliceB_after - aliceB_before - expectedB) <= 1, "Alice B payout mismatch");
+        });
+
+        it("Fails to remove liquidity when minimum amounts are too high", async () => {
+            const poolAccounts = await getPoolAccounts();
+            const lpAccount = await getAliceLpAccount();
+            const vaultA = await getTokenBalance(poolTokenAVault);
+
+            try {
+                await program.methods
+                    .removeLiquidity(new BN(1 * (10 ** decimals)), new BN(vaultA), new BN(0), null)
+                    .accounts({
+                        userAuthority: alice.publicKey,
+                        pool: poolPda,
+                        observations: getObservationsPda(poolPda),
+                        poolAuthority: poolAuthorityPda,
+                        tokenAMint: poolAccounts.tokenAMint,
+                        tokenBMint: poolAccounts.tokenBMint,
+                        userTokenAAccount: aliceTokenAAccount,
+                        userTokenBAccount: aliceTokenBAccount,
+                        tokenAVault: poolAccounts.tokenAVault,
+                        tokenBVault: poolAccounts.tokenBVault,
+                        lpMint: lpMintPda,
+                        userLpTokenAccount: lpAccount,
+                        tokenProgram: TOKEN_PROGRAM_ID,
+                    } as any)
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed due to slippage");
+            } catch (e) {
+                assert.include(e.toString(), "SlippageExceeded", "Expected SlippageExceeded error");
+            }
+        });
+    });
+
+    describe("collect_protocol_fees", () => {
+        it("Sends owed protocol fees to the treasury", async () => {
+            const poolAccount = await program.account.liquidityPool.fetch(poolPda);
+            const config = await program.account.programConfig.fetch(configPda);
+            const owedA = poolAccount.protocolFeesOwedA.toNumber();
+            const owedB = poolAccount.protocolFeesOwedB.toNumber();
+            if (owedA === 0 && owedB === 0) {
+                console.log("Skipping test - no protocol fees owed");
+                return;
+            }
+
+            const treasuryA = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, poolAccount.tokenAMint, config.treasury)).address;
+            const treasuryB = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, poolAccount.tokenBMint, config.treasury)).address;
+            const treasuryA_before = await getTokenBalance(treasuryA);
+            const treasuryB_before = await getTokenBalance(treasuryB);
+
+            await program.methods
+                .collectProtocolFees()
+                .accounts({
+                    config: configPda,
+                    pool: poolPda,
+                    poolAuthority: poolAuthorityPda,
+                    tokenAMint: poolAccount.tokenAMint,
+                    tokenBMint: poolAccount.tokenBMint,
+                    tokenAVault: poolAccount.tokenAVault,
+                    tokenBVault: poolAccount.tokenBVault,
+                    treasuryTokenAAccount: treasuryA,
+                    treasuryTokenBAccount: treasuryB,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                } as any)
+                .rpc();
+
+            assert.equal(await getTokenBalance(treasuryA) - treasuryA_before, owedA, "Treasury A balance change mismatch");
+            assert.equal(await getTokenBalance(treasuryB) - treasuryB_before, owedB, "Treasury B balance change mismatch");
+
+            const poolAfter = await program.account.liquidityPool.fetch(poolPda);
+            assert.equal(poolAfter.protocolFeesOwedA.toNumber(), 0, "Owed A not cleared");
+            assert.equal(poolAfter.protocolFeesOwedB.toNumber(), 0, "Owed B not cleared");
+        });
+    });
+
+    describe("flash_swap", () => {
+        const flashSwapAccounts = async (receiverProgram: PublicKey) => {
+            const poolAccounts = await getPoolAccounts();
+            return {
+                pool: poolPda,
+                poolAuthority: poolAuthorityPda,
+                observations: getObservationsPda(poolPda),
+                tokenAMint: poolAccounts.tokenAMint,
+                tokenBMint: poolAccounts.tokenBMint,
+                tokenAVault: poolAccounts.tokenAVault,
+                tokenBVault: poolAccounts.tokenBVault,
+                receiverTokenAAccount: aliceTokenAAccount,
+                receiverTokenBAccount: aliceTokenBAccount,
+                receiverProgram,
+                userAuthority: alice.publicKey,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            };
+        };
+
+        it("Fails to flash swap into this program as the receiver", async () => {
+            try {
+                await program.methods
+                    .flashSwap(new BN(0), new BN(1 * 10 ** decimals), Buffer.from([]))
+                    .accounts(await flashSwapAccounts(program.programId) as any)
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed because the receiver is this program");
+            } catch (e) {
+                assert.include(e.toString(), "InvalidFlashSwapReceiver", "Expected InvalidFlashSwapReceiver error");
+            }
+        });
+
+        it("Reverts the borrow when the receiver's callback fails", async () => {
+            const poolAccounts = await getPoolAccounts();
+            const vaultB_before = await getTokenBalance(poolAccounts.tokenBVault);
+
+            // The system program has no flash_swap_callback, so nothing is ever repaid
+            try {
+                await program.methods
+                    .flashSwap(new BN(0), new BN(1 * 10 ** decimals), Buffer.from([]))
+                    .accounts(await flashSwapAccounts(SystemProgram.programId) as any)
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed because the receiver did not repay");
+            } catch (e) {
+                assert.notInclude(e.toString(), "Should have failed");
+            }
+
+            assert.equal(await getTokenBalance(poolAccounts.tokenBVault), vaultB_before, "Vault B balance changed");
+            assert.isFalse((await program.account.liquidityPool.fetch(poolPda)).locked, "Pool left locked");
+        });
+
+        // The receiver's `ReceiveFlashSwap` accounts, passed through as remaining accounts
+        const receiverAccounts = async () => {
+            const poolAccounts = await getPoolAccounts();
+            const account = (pubkey: PublicKey, isWritable = false, isSigner = false) => ({ pubkey, isSigner, isWritable });
+            return [
+                account(program.programId),
+                account(poolPda, true),
+                account(poolAuthorityPda),
+                account(getObservationsPda(poolPda), true),
+                account(poolAccounts.tokenAMint),
+                account(poolAccounts.tokenBMint),
+                account(poolAccounts.tokenAVault, true),
+                account(poolAccounts.tokenBVault, true),
+                account(aliceTokenAAccount, true),
+                account(aliceTokenBAccount, true),
+                account(alice.publicKey, false, true),
+                account(TOKEN_PROGRAM_ID),
+                account(anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY),
+            ];
+        };
+
+        // Borsh-encoded `ReceiverAction`s of the receiver program
+        const repayAction = (amountA: BN, amountB: BN) =>
+            Buffer.concat([Buffer.from([0]), amountA.toArrayLike(Buffer, "le", 8), amountB.toArrayLike(Buffer, "le", 8)]);
+        const swapAction = (amountIn: BN) =>
+            Buffer.concat([Buffer.from([1]), amountIn.toArrayLike(Buffer, "le", 8)]);
+        const flashBorrowAction = (amount: BN) =>
+            Buffer.concat([Buffer.from([2]), amount.toArrayLike(Buffer, "le", 8)]);
+
+        const flashSwapThroughReceiver = async (amountBOut: BN, callbackData: Buffer) =>
+            program.methods
+                .flashSwap(new BN(0), amountBOut, callbackData)
+                .accounts(await flashSwapAccounts(flashSwapReceiver.programId) as any)
+                .remainingAccounts(await receiverAccounts())
+                .signers([alice])
+                .rpc();
+
+        it("Flash swaps Token B and repays it with the fee in the callback", async () => {
+            const poolAccounts = await getPoolAccounts();
+            const { feeBps } = await program.account.liquidityPool.fetch(poolPda);
+            const amountOut = new BN(1 * 10 ** decimals);
+            // The fee is charged on the amount paid back: repay * (1 - fee) >= amount out
+            const repay = amountOut.muln(10_000).addn(10_000 - feeBps - 1).divn(10_000 - feeBps);
+            const vaultB_before = await getTokenBalance(poolAccounts.tokenBVault);
+            const aliceB_before = await getTokenBalance(aliceTokenBAccount);
+
+            await flashSwapThroughReceiver(amountOut, repayAction(new BN(0), repay));
+
+            const fee = repay.sub(amountOut).toNumber();
+            assert.isAbove(fee, 0, "No fee paid");
+            assert.equal(await getTokenBalance(poolAccounts.tokenBVault) - vaultB_before, fee, "Vault B should gain the fee");
+            assert.equal(aliceB_before - await getTokenBalance(aliceTokenBAccount), fee, "Alice should pay the fee");
+            assert.isFalse((await program.account.liquidityPool.fetch(poolPda)).locked, "Pool left locked");
+        });
+
+        it("Fails when the callback repays less than the fee", async () => {
+            const amountOut = new BN(1 * 10 ** decimals);
+            try {
+                await flashSwapThroughReceiver(amountOut, repayAction(new BN(0), amountOut));
+                assert.fail("Should have failed because the fee was not paid");
+            } catch (e) {
+                assert.include(e.toString(), "FlashSwapNotRepaid", "Expected FlashSwapNotRepaid error");
+            }
+        });
+
+        it("Fails to swap on the pool from inside the callback", async () => {
+            try {
+                await flashSwapThroughReceiver(new BN(1 * 10 ** decimals), swapAction(new BN(1 * 10 ** decimals)));
+                assert.fail("Should have failed because the pool is locked during the callback");
+            } catch (e) {
+                assert.include(e.toString(), "PoolLocked", "Expected PoolLocked error");
+            }
+        });
+
+        it("Fails to flash borrow from inside the callback", async () => {
+            try {
+                await flashSwapThroughReceiver(new BN(1 * 10 ** decimals), flashBorrowAction(new BN(1 * 10 ** decimals)));
+                assert.fail("Should have failed because flash loans must be top-level");
+            } catch (e) {
+                assert.include(e.toString(), "FlashLoanNotTopLevel", "Expected FlashLoanNotTopLevel error");
+            }
+        });
+    });
+
+    describe("flash_loan", () => {
+        const flashLoanAccounts = async () => {
+            const poolAccounts = await getPoolAccounts();
+            return {
+                pool: poolPda,
+                poolAuthority: poolAuthorityPda,
+                mint: poolAccounts.tokenAMint,
+                vault: poolAccounts.tokenAVault,
+                userTokenAccount: aliceTokenAAccount,
+                userAuthority: alice.publicKey,
+                instructions: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+                tokenProgram: TOKEN_PROGRAM_ID,
+            };
+        };
+
+        it("Borrows and repays Token A with the fee in one transaction", async () => {
+            const accounts = await flashLoanAccounts();
+            const amount = 10 * 10 ** decimals;
+            const poolBefore = await program.account.liquidityPool.fetch(poolPda);
+            const vault_before = await getTokenBalance(accounts.vault);
+
+            const repayIx = await program.methods
+                .flashRepay()
+                .accounts(accounts as any)
+                .instruction();
+            await program.methods
+                .flashBorrow(new BN(amount), { a: {} })
+                .accounts(accounts as any)
+                .postInstructions([repayIx])
+                .signers([alice])
+                .rpc();
+
+            const expectedFee = Math.ceil(amount * poolBefore.feeBps / 10_000);
+            assert.equal(await getTokenBalance(accounts.vault) - vault_before, expectedFee, "Vault should keep the loan fee");
+
+            const poolAfter = await program.account.liquidityPool.fetch(poolPda);
+            assert.equal(poolAfter.flashLoanVolumeA.sub(poolBefore.flashLoanVolumeA).toNumber(), amount, "Flash loan volume mismatch");
+            assert.equal(poolAfter.flashLoanOwed.toNumber(), 0, "Loan not cleared");
+            assert.isFalse(poolAfter.locked, "Pool left locked");
+        });
+
+        it("Fails to borrow without a repay later in the transaction", async () => {
+            try {
+                await program.methods
+                    .flashBorrow(new BN(10 * 10 ** decimals), { a: {} })
+                    .accounts(await flashLoanAccounts() as any)
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed because the loan is never repaid");
+            } catch (e) {
+                assert.include(e.toString(), "FlashLoanNotRepaid", "Expected FlashLoanNotRepaid error");
+            }
+        });
+
+        it("Fails to repay a Token A loan in Token B", async () => {
+            const accounts = await flashLoanAccounts();
+            const poolAccounts = await getPoolAccounts();
+            const vault_a_before = await getTokenBalance(accounts.vault);
+
+            const repayInBIx = await program.methods
+                .flashRepay()
+                .accounts({
+                    ...accounts,
+                    mint: poolAccounts.tokenBMint,
+                    vault: poolAccounts.tokenBVault,
+                    userTokenAccount: aliceTokenBAccount,
+                } as any)
+                .instruction();
+            const repayIx = await program.methods
+                .flashRepay()
+                .accounts(accounts as any)
+                .instruction();
+            try {
+                await program.methods
+                    .flashBorrow(new BN(10 * 10 ** decimals), { a: {} })
+                    .accounts(accounts as any)
+                    .postInstructions([repayInBIx, repayIx])
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed because the loan is repaid in the other token");
+            } catch (e) {
+                assert.include(e.toString(), "FlashLoanNotRepaid", "Expected FlashLoanNotRepaid error");
+            }
+            assert.equal(await getTokenBalance(accounts.vault), vault_a_before, "Token A vault changed");
+        });
+
+        it("Fails to repay without an outstanding loan", async () => {
+            try {
+                await program.methods
+                    .flashRepay()
+                    .accounts(await flashLoanAccounts() as any)
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed because nothing was borrowed");
+            } catch (e) {
+                assert.include(e.toString(), "NoFlashLoan", "Expected NoFlashLoan error");
+            }
+        });
+    });
+
+    describe("set_pool_status", () => {
+        const SWAPS_PAUSED = 1;
+
+        it("Rejects swaps while paused and accepts them after unpausing", async () => {
+            const poolAccounts = await getPoolAccounts();
+            const swapAccounts = {
+                userAuthority: alice.publicKey,
+                pool: poolPda,
+                observations: getObservationsPda(poolPda),
+                poolAuthority: poolAuthorityPda,
+                sourceMint: poolAccounts.tokenAMint,
+                destinationMint: poolAccounts.tokenBMint,
+                userSourceTokenAccount: aliceTokenAAccount,
+                userDestinationTokenAccount: aliceTokenBAccount,
+                tokenAVault: poolAccounts.tokenAVault,
+                tokenBVault: poolAccounts.tokenBVault,
+                tokenProgram: TOKEN_PROGRAM_ID
+            };
+
+            await program.methods
+                .setPoolStatus(SWAPS_PAUSED)
+                .accounts({ pool: poolPda, admin: intializer.publicKey } as any)
+                .signers([intializer])
+                .rpc();
+            assert.equal((await program.account.liquidityPool.fetch(poolPda)).status, SWAPS_PAUSED, "Pool status mismatch");
+
+            try {
+                await program.methods
+                    .swap(new BN(1 * 10 ** decimals), new BN(0), null)
+                    .accounts(swapAccounts as any)
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed because swaps are paused");
+            } catch (e) {
+                assert.include(e.toString(), "PoolPaused", "Expected PoolPaused error");
+            }
+
+            // Unpausing is not an emergency action and goes through the (zero-delay) timelock
+            const pendingStatusChange = getPendingChangePda(poolPda, 3);
+            await program.methods
+                .queueChange({ status: { 0: 0 } })
+                .accounts({
+                    config: configPda,
+                    pool: poolPda,
+                    pendingChange: pendingStatusChange,
+                    admin: intializer.publicKey,
+                    systemProgram: SystemProgram.programId,
+                } as any)
+                .signers([intializer])
+                .rpc();
+            await program.methods
+                .executeChange()
+                .accounts({ pool: poolPda, pendingChange: pendingStatusChange, proposer: intializer.publicKey } as any)
+                .rpc();
+
+            await program.methods
+                .swap(new BN(1 * 10 ** decimals), new BN(0), null)
+                .accounts(swapAccounts as any)
+                .signers([alice])
+                .rpc();
+        });
+
+        it("Fails to change the status when not the pool admin", async () => {
+            try {
+                await program.methods
+                    .setPoolStatus(SWAPS_PAUSED)
+                    .accounts({ pool: poolPda, admin: bob.publicKey } as any)
+                    .signers([bob])
+                    .rpc();
+                assert.fail("Should have failed because bob is not the admin");
+            } catch (e) {
+                assert.include(e.toString(), "Unauthorized", "Expected Unauthorized error");
+            }
+        });
+    });
+
+    describe("admin_transfer", () => {
+        it("Hands the pool admin role over in two steps", async () => {
+            await program.methods
+                .proposeAdmin(bob.publicKey)
+                .accounts({ pool: poolPda, admin: intializer.publicKey } as any)
+                .signers([intializer])
+                .rpc();
+            assert.ok((await program.account.liquidityPool.fetch(poolPda)).pendingAdmin.equals(bob.publicKey), "Pending admin mismatch");
+
+            await program.methods
+                .acceptAdmin()
+                .accounts({ pool: poolPda, newAdmin: bob.publicKey } as any)
+                .signers([bob])
+                .rpc();
+            let poolAccount = await program.account.liquidityPool.fetch(poolPda);
+            assert.ok(poolAccount.admin.equals(bob.publicKey), "Admin not transferred");
+            assert.ok(poolAccount.pendingAdmin.equals(PublicKey.default), "Pending admin not cleared");
+
+            // Hand the role back so the rest of the suite keeps working
+            await program.methods
+                .proposeAdmin(intializer.publicKey)
+                .accounts({ pool: poolPda, admin: bob.publicKey } as any)
+                .signers([bob])
+                .rpc();
+            await program.methods
+                .acceptAdmin()
+                .accounts({ pool: poolPda, newAdmin: intializer.publicKey } as any)
+                .signers([intializer])
+                .rpc();
+            poolAccount = await program.account.liquidityPool.fetch(poolPda);
+            assert.ok(poolAccount.admin.equals(intializer.publicKey), "Admin not transferred back");
+        });
+
+        it("Fails to accept the pool admin role when not the proposed admin", async () => {
+            await program.methods
+                .proposeAdmin(bob.publicKey)
+                .accounts({ pool: poolPda, admin: intializer.publicKey } as any)
+                .signers([intializer])
+                .rpc();
+
+            try {
+                await program.methods
+                    .acceptAdmin()
+                    .accounts({ pool: poolPda, newAdmin: alice.publicKey } as any)
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed because alice is not the proposed admin");
+            } catch (e) {
+                assert.include(e.toString(), "Unauthorized", "Expected Unauthorized error");
+            }
+
+            await program.methods
+                .cancelAdminTransfer()
+                .accounts({ pool: poolPda, admin: intializer.publicKey } as any)
+                .signers([intializer])
+                .rpc();
+            const poolAccount = await program.account.liquidityPool.fetch(poolPda);
+            assert.ok(poolAccount.admin.equals(intializer.publicKey), "Admin changed unexpectedly");
+            assert.ok(poolAccount.pendingAdmin.equals(PublicKey.default), "Pending admin not cleared");
+        });
+
+        it("Fails to cancel a config handover when none is pending", async () => {
+            const config = await program.account.programConfig.fetch(configPda);
+            try {
+                await program.methods
+                    .cancelConfigAdminTransfer()
+                    .accounts({ config: configPda, admin: config.admin } as any)
+                    .signers([payer])
+                    .rpc();
+                assert.fail("Should have failed because no handover is pending");
+            } catch (e) {
+                assert.include(e.toString(), "NoPendingAdmin", "Expected NoPendingAdmin error");
+            }
+        });
+    });
+
+    describe("timelocked_changes", () => {
+        const FEE_CHANGE = 0;
+
+        it("Queues and executes a fee change", async () => {
+            const pendingChange = getPendingChangePda(poolPda, FEE_CHANGE);
+            const oldFee = (await program.account.liquidityPool.fetch(poolPda)).feeBps;
+
+            await program.methods
+                .queueChange({ feeBps: { 0: 50 } })
+                .accounts({
+                    config: configPda,
+                    pool: poolPda,
+                    pendingChange,
+                    admin: intializer.publicKey,
+                    systemProgram: SystemProgram.programId,
+                } as any)
+                .signers([intializer])
+                .rpc();
+            const queued = await program.account.pendingChange.fetch(pendingChange);
+            assert.ok(queued.pool.equals(poolPda), "Pending change pool mismatch");
+
+            await program.methods
+                .executeChange()
+                .accounts({ pool: poolPda, pendingChange, proposer: intializer.publicKey } as any)
+                .rpc();
+            assert.equal((await program.account.liquidityPool.fetch(poolPda)).feeBps, 50, "Fee not changed");
+
+            // Restore the original fee for the rest of the suite
+            await program.methods
+                .queueChange({ feeBps: { 0: oldFee } })
+                .accounts({
+                    config: configPda,
+                    pool: poolPda,
+                    pendingChange,
+                    admin: intializer.publicKey,
+                    systemProgram: SystemProgram.programId,
+                } as any)
+                .signers([intializer])
+                .rpc();
+            await program.methods
+                .executeChange()
+                .accounts({ pool: poolPda, pendingChange, proposer: intializer.publicKey } as any)
+                .rpc();
+        });
+
+        it("Fails to execute a change before its eta and cancels it", async () => {
+            const config = await program.account.programConfig.fetch(configPda);
+            const pendingChange = getPendingChangePda(poolPda, FEE_CHANGE);
+
+            await program.methods
+                .updateConfig(config.treasury, { ...configParams, changeDelaySecs: 3600 })
+                .accounts({ config: configPda, admin: config.admin } as any)
+                .signers([payer])
+                .rpc();
+
+            await program.methods
+                .queueChange({ feeBps: { 0: 100 } })
+                .accounts({
+                    config: configPda,
+                    pool: poolPda,
+                    pendingChange,
+                    admin: intializer.publicKey,
+                    systemProgram: SystemProgram.programId,
+                } as any)
+                .signers([intializer])
+                .rpc();
+
+            try {
+                await program.methods
+                    .executeChange()
+                    .accounts({ pool: poolPda, pendingChange, proposer: intializer.publicKey } as any)
+                    .rpc();
+                assert.fail("Should have failed because the timelock has not elapsed");
+            } catch (e) {
+                assert.include(e.toString(), "TimelockNotElapsed", "Expected TimelockNotElapsed error");
+            }
+
+            await program.methods
+                .cancelChange()
+                .accounts({ config: configPda, pool: poolPda, pendingChange, admin: intializer.publicKey } as any)
+                .signers([intializer])
+                .rpc();
+            assert.isNull(await provider.connection.getAccountInfo(pendingChange), "Pending change not closed");
+
+            await program.methods
+                .updateConfig(config.treasury, configParams)
+                .accounts({ config: configPda, admin: config.admin } as any)
+                .signers([payer])
+                .rpc();
+        });
+
+        it("Lets only the protocol admin queue a protocol fee share change", async () => {
+            const PROTOCOL_FEE_SHARE_CHANGE = 1;
+            const pendingChange = getPendingChangePda(poolPda, PROTOCOL_FEE_SHARE_CHANGE);
+            const config = await program.account.programConfig.fetch(configPda);
+            const queueShare = (shareBps: number, admin: PublicKey, signer: Keypair) => program.methods
+                .queueChange({ protocolFeeShareBps: { 0: shareBps } })
+                .accounts({
+                    config: configPda,
+                    pool: poolPda,
+                    pendingChange,
+                    admin,
+                    systemProgram: SystemProgram.programId,
+                } as any)
+                .signers([signer])
+                .rpc();
+            const executeShare = () => program.methods
+                .executeChange()
+                .accounts({ pool: poolPda, pendingChange, proposer: config.admin } as any)
+                .rpc();
+
+            try {
+                await queueShare(0, intializer.publicKey, intializer);
+                assert.fail("Should have failed because the pool admin is not the protocol admin");
+            } catch (e) {
+                assert.include(e.toString(), "Unauthorized", "Expected Unauthorized error");
+            }
+
+            await queueShare(3000, config.admin, payer);
+            await executeShare();
+            assert.equal((await program.account.liquidityPool.fetch(poolPda)).protocolFeeShareBps, 3000, "Protocol fee share not changed");
+
+            // Restore the share used by the rest of the suite
+            await queueShare(protocolFeeShareBps, config.admin, payer);
+            await executeShare();
+        });
+
+        it("Fails to unpause a pool without the timelock", async () => {
+            await program.methods
+                .setPoolStatus(1)
+                .accounts({ pool: poolPda, admin: intializer.publicKey } as any)
+                .signers([intializer])
+                .rpc();
+
+            try {
+                await program.methods
+                    .setPoolStatus(0)
+                    .accounts({ pool: poolPda, admin: intializer.publicKey } as any)
+                    .signers([intializer])
+                    .rpc();
+                assert.fail("Should have failed because unpausing needs the timelock");
+            } catch (e) {
+                assert.include(e.toString(), "TimelockRequired", "Expected TimelockRequired error");
+            }
+
+            const pendingChange = getPendingChangePda(poolPda, 3);
+            await program.methods
+                .queueChange({ status: { 0: 0 } })
+                .accounts({
+                    config: configPda,
+                    pool: poolPda,
+                    pendingChange,
+                    admin: intializer.publicKey,
+                    systemProgram: SystemProgram.programId,
+                } as any)
+                .signers([intializer])
+                .rpc();
+            await program.methods
+                .executeChange()
+                .accounts({ pool: poolPda, pendingChange, proposer: intializer.publicKey } as any)
+                .rpc();
+        });
+
+        it("Fails to execute an unpause queued before a later emergency pause", async () => {
+            const pendingChange = getPendingChangePda(poolPda, 3);
+            const queueUnpause = () => program.methods
+                .queueChange({ status: { 0: 0 } })
+                .accounts({
+                    config: configPda,
+                    pool: poolPda,
+                    pendingChange,
+                    admin: intializer.publicKey,
+                    systemProgram: SystemProgram.programId,
+                } as any)
+                .signers([intializer])
+                .rpc();
+            const setStatus = (status: number) => program.methods
+                .setPoolStatus(status)
+                .accounts({ pool: poolPda, admin: intializer.publicKey } as any)
+                .signers([intializer])
+                .rpc();
+
+            await setStatus(1);
+            await queueUnpause();
+            await setStatus(7);
+
+            try {
+                await program.methods
+                    .executeChange()
+                    .accounts({ pool: poolPda, pendingChange, proposer: intializer.publicKey } as any)
+                    .rpc();
+                assert.fail("Should have failed because the pool was paused after the change was queued");
+            } catch (e) {
+                assert.include(e.toString(), "StaleStatusChange", "Expected StaleStatusChange error");
+            }
+            assert.equal((await program.account.liquidityPool.fetch(poolPda)).status, 7, "Emergency pause undone");
+
+            // Re-queue the unpause after the emergency pause to restore the pool for the rest of the suite
+            await program.methods
+                .cancelChange()
+                .accounts({ config: configPda, pool: poolPda, pendingChange, admin: intializer.publicKey } as any)
+                .signers([intializer])
+                .rpc();
+            await queueUnpause();
+            await program.methods
+                .executeChange()
+                .accounts({ pool: poolPda, pendingChange, proposer: intializer.publicKey } as any)
+                .rpc();
+            assert.equal((await program.account.liquidityPool.fetch(poolPda)).status, 0, "Pool not unpaused");
+        });
+    });
+
+    describe("stable_swap", () => {
+        const amp = 100;
+        let stableMintX: PublicKey;
+        let stableMintY: PublicKey;
+        let stablePool: PublicKey;
+        let stableLpMint: PublicKey;
+        let aliceX: PublicKey;
+        let aliceY: PublicKey;
+
+        before(async () => {
+            const mintX = await createMint(provider.connection, payer, mintAuthority.publicKey, null, decimals);
+            const mintY = await createMint(provider.connection, payer, mintAuthority.publicKey, null, decimals);
+            [stableMintX, stableMintY] = [mintX, mintY].sort((a, b) => a.toBuffer().compare(b.toBuffer()));
+            aliceX = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, stableMintX, alice.publicKey)).address;
+            aliceY = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, stableMintY, alice.publicKey)).address;
+            await mintTo(provider.connection, payer, stableMintX, aliceX, mintAuthority, BigInt(initialMintAmount));
+            await mintTo(provider.connection, payer, stableMintY, aliceY, mintAuthority, BigInt(initialMintAmount));
+
+            [stablePool] = PublicKey.findProgramAddressSync(
+                [Buffer.from("pool"), stableMintX.toBuffer(), stableMintY.toBuffer()],
+                program.programId
+            );
+            [stableLpMint] = PublicKey.findProgramAddressSync(
+                [Buffer.from("lp_mint"), stablePool.toBuffer()],
+                program.programId
+            );
+        });
+
+        const stableSwapAccounts = async () => ({
+            userAuthority: alice.publicKey,
+            pool: stablePool,
+            observations: getObservationsPda(stablePool),
+            poolAuthority: stablePool,
+            sourceMint: stableMintX,
+            destinationMint: stableMintY,
+            userSourceTokenAccount: aliceX,
+            userDestinationTokenAccount: aliceY,
+            tokenAVault: await getAssociatedTokenAddress(stableMintX, stablePool, true),
+            tokenBVault: await getAssociatedTokenAddress(stableMintY, stablePool, true),
+            tokenProgram: TOKEN_PROGRAM_ID
+        });
+
+        it("Initializes a StableSwap pool and adds liquidity", async () => {
+            await program.methods
+                .initializePool(4, { stableSwap: {} }, new BN(amp), null)
+                .accounts({
+                    config: configPda,
+                    initializer: intializer.publicKey,
+                    tokenAMint: stableMintX,
+                    tokenBMint: stableMintY,
+                    pool: stablePool,
+                    observations: getObservationsPda(stablePool),
+                    poolAuthority: stablePool,
+                    tokenAVault: await getAssociatedTokenAddress(stableMintX, stablePool, true),
+                    tokenBVault: await getAssociatedTokenAddress(stableMintY, stablePool, true),
+                    lpMint: stableLpMint,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                } as any)
+                .signers([intializer])
+                .rpc();
+
+            const poolAccount = await program.account.liquidityPool.fetch(stablePool);
+            assert.deepEqual(poolAccount.curveKind, { stableSwap: {} }, "Curve kind mismatch");
+            assert.equal(poolAccount.ampTarget.toNumber(), amp, "Amp mismatch");
+
+            const aliceLp = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, stableLpMint, alice.publicKey)).address;
+            const liquidityAmount = new BN(10_000 * (10 ** decimals));
+            await program.methods
+                .addLiquidity(liquidityAmount, liquidityAmount, new BN(0), null)
+                .accounts({
+                    userAuthority: alice.publicKey,
+                    pool: stablePool,
+                    poolAuthority: stablePool,
+                    tokenAMint: stableMintX,
+                    tokenBMint: stableMintY,
+                    userTokenAAccount: aliceX,
+                    userTokenBAccount: aliceY,
+                    tokenAVault: await getAssociatedTokenAddress(stableMintX, stablePool, true),
+                    tokenBVault: await getAssociatedTokenAddress(stableMintY, stablePool, true),
+                    lpMint: stableLpMint,
+                    userLpTokenAccount: aliceLp,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                } as any)
+                .signers([alice])
+                .rpc();
+
+            // For a balanced pool the invariant D is the sum of the reserves
+            assert.equal(await getTokenBalance(aliceLp), 2 * liquidityAmount.toNumber() - MINIMUM_LIQUIDITY, "LP shares mismatch");
+        });
+
+        it("Swaps near 1:1 on a balanced StableSwap pool", async () => {
+            const amountIn = new BN(100 * (10 ** decimals));
+            const aliceY_before = await getTokenBalance(aliceY);
+
+            await program.methods
+                .swap(amountIn, new BN(1), null)
+                .accounts(await stableSwapAccounts() as any)
+                .signers([alice])
+                .rpc();
+
+            const received = await getTokenBalance(aliceY) - aliceY_before;
+            // A constant-product pool of the same depth would pay about 1% less for this trade
+            assert.isAbove(received, amountIn.toNumber() * 0.999, "StableSwap output too low");
+            assert.isBelow(received, amountIn.toNumber(), "StableSwap output above input");
+        });
+
+        it("Fails to ramp amp faster than the minimum duration", async () => {
+            const now = Math.floor(Date.now() / 1000);
+            try {
+                await program.methods
+                    .rampAmp(new BN(amp * 2), new BN(now + 60))
+                    .accounts({ pool: stablePool, admin: intializer.publicKey } as any)
+                    .signers([intializer])
+                    .rpc();
+                assert.fail("Should have failed due to a too short ramp");
+            } catch (e) {
+                assert.include(e.toString(), "InvalidAmpRamp", "Expected InvalidAmpRamp error");
+            }
+        });
+
+        it("Ramps amp and stops the ramp", async () => {
+            const now = Math.floor(Date.now() / 1000);
+            await program.methods
+                .rampAmp(new BN(amp * 2), new BN(now + 2 * 24 * 60 * 60))
+                .accounts({ pool: stablePool, admin: intializer.publicKey } as any)
+                .signers([intializer])
+                .rpc();
+            let poolAccount = await program.account.liquidityPool.fetch(stablePool);
+            assert.equal(poolAccount.ampTarget.toNumber(), amp * 2, "Amp target mismatch");
+
+            await program.methods
+                .stopRampAmp()
+                .accounts({ pool: stablePool, admin: intializer.publicKey } as any)
+                .signers([intializer])
+                .rpc();
+            poolAccount = await program.account.liquidityPool.fetch(stablePool);
+            assert.equal(poolAccount.ampInitial.toNumber(), poolAccount.ampTarget.toNumber(), "Ramp not stopped");
+        });
+    });
+
+    describe("constant_price", () => {
+        it("Fails to initialize a constant-price pool without a price", async () => {
+            const mintX = await createMint(provider.connection, payer, mintAuthority.publicKey, null, decimals);
+            const mintY = await createMint(provider.connection, payer, mintAuthority.publicKey, null, decimals);
+            const [mintXKey, mintYKey] = [mintX, mintY].sort((a, b) => a.toBuffer().compare(b.toBuffer()));
+            const [pricePool] = PublicKey.findProgramAddressSync(
+                [Buffer.from("pool"), mintXKey.toBuffer(), mintYKey.toBuffer()],
+                program.programId
+            );
+            const [priceLpMint] = PublicKey.findProgramAddressSync(
+                [Buffer.from("lp_mint"), pricePool.toBuffer()],
+                program.programId
+            );
+
+            try {
+                await program.methods
+                    .initializePool(poolFeeBps, { constantPrice: {} }, new BN(0), null)
+                    .accounts({
+                        config: configPda,
+                        initializer: intializer.publicKey,
+                        tokenAMint: mintXKey,
+                        tokenBMint: mintYKey,
+                        pool: pricePool,
+                        observations: getObservationsPda(pricePool),
+                        poolAuthority: pricePool,
+                        tokenAVault: await getAssociatedTokenAddress(mintXKey, pricePool, true),
+                        tokenBVault: await getAssociatedTokenAddress(mintYKey, pricePool, true),
+                        lpMint: priceLpMint,
+                        tokenProgram: TOKEN_PROGRAM_ID,
+                        associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+                        systemProgram: SystemProgram.programId,
+                    } as any)
+                    .signers([intializer])
+                    .rpc();
+                assert.fail("Should have failed due to a missing price");
+            } catch (e) {
+                assert.include(e.toString(), "InvalidCurveParams", "Expected InvalidCurveParams error");
+            }
+        });
+    });
+
+    describe("weighted_pool", () => {
+        // 80/20 pool seeded 8000:2000, so both tokens start at the same price
+        const weightABps = 8_000;
+        let weightedMintX: PublicKey;
+        let weightedMintY: PublicKey;
+        let weightedPool: PublicKey;
+        let weightedLpMint: PublicKey;
+        let aliceX: PublicKey;
+        let aliceY: PublicKey;
+        let aliceLp: PublicKey;
+
+        before(async () => {
+            const mintX = await createMint(provider.connection, payer, mintAuthority.publicKey, null, decimals);
+            const mintY = await createMint(provider.connection, payer, mintAuthority.publicKey, null, decimals);
+            [weightedMintX, weightedMintY] = [mintX, mintY].sort((a, b) => a.toBuffer().compare(b.toBuffer()));
+            aliceX = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, weightedMintX, alice.publicKey)).address;
+            aliceY = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, weightedMintY, alice.publicKey)).address;
+            await mintTo(provider.connection, payer, weightedMintX, aliceX, mintAuthority, BigInt(initialMintAmount));
+            await mintTo(provider.connection, payer, weightedMintY, aliceY, mintAuthority, BigInt(initialMintAmount));
+
+            [weightedPool] = PublicKey.findProgramAddressSync(
+                [Buffer.from("pool"), weightedMintX.toBuffer(), weightedMintY.toBuffer()],
+                program.programId
+            );
+            [weightedLpMint] = PublicKey.findProgramAddressSync(
+                [Buffer.from("lp_mint"), weightedPool.toBuffer()],
+                program.programId
+            );
+        });
+
+        const initializeWeightedPool = async (curveParam: number) => {
+            await program.methods
+                .initializePool(poolFeeBps, { weighted: {} }, new BN(curveParam), null)
+                .accounts({
+                    config: configPda,
+                    initializer: intializer.publicKey,
+                    tokenAMint: weightedMintX,
+                    tokenBMint: weightedMintY,
+                    pool: weightedPool,
+                    observations: getObservationsPda(weightedPool),
+                    poolAuthority: weightedPool,
+                    tokenAVault: await getAssociatedTokenAddress(weightedMintX, weightedPool, true),
+                    tokenBVault: await getAssociatedTokenAddress(weightedMintY, weightedPool, true),
+                    lpMint: weightedLpMint,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                } as any)
+                .signers([intializer])
+                .rpc();
+        };
+
+        const addWeightedLiquidity = async (amountA: BN, amountB: BN) => {
+            await program.methods
+                .addLiquidity(amountA, amountB, new BN(0), null)
+                .accounts({
+                    userAuthority: alice.publicKey,
+                    pool: weightedPool,
+                    poolAuthority: weightedPool,
+                    tokenAMint: weightedMintX,
+                    tokenBMint: weightedMintY,
+                    userTokenAAccount: aliceX,
+                    userTokenBAccount: aliceY,
+                    tokenAVault: await getAssociatedTokenAddress(weightedMintX, weightedPool, true),
+                    tokenBVault: await getAssociatedTokenAddress(weightedMintY, weightedPool, true),
+                    lpMint: weightedLpMint,
+                    userLpTokenAccount: aliceLp,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                } as any)
+                .signers([alice])
+                .rpc();
+        };
+
+        it("Fails to initialize a weighted pool with a weight below 1%", async () => {
+            try {
+                await initializeWeightedPool(50);
+                assert.fail("Should have failed due to an invalid weight");
+            } catch (e) {
+                assert.include(e.toString(), "InvalidCurveParams", "Expected InvalidCurveParams error");
+            }
+        });
+
+        it("Initializes an 80/20 pool and adds liquidity", async () => {
+            await initializeWeightedPool(weightABps);
+
+            const poolAccount = await program.account.liquidityPool.fetch(weightedPool);
+            assert.deepEqual(poolAccount.curveKind, { weighted: {} }, "Curve kind mismatch");
+            assert.equal(poolAccount.weightABps, weightABps, "Weight A mismatch");
+            assert.equal(poolAccount.weightBBps, 10_000 - weightABps, "Weight B mismatch");
+
+            aliceLp = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, weightedLpMint, alice.publicKey)).address;
+            await addWeightedLiquidity(new BN(8_000 * (10 ** decimals)), new BN(2_000 * (10 ** decimals)));
+            assert.isAbove(await getTokenBalance(aliceLp), 0, "No LP shares minted");
+        });
+
+        it("Fails to add liquidity off the pool's weighted ratio", async () => {
+            try {
+                // 1:1 by amount is far off the 4:1 reserves of the 80/20 pool
+                await addWeightedLiquidity(new BN(100 * (10 ** decimals)), new BN(100 * (10 ** decimals)));
+                assert.fail("Should have failed due to a disproportionate deposit");
+            } catch (e) {
+                assert.include(e.toString(), "DisproportionateLiquidity", "Expected DisproportionateLiquidity error");
+            }
+        });
+
+        it("Swaps with the weighted-product formula", async () => {
+            const amountIn = 10 * (10 ** decimals);
+            const aliceY_before = await getTokenBalance(aliceY);
+
+            await program.methods
+                .swap(new BN(amountIn), new BN(1), null)
+                .accounts({
+                    userAuthority: alice.publicKey,
+                    pool: weightedPool,
+                    observations: getObservationsPda(weightedPool),
+                    poolAuthority: weightedPool,
+                    sourceMint: weightedMintX,
+                    destinationMint: weightedMintY,
+                    userSourceTokenAccount: aliceX,
+                    userDestinationTokenAccount: aliceY,
+                    tokenAVault: await getAssociatedTokenAddress(weightedMintX, weightedPool, true),
+                    tokenBVault: await getAssociatedTokenAddress(weightedMintY, weightedPool, true),
+                    tokenProgram: TOKEN_PROGRAM_ID
+                } as any)
+                .signers([alice])
+                .rpc();
+
+            // out = Bo * (1 - (Bi / (Bi + Ai))^(wi / wo))
+            const reserveIn = 8_000 * (10 ** decimals);
+            const reserveOut = 2_000 * (10 ** decimals);
+            const amountInAfterFee = amountIn * (10_000 - poolFeeBps) / 10_000;
+            const expected = reserveOut * (1 - Math.pow(reserveIn / (reserveIn + amountInAfterFee), 4));
+            const received = await getTokenBalance(aliceY) - aliceY_before;
+            assert.approximately(received, expected, 2, "Weighted swap output mismatch");
+        });
+    });
+
+    describe("liquidity_bootstrapping_pool", () => {
+        // Launch-style schedule: Token A starts at 90% weight and ends at 30%
+        const startWeights = [9_000, 1_000];
+        const endWeights = [3_000, 7_000];
+        let lbpMintX: PublicKey;
+        let lbpMintY: PublicKey;
+        let lbpPool: PublicKey;
+        let lbpLpMint: PublicKey;
+        let creatorX: PublicKey;
+        let creatorY: PublicKey;
+        let creatorLp: PublicKey;
+        let aliceX: PublicKey;
+        let aliceY: PublicKey;
+        let aliceLp: PublicKey;
+
+        before(async () => {
+            const mintX = await createMint(provider.connection, payer, mintAuthority.publicKey, null, decimals);
+            const mintY = await createMint(provider.connection, payer, mintAuthority.publicKey, null, decimals);
+            [lbpMintX, lbpMintY] = [mintX, mintY].sort((a, b) => a.toBuffer().compare(b.toBuffer()));
+            creatorX = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, lbpMintX, intializer.publicKey)).address;
+            creatorY = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, lbpMintY, intializer.publicKey)).address;
+            aliceX = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, lbpMintX, alice.publicKey)).address;
+            aliceY = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, lbpMintY, alice.publicKey)).address;
+            for (const account of [creatorX, aliceX]) {
+                await mintTo(provider.connection, payer, lbpMintX, account, mintAuthority, BigInt(initialMintAmount));
+            }
+            for (const account of [creatorY, aliceY]) {
+                await mintTo(provider.connection, payer, lbpMintY, account, mintAuthority, BigInt(initialMintAmount));
+            }
+
+            [lbpPool] = PublicKey.findProgramAddressSync(
+                [Buffer.from("pool"), lbpMintX.toBuffer(), lbpMintY.toBuffer()],
+                program.programId
+            );
+            [lbpLpMint] = PublicKey.findProgramAddressSync(
+                [Buffer.from("lp_mint"), lbpPool.toBuffer()],
+                program.programId
+            );
+        });
+
+        const addLbpLiquidity = async (user: anchor.web3.Keypair, userX: PublicKey, userY: PublicKey, userLp: PublicKey, amountA: BN, amountB: BN) => {
+            await program.methods
+                .addLiquidity(amountA, amountB, new BN(0), null)
+                .accounts({
+                    userAuthority: user.publicKey,
+                    pool: lbpPool,
+                    poolAuthority: lbpPool,
+                    tokenAMint: lbpMintX,
+                    tokenBMint: lbpMintY,
+                    userTokenAAccount: userX,
+                    userTokenBAccount: userY,
+                    tokenAVault: await getAssociatedTokenAddress(lbpMintX, lbpPool, true),
+                    tokenBVault: await getAssociatedTokenAddress(lbpMintY, lbpPool, true),
+                    lpMint: lbpLpMint,
+                    userLpTokenAccount: userLp,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                } as any)
+                .signers([user])
+                .rpc();
+        };
+
+        it("Initializes a liquidity bootstrapping pool with a weight schedule", async () => {
+            const now = Math.floor(Date.now() / 1000);
+            await program.methods
+                .initializePool(poolFeeBps, { liquidityBootstrapping: {} }, new BN(0), {
+                    startWeights,
+                    endWeights,
+                    startTs: new BN(now - 60),
+                    endTs: new BN(now + 3 * 24 * 60 * 60),
+                })
+                .accounts({
+                    config: configPda,
+                    initializer: intializer.publicKey,
+                    tokenAMint: lbpMintX,
+                    tokenBMint: lbpMintY,
+                    pool: lbpPool,
+                    observations: getObservationsPda(lbpPool),
+                    poolAuthority: lbpPool,
+                    tokenAVault: await getAssociatedTokenAddress(lbpMintX, lbpPool, true),
+                    tokenBVault: await getAssociatedTokenAddress(lbpMintY, lbpPool, true),
+                    lpMint: lbpLpMint,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                } as any)
+                .signers([intializer])
+                .rpc();
+
+            const poolAccount = await program.account.liquidityPool.fetch(lbpPool);
+            assert.deepEqual(poolAccount.curveKind, { liquidityBootstrapping: {} }, "Curve kind mismatch");
+            assert.deepEqual(poolAccount.startWeights, startWeights, "Start weights mismatch");
+            assert.deepEqual(poolAccount.endWeights, endWeights, "End weights mismatch");
+            assert.ok(poolAccount.creator.equals(intializer.publicKey), "Creator mismatch");
+
+            creatorLp = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, lbpLpMint, intializer.publicKey)).address;
+            aliceLp = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, lbpLpMint, alice.publicKey)).address;
+        });
+
+        it("Fails to add liquidity as anyone but the creator during the sale", async () => {
+            try {
+                await addLbpLiquidity(alice, aliceX, aliceY, aliceLp, new BN(9_000 * (10 ** decimals)), new BN(1_000 * (10 ** decimals)));
+                assert.fail("Should have failed due to a non-creator deposit");
+            } catch (e) {
+                assert.include(e.toString(), "Unauthorized", "Expected Unauthorized error");
+            }
+        });
+
+        it("Lets the creator seed the pool and buyers swap during the sale", async () => {
+            await addLbpLiquidity(intializer, creatorX, creatorY, creatorLp, new BN(9_000 * (10 ** decimals)), new BN(1_000 * (10 ** decimals)));
+            assert.isAbove(await getTokenBalance(creatorLp), 0, "No LP shares minted");
+
+            const aliceX_before = await getTokenBalance(aliceX);
+            await program.methods
+                .swap(new BN(10 * (10 ** decimals)), new BN(1), null)
+                .accounts({
+                    userAuthority: alice.publicKey,
+                    pool: lbpPool,
+                    observations: getObservationsPda(lbpPool),
+                    poolAuthority: lbpPool,
+                    sourceMint: lbpMintY,
+                    destinationMint: lbpMintX,
+                    userSourceTokenAccount: aliceY,
+                    userDestinationTokenAccount: aliceX,
+                    tokenAVault: await getAssociatedTokenAddress(lbpMintX, lbpPool, true),
+                    tokenBVault: await getAssociatedTokenAddress(lbpMintY, lbpPool, true),
+                    tokenProgram: TOKEN_PROGRAM_ID
+                } as any)
+                .signers([alice])
+                .rpc();
+            assert.isAbove(await getTokenBalance(aliceX), aliceX_before, "Sale swap paid nothing");
+        });
+
+        it("Ends the sale early and opens deposits", async () => {
+            await program.methods
+                .endSale()
+                .accounts({ pool: lbpPool, admin: intializer.publicKey } as any)
+                .signers([intializer])
+                .rpc();
+
+            const poolAccount = await program.account.liquidityPool.fetch(lbpPool);
+            assert.deepEqual(poolAccount.startWeights, poolAccount.endWeights, "Weights not frozen");
+            assert.isAtMost(poolAccount.saleEndTs.toNumber(), Math.floor(Date.now() / 1000) + 60, "Sale not ended");
+
+            // Deposits now only have to match the reserve ratio
+            const reserveA = await getTokenBalance(await getAssociatedTokenAddress(lbpMintX, lbpPool, true));
+            const reserveB = await getTokenBalance(await getAssociatedTokenAddress(lbpMintY, lbpPool, true));
+            const amountA = 90 * (10 ** decimals);
+            const amountB = Math.floor(amountA * reserveB / reserveA);
+            await addLbpLiquidity(alice, aliceX, aliceY, aliceLp, new BN(amountA), new BN(amountB));
+            assert.isAbove(await getTokenBalance(aliceLp), 0, "No LP shares minted after the sale");
+        });
+
+        it("Fails to end a sale that already ended", async () => {
+            try {
+                await program.methods
+                    .endSale()
+                    .accounts({ pool: lbpPool, admin: intializer.publicKey } as any)
+                    .signers([intializer])
+                    .rpc();
+                assert.fail("Should have failed due to an ended sale");
+            } catch (e) {
+                assert.include(e.toString(), "SaleEnded", "Expected SaleEnded error");
+            }
+        });
+    });
+
+    describe("concentrated_liquidity", () => {
+        // Price 1.0 (tick 0), ticks 10 apart, so each tick array spans 320 ticks
+        const tickSpacing = 10;
+        const tickArraySpan = tickSpacing * 32;
+        const [tickLower, tickUpper] = [-100, 100];
+        const liquidity = new BN(1_000_000_000_000);
+        let clMintX: PublicKey;
+        let clMintY: PublicKey;
+        let clPool: PublicKey;
+        let position: PublicKey;
+        let aliceX: PublicKey;
+        let aliceY: PublicKey;
+
+        const i32Seed = (value: number): Buffer => {
+            const buffer = Buffer.alloc(4);
+            buffer.writeInt32LE(value);
+            return buffer;
+        };
+        const getTickArrayPda = (startTickIndex: number): PublicKey =>
+            PublicKey.findProgramAddressSync(
+                [Buffer.from("tick_array"), clPool.toBuffer(), i32Seed(startTickIndex)],
+                program.programId
+            )[0];
+
+        before(async () => {
+            const mintX = await createMint(provider.connection, payer, mintAuthority.publicKey, null, decimals);
+            const mintY = await createMint(provider.connection, payer, mintAuthority.publicKey, null, decimals);
+            [clMintX, clMintY] = [mintX, mintY].sort((a, b) => a.toBuffer().compare(b.toBuffer()));
+            aliceX = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, clMintX, alice.publicKey)).address;
+            aliceY = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, clMintY, alice.publicKey)).address;
+            await mintTo(provider.connection, payer, clMintX, aliceX, mintAuthority, BigInt(initialMintAmount));
+            await mintTo(provider.connection, payer, clMintY, aliceY, mintAuthority, BigInt(initialMintAmount));
+
+            [clPool] = PublicKey.findProgramAddressSync(
+                [Buffer.from("cl_pool"), clMintX.toBuffer(), clMintY.toBuffer()],
+                program.programId
+            );
+            [position] = PublicKey.findProgramAddressSync(
+                [Buffer.from("position"), clPool.toBuffer(), alice.publicKey.toBuffer(), i32Seed(tickLower), i32Seed(tickUpper)],
+                program.programId
+            );
+        });
+
+        const modifyPositionAccounts = async () => ({
+            pool: clPool,
+            poolAuthority: clPool,
+            position,
+            tickArrayLower: getTickArrayPda(-tickArraySpan),
+            tickArrayUpper: getTickArrayPda(0),
+            tokenAMint: clMintX,
+            tokenBMint: clMintY,
+            ownerTokenAAccount: aliceX,
+            ownerTokenBAccount: aliceY,
+            tokenAVault: await getAssociatedTokenAddress(clMintX, clPool, true),
+            tokenBVault: await getAssociatedTokenAddress(clMintY, clPool, true),
+            owner: alice.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+        });
+
+        it("Initializes a concentrated-liquidity pool and its tick arrays", async () => {
+            await program.methods
+                .initializeConcentratedPool(poolFeeBps, tickSpacing, new BN(1).shln(64))
+                .accounts({
+                    config: configPda,
+                    initializer: intializer.publicKey,
+                    tokenAMint: clMintX,
+                    tokenBMint: clMintY,
+                    pool: clPool,
+                    poolAuthority: clPool,
+                    tokenAVault: await getAssociatedTokenAddress(clMintX, clPool, true),
+                    tokenBVault: await getAssociatedTokenAddress(clMintY, clPool, true),
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                } as any)
+                .signers([intializer])
+                .rpc();
+
+            for (const startTickIndex of [-2 * tickArraySpan, -tickArraySpan, 0, tickArraySpan]) {
+                await program.methods
+                    .initializeTickArray(startTickIndex)
+                    .accounts({
+                        pool: clPool,
+                        tickArray: getTickArrayPda(startTickIndex),
+                        payer: payer.publicKey,
+                        systemProgram: SystemProgram.programId,
+                    } as any)
+                    .signers([payer])
+                    .rpc();
+            }
+
+            const poolAccount = await program.account.concentratedPool.fetch(clPool);
+            assert.equal(poolAccount.tickCurrent, 0, "Current tick mismatch");
+            assert.equal(poolAccount.liquidity.toString(), "0", "New pool should have no liquidity");
+        });
+
+        it("Fails to open a position off the tick spacing", async () => {
+            try {
+                await program.methods
+                    .openPosition(-105, 100)
+                    .accounts({ pool: clPool, owner: alice.publicKey, systemProgram: SystemProgram.programId } as any)
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed due to an unaligned tick");
+            } catch (e) {
+                assert.include(e.toString(), "InvalidTickRange", "Expected InvalidTickRange error");
+            }
+        });
+
+        it("Opens a position and adds liquidity around the current price", async () => {
+            await program.methods
+                .openPosition(tickLower, tickUpper)
+                .accounts({ pool: clPool, position, owner: alice.publicKey, systemProgram: SystemProgram.programId } as any)
+                .signers([alice])
+                .rpc();
+
+            const aliceX_before = await getTokenBalance(aliceX);
+            const aliceY_before = await getTokenBalance(aliceY);
+            await program.methods
+                .increaseLiquidity(liquidity, new BN(initialMintAmount), new BN(initialMintAmount))
+                .accounts(await modifyPositionAccounts() as any)
+                .signers([alice])
+                .rpc();
+
+            // The range straddles the price, so the position holds both tokens
+            assert.isAbove(aliceX_before - await getTokenBalance(aliceX), 0, "No Token A deposited");
+            assert.isAbove(aliceY_before - await getTokenBalance(aliceY), 0, "No Token B deposited");
+            const poolAccount = await program.account.concentratedPool.fetch(clPool);
+            assert.equal(poolAccount.liquidity.toString(), liquidity.toString(), "Active liquidity mismatch");
+        });
+
+        it("Swaps through the position's range", async () => {
+            const amountIn = 10 * (10 ** decimals);
+            const aliceY_before = await getTokenBalance(aliceY);
+
+            await program.methods
+                .swapConcentrated(new BN(amountIn), new BN(1), null)
+                .accounts({
+                    sourceMint: clMintX,
+                    destinationMint: clMintY,
+                    pool: clPool,
+                    poolAuthority: clPool,
+                    userSourceTokenAccount: aliceX,
+                    userDestinationTokenAccount: aliceY,
+                    tokenAVault: await getAssociatedTokenAddress(clMintX, clPool, true),
+                    tokenBVault: await getAssociatedTokenAddress(clMintY, clPool, true),
+                    userAuthority: alice.publicKey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                } as any)
+                .remainingAccounts([
+                    { pubkey: getTickArrayPda(0), isWritable: true, isSigner: false },
+                    { pubkey: getTickArrayPda(-tickArraySpan), isWritable: true, isSigner: false },
+                ])
+                .signers([alice])
+                .rpc();
+
+            // Close to 1:1 near tick 0, less the fee and the price movement
+            const received = await getTokenBalance(aliceY) - aliceY_before;
+            assert.isAbove(received, amountIn * 0.99, "Concentrated swap output too low");
+            assert.isBelow(received, amountIn, "Concentrated swap output above input");
+        });
+
+        it("Collects the fees earned by the position", async () => {
+            const aliceX_before = await getTokenBalance(aliceX);
+            await program.methods
+                .collectFees()
+                .accounts(await modifyPositionAccounts() as any)
+                .signers([alice])
+                .rpc();
+            // The swap paid its fee in Token A, all of it to the only position
+            assert.isAbove(await getTokenBalance(aliceX), aliceX_before, "No fees collected");
+        });
+
+        it("Removes all liquidity from the position", async () => {
+            await program.methods
+                .decreaseLiquidity(liquidity, new BN(0), new BN(0))
+                .accounts(await modifyPositionAccounts() as any)
+                .signers([alice])
+                .rpc();
+
+            const positionAccount = await program.account.position.fetch(position);
+            assert.equal(positionAccount.liquidity.toString(), "0", "Position liquidity should be zero");
+            const poolAccount = await program.account.concentratedPool.fetch(clPool);
+            assert.equal(poolAccount.liquidity.toString(), "0", "Active liquidity should be zero");
+        });
+    });
+
+    describe("liquidity_mining_farm", () => {
+        const rewardPerSecond = new BN(1_000);
+        let rewardMint: PublicKey;
+        let adminRewardAccount: PublicKey;
+        let aliceRewardAccount: PublicKey;
+        let partnerRewardMint: PublicKey;
+        let adminPartnerRewardAccount: PublicKey;
+        let alicePartnerRewardAccount: PublicKey;
+        let farm: PublicKey;
+        let stakePosition: PublicKey;
+
+        before(async () => {
+            ({ mint: rewardMint, ata: adminRewardAccount } = await setupToken(mintAuthority, intializer.publicKey, initialMintAmount));
+            aliceRewardAccount = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, rewardMint, alice.publicKey)).address;
+            ({ mint: partnerRewardMint, ata: adminPartnerRewardAccount } = await setupToken(mintAuthority, intializer.publicKey, initialMintAmount));
+            alicePartnerRewardAccount = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, partnerRewardMint, alice.publicKey)).address;
+            [farm] = PublicKey.findProgramAddressSync([Buffer.from("farm"), poolPda.toBuffer()], program.programId);
+            [stakePosition] = PublicKey.findProgramAddressSync(
+                [Buffer.from("stake"), farm.toBuffer(), alice.publicKey.toBuffer()],
+                program.programId
+            );
+        });
+
+        const initializeFarmAccounts = async (admin: PublicKey) => ({
+            pool: poolPda,
+            farm,
+            lpMint: lpMintPda,
+            stakedLpVault: await getAssociatedTokenAddress(lpMintPda, farm, true),
+            rewardMint,
+            rewardVault: await getAssociatedTokenAddress(rewardMint, farm, true),
+            admin,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+        });
+
+        const fundFarm = async (rewardIndex: number, mint: PublicKey, adminAccount: PublicKey, amount: number) => {
+            await program.methods
+                .fundFarm(rewardIndex, new BN(amount))
+                .accounts({
+                    pool: poolPda,
+                    farm,
+                    rewardMint: mint,
+                    rewardVault: await getAssociatedTokenAddress(mint, farm, true),
+                    adminRewardTokenAccount: adminAccount,
+                    admin: intializer.publicKey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                } as any)
+                .signers([intializer])
+                .rpc();
+        };
+
+        // [reward_mint, reward_vault, owner_reward_token_account] per reward stream, in stream order
+        const harvestRemainingAccounts = async (streams: { mint: PublicKey, destination: PublicKey }[]) =>
+            (await Promise.all(streams.map(async ({ mint, destination }) => [
+                { pubkey: mint, isSigner: false, isWritable: false },
+                { pubkey: await getAssociatedTokenAddress(mint, farm, true), isSigner: false, isWritable: true },
+                { pubkey: destination, isSigner: false, isWritable: true },
+            ]))).flat();
+
+        const updateStakeAccounts = async () => ({
+            farm,
+            stakePosition,
+            lpMint: lpMintPda,
+            stakedLpVault: await getAssociatedTokenAddress(lpMintPda, farm, true),
+            ownerLpTokenAccount: await getAliceLpAccount(),
+            owner: alice.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+        });
+
+        it("Fails to initialize a farm when not the pool admin", async () => {
+            const now = Math.floor(Date.now() / 1000);
+            try {
+                await program.methods
+                    .initializeFarm(rewardPerSecond, new BN(now), new BN(now + 3600))
+                    .accounts(await initializeFarmAccounts(bob.publicKey) as any)
+                    .signers([bob])
+                    .rpc();
+                assert.fail("Should have failed because bob is not the admin");
+            } catch (e) {
+                assert.include(e.toString(), "Unauthorized", "Expected Unauthorized error");
+            }
+        });
+
+        it("Initializes and funds a farm", async () => {
+            const now = Math.floor(Date.now() / 1000);
+            await program.methods
+                .initializeFarm(rewardPerSecond, new BN(now), new BN(now + 3600))
+                .accounts(await initializeFarmAccounts(intializer.publicKey) as any)
+                .signers([intializer])
+                .rpc();
+
+            const fundAmount = 10_000_000;
+            await fundFarm(0, rewardMint, adminRewardAccount, fundAmount);
+
+            const farmAccount = await program.account.farm.fetch(farm);
+            assert.equal(farmAccount.rewardStreamCount, 1, "Reward stream count mismatch");
+            assert.ok(farmAccount.rewardStreams[0].rewardPerSecond.eq(rewardPerSecond), "Emission rate mismatch");
+            assert.equal(await getTokenBalance(farmAccount.rewardStreams[0].rewardVault), fundAmount, "Reward vault not funded");
+        });
+
+        it("Stakes LP shares and harvests rewards", async () => {
+            await program.methods
+                .openStakePosition()
+                .accounts({ farm, stakePosition, owner: alice.publicKey, systemProgram: SystemProgram.programId } as any)
+                .signers([alice])
+                .rpc();
+
+            const lpAccount = await getAliceLpAccount();
+            const stakeAmount = Math.floor(await getTokenBalance(lpAccount) / 2);
+            await program.methods
+                .stake(new BN(stakeAmount))
+                .accounts(await updateStakeAccounts() as any)
+                .signers([alice])
+                .rpc();
+            assert.equal((await program.account.stakePosition.fetch(stakePosition)).amount.toNumber(), stakeAmount, "Staked amount mismatch");
+
+            await new Promise((resolve) => setTimeout(resolve, 2000));
+
+            const rewards_before = await getTokenBalance(aliceRewardAccount);
+            await program.methods
+                .harvest()
+                .accounts({ farm, stakePosition, owner: alice.publicKey, tokenProgram: TOKEN_PROGRAM_ID } as any)
+                .remainingAccounts(await harvestRemainingAccounts([{ mint: rewardMint, destination: aliceRewardAccount }]))
+                .signers([alice])
+                .rpc();
+            assert.isAbove(await getTokenBalance(aliceRewardAccount) - rewards_before, 0, "No rewards harvested");
+        });
+
+        it("Adds a partner reward stream and harvests both streams at once", async () => {
+            const now = Math.floor(Date.now() / 1000);
+            const streamAccrued_before = (await program.account.farm.fetch(farm)).rewardStreams[0].accRewardPerShare;
+            await program.methods
+                .addRewardStream(rewardPerSecond, new BN(now), new BN(now + 3600))
+                .accounts({
+                    pool: poolPda,
+                    farm,
+                    rewardMint: partnerRewardMint,
+                    rewardVault: await getAssociatedTokenAddress(partnerRewardMint, farm, true),
+                    admin: intializer.publicKey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                } as any)
+                .signers([intializer])
+                .rpc();
+            await fundFarm(1, partnerRewardMint, adminPartnerRewardAccount, 10_000_000);
+
+            const farmAccount = await program.account.farm.fetch(farm);
+            assert.equal(farmAccount.rewardStreamCount, 2, "Reward stream count mismatch");
+            // Adding the stream accrued the first one up to now instead of resetting it
+            assert.isTrue(farmAccount.rewardStreams[0].accRewardPerShare.gte(streamAccrued_before), "First stream's accumulator was reset");
+
+            await new Promise((resolve) => setTimeout(resolve, 2000));
+
+            const rewards_before = await getTokenBalance(aliceRewardAccount);
+            const partnerRewards_before = await getTokenBalance(alicePartnerRewardAccount);
+            await program.methods
+                .harvest()
+                .accounts({ farm, stakePosition, owner: alice.publicKey, tokenProgram: TOKEN_PROGRAM_ID } as any)
+                .remainingAccounts(await harvestRemainingAccounts([
+                    { mint: rewardMint, destination: aliceRewardAccount },
+                    { mint: partnerRewardMint, destination: alicePartnerRewardAccount },
+                ]))
+                .signers([alice])
+                .rpc();
+            assert.isAbove(await getTokenBalance(aliceRewardAccount) - rewards_before, 0, "No rewards harvested");
+            assert.isAbove(await getTokenBalance(alicePartnerRewardAccount) - partnerRewards_before, 0, "No partner rewards harvested");
+        });
+
+        it("Fails to harvest without the accounts of every reward stream", async () => {
+            try {
+                await program.methods
+                    .harvest()
+                    .accounts({ farm, stakePosition, owner: alice.publicKey, tokenProgram: TOKEN_PROGRAM_ID } as any)
+                    .remainingAccounts(await harvestRemainingAccounts([{ mint: rewardMint, destination: aliceRewardAccount }]))
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed because the partner stream's accounts are missing");
+            } catch (e) {
+                assert.include(e.toString(), "InvalidRewardAccounts", "Expected InvalidRewardAccounts error");
+            }
+        });
+
+        it("Changes the emission rate of one stream", async () => {
+            await program.methods
+                .setEmissionRate(0, new BN(0))
+                .accounts({ pool: poolPda, farm, admin: intializer.publicKey } as any)
+                .signers([intializer])
+                .rpc();
+            const farmAccount = await program.account.farm.fetch(farm);
+            assert.equal(farmAccount.rewardStreams[0].rewardPerSecond.toNumber(), 0, "Emission rate not updated");
+            assert.ok(farmAccount.rewardStreams[1].rewardPerSecond.eq(rewardPerSecond), "Other stream's rate changed");
+        });
+
+        it("Fails to unstake more than staked", async () => {
+            const staked = (await program.account.stakePosition.fetch(stakePosition)).amount;
+            try {
+                await program.methods
+                    .unstake(staked.addn(1))
+                    .accounts(await updateStakeAccounts() as any)
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed because the amount exceeds the stake");
+            } catch (e) {
+                assert.include(e.toString(), "InsufficientStake", "Expected InsufficientStake error");
+            }
+        });
+
+        it("Unstakes all LP shares", async () => {
+            const lpAccount = await getAliceLpAccount();
+            const lp_before = await getTokenBalance(lpAccount);
+            const staked = (await program.account.stakePosition.fetch(stakePosition)).amount;
+
+            await program.methods
+                .unstake(staked)
+                .accounts(await updateStakeAccounts() as any)
+                .signers([alice])
+                .rpc();
+
+            assert.equal(await getTokenBalance(lpAccount) - lp_before, staked.toNumber(), "LP shares not returned");
+            assert.equal((await program.account.farm.fetch(farm)).totalStaked.toNumber(), 0, "Farm total not cleared");
+        });
+    });
+
+    describe("vote_escrow", () => {
+        const lockAmount = 1_000_000;
+        let governanceMint: PublicKey;
+        let aliceGovernanceAccount: PublicKey;
+        let escrow: PublicKey;
+        let escrowVault: PublicKey;
+        let veLock: PublicKey;
+
+        before(async () => {
+            ({ mint: governanceMint, ata: aliceGovernanceAccount } = await setupToken(mintAuthority, alice.publicKey, initialMintAmount));
+            [escrow] = PublicKey.findProgramAddressSync([Buffer.from("ve_escrow")], program.programId);
+            [veLock] = PublicKey.findProgramAddressSync([Buffer.from("ve_lock"), alice.publicKey.toBuffer()], program.programId);
+            escrowVault = await getAssociatedTokenAddress(governanceMint, escrow, true);
+
+            // The escrow is a program-wide singleton, so it may already exist on a shared cluster
+            try {
+                const existing = await program.account.veEscrow.fetch(escrow);
+                governanceMint = existing.governanceMint;
+                aliceGovernanceAccount = (await getOrCreateAssociatedTokenAccount(provider.connection, payer, governanceMint, alice.publicKey)).address;
+                escrowVault = existing.vault;
+                return;
+            } catch (e) {}
+
+            const config = await program.account.programConfig.fetch(configPda);
+            await program.methods
+                .initializeVeEscrow()
+                .accounts({
+                    config: configPda,
+                    escrow,
+                    governanceMint,
+                    vault: escrowVault,
+                    admin: config.admin,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                } as any)
+                .signers([payer])
+                .rpc();
+        });
+
+        const lockAccounts = () => ({
+            escrow,
+            veLock,
+            governanceMint,
+            vault: escrowVault,
+            ownerTokenAccount: aliceGovernanceAccount,
+            owner: alice.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+        });
+
+        const getVotingPower = async () =>
+            (await program.methods.getVotingPower().accounts({ veLock } as any).view()) as BN;
+
+        it("Fails to lock beyond the maximum duration", async () => {
+            const now = Math.floor(Date.now() / 1000);
+            try {
+                await program.methods
+                    .lock(new BN(lockAmount), new BN(now + 5 * 365 * 24 * 60 * 60))
+                    .accounts(lockAccounts() as any)
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed because the lock is longer than four years");
+            } catch (e) {
+                assert.include(e.toString(), "InvalidLockDuration", "Expected InvalidLockDuration error");
+            }
+        });
+
+        it("Locks governance tokens with decaying voting power", async () => {
+            const now = Math.floor(Date.now() / 1000);
+            await program.methods
+                .lock(new BN(lockAmount), new BN(now + 365 * 24 * 60 * 60))
+                .accounts(lockAccounts() as any)
+                .signers([alice])
+                .rpc();
+
+            const lockAccount = await program.account.veLock.fetch(veLock);
+            assert.equal(lockAccount.amount.toNumber(), lockAmount, "Locked amount mismatch");
+            assert.equal(await getTokenBalance(escrowVault), lockAmount, "Escrow vault not funded");
+
+            // A one-year lock carries roughly a quarter of the amount as voting power
+            const power = (await getVotingPower()).toNumber();
+            assert.isAtMost(power, lockAmount / 4, "Voting power too high");
+            assert.isAbove(power, lockAmount / 4 - 1_000, "Voting power too low");
+        });
+
+        it("Increases the amount and extends the lock", async () => {
+            const power_before = await getVotingPower();
+            await program.methods
+                .increaseAmount(new BN(lockAmount))
+                .accounts(lockAccounts() as any)
+                .signers([alice])
+                .rpc();
+            assert.equal((await program.account.veLock.fetch(veLock)).amount.toNumber(), 2 * lockAmount, "Amount not increased");
+
+            const now = Math.floor(Date.now() / 1000);
+            await program.methods
+                .extendLock(new BN(now + 2 * 365 * 24 * 60 * 60))
+                .accounts({ veLock, owner: alice.publicKey } as any)
+                .signers([alice])
+                .rpc();
+            assert.ok((await getVotingPower()).gt(power_before.muln(3)), "Voting power not increased");
+        });
+
+        it("Fails to shorten the lock", async () => {
+            const lockAccount = await program.account.veLock.fetch(veLock);
+            try {
+                await program.methods
+                    .extendLock(lockAccount.unlockTs.subn(60))
+                    .accounts({ veLock, owner: alice.publicKey } as any)
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed because the unlock time moves earlier");
+            } catch (e) {
+                assert.include(e.toString(), "InvalidLockDuration", "Expected InvalidLockDuration error");
+            }
+        });
+
+        it("Fails to withdraw before the lock expires", async () => {
+            try {
+                await program.methods
+                    .withdrawAfterExpiry()
+                    .accounts(lockAccounts() as any)
+                    .signers([alice])
+                    .rpc();
+                assert.fail("Should have failed because the lock has not expired");
+            } catch (e) {
+                assert.include(e.toString(), "LockNotExpired", "Expected LockNotExpired error");
+            }
+        });
+    });
+})